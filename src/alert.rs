@@ -0,0 +1,617 @@
+//! Alert and notification flashes.
+//!
+//! [OpenRGB::alert] plays an [AlertPattern] (blink, pulse or color wipe) on an [AlertTarget] (controller, zone or set
+//! of LEDs), eg: for CI status lights or chat notifications.
+//!
+//! Before playing, the current mode and colors of the target controller are captured, the controller is switched to
+//! direct mode with [OpenRGB::set_custom_mode], and the captured state is restored afterwards (see
+//! [state](crate::state) module).
+//!
+//! Alerts play in a background task, so that dropping the alert future (eg: on timeout) never interrupts a request on
+//! the connection, which would [poison](crate::OpenRGBError::ConnectionPoisoned) it: the alert stops after its current
+//! request and state is restored in the background.
+//!
+//! Alerts of a client connection (and of clients sharing it, see [OpenRGB::with_timeouts]) play one at a time:
+//! concurrent alerts wait for their turn, highest [priority](AlertPattern::priority) first, then in call order.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::alert::{AlertPattern, AlertTarget};
+//! # use openrgb::data::Color;
+//! # use std::error::Error;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let client = OpenRGB::connect().await?;
+//!
+//! // build failed: blink keyboard red 3 times
+//! client.alert(AlertTarget::Controller { controller: 0 }, AlertPattern::blink(Color::new(255, 0, 0), 3).priority(10)).await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32::consts::PI;
+use std::future::{Future, poll_fn};
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
+
+use log::{debug, warn};
+use tokio::sync::oneshot;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::{OpenRGB, OpenRGBError};
+use crate::data::Color;
+use crate::protocol::OpenRGBStream;
+use crate::state::ControllerState;
+use crate::transition::{from_linear, to_linear};
+
+/// Interval between alert frames.
+const FRAME_INTERVAL: Duration = Duration::from_millis(1000 / 30);
+
+/// LEDs an alert plays on.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AlertTarget {
+    /// All LEDs of a controller.
+    Controller {
+        /// Controller ID.
+        controller: u32,
+    },
+
+    /// All LEDs of a zone.
+    Zone {
+        /// Controller ID.
+        controller: u32,
+
+        /// Zone ID.
+        zone: u32,
+    },
+
+    /// Set of LEDs of a controller, played in given order by [color wipes](AlertPattern::wipe).
+    Leds {
+        /// Controller ID.
+        controller: u32,
+
+        /// LED IDs.
+        leds: Vec<u32>,
+    },
+}
+
+impl AlertTarget {
+    /// Target controller ID.
+    pub fn controller(&self) -> u32 {
+        match self {
+            AlertTarget::Controller { controller } | AlertTarget::Zone { controller, .. } | AlertTarget::Leds { controller, .. } => *controller,
+        }
+    }
+}
+
+/// Alert animation.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Animation {
+    Blink(u32),
+    Pulse(u32),
+    Wipe,
+}
+
+/// Alert pattern, see [module documentation](self).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AlertPattern {
+    animation: Animation,
+    color: Color,
+    period: Duration,
+    priority: u8,
+}
+
+impl AlertPattern {
+    /// Blink `color` `times` times, alternating with black, with a 500 ms period.
+    pub fn blink(color: Color, times: u32) -> Self {
+        Self { animation: Animation::Blink(times), color, period: Duration::from_millis(500), priority: 0 }
+    }
+
+    /// Smoothly pulse `color` from and back to black `times` times, with a 1 s period.
+    pub fn pulse(color: Color, times: u32) -> Self {
+        Self { animation: Animation::Pulse(times), color, period: Duration::from_secs(1), priority: 0 }
+    }
+
+    /// Light LEDs with `color` one after the other over a 1 s period, and hold for another period.
+    pub fn wipe(color: Color) -> Self {
+        Self { animation: Animation::Wipe, color, period: Duration::from_secs(1), priority: 0 }
+    }
+
+    /// Set period of a blink or pulse, or duration of a wipe.
+    pub fn period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Set priority (default: 0), alerts with higher priority play first.
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Total pattern duration.
+    pub fn duration(&self) -> Duration {
+        match self.animation {
+            Animation::Blink(times) | Animation::Pulse(times) => self.period * times,
+            Animation::Wipe => self.period * 2,
+        }
+    }
+
+    /// Colors of target LEDs with current colors `base`, `elapsed` after start, or `None` once pattern ended.
+    fn colors(&self, base: &[Color], elapsed: Duration) -> Option<Vec<Color>> {
+        if elapsed >= self.duration() {
+            return None;
+        }
+        let progress = elapsed.as_secs_f32() / self.period.as_secs_f32();
+        Some(match self.animation {
+            Animation::Blink(_) if progress.fract() < 0.5 => vec![self.color; base.len()],
+            Animation::Blink(_) => vec![Color::new(0, 0, 0); base.len()],
+            Animation::Pulse(_) => {
+                let level = (1.0 - (2.0 * PI * progress.fract()).cos()) / 2.0;
+                let scale = |c: u8| from_linear(to_linear(c) * level);
+                vec![Color::new(scale(self.color.r), scale(self.color.g), scale(self.color.b)); base.len()]
+            }
+            Animation::Wipe => {
+                let lit = (progress * base.len() as f32) as usize + 1;
+                base.iter().enumerate().map(|(i, c)| if i < lit { self.color } else { *c }).collect()
+            }
+        })
+    }
+}
+
+impl<S: OpenRGBStream + 'static> OpenRGB<S> {
+    /// Play alert `pattern` on `target`, and restore previous mode and colors of target controller.
+    ///
+    /// Waits for the playing alert and queued alerts with higher or equal priority, see [module documentation](crate::alert).
+    ///
+    /// Invalid zone and LED IDs are skipped.
+    pub async fn alert(&self, target: AlertTarget, pattern: AlertPattern) -> Result<(), OpenRGBError> {
+        let turn = self.alerts.turn(pattern.priority);
+        // dropping this future drops the sender, which stops the alert task between requests
+        let (_stop, stopped) = oneshot::channel();
+        let client = self.with_timeouts(self.get_timeouts());
+        let task = tokio::spawn(async move { client.play_alert_task(target, pattern, turn, stopped).await });
+        match task.await {
+            Ok(result) => result,
+            // task is never aborted, and runtime shutdown drops this future as well
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    /// Wait for `turn`, play alert `pattern` on `target` and restore controller, until `stop` fires.
+    async fn play_alert_task(&self, target: AlertTarget, pattern: AlertPattern, mut turn: Turn, mut stop: oneshot::Receiver<()>) -> Result<(), OpenRGBError> {
+        if until_stopped(turn.wait(), &mut stop).await.is_none() {
+            return Ok(());
+        }
+
+        let controller_id = target.controller();
+        let controller = self.get_controller_unfiltered(controller_id).await?;
+        if is_stopped(&mut stop) {
+            debug!("Alert on controller {} ({}) stopped before playing", controller_id, controller.name);
+            return Ok(());
+        }
+        let leds: Vec<usize> = match &target {
+            AlertTarget::Controller { .. } => (0..controller.colors.len()).collect(),
+            AlertTarget::Zone { zone, .. } => match controller.zones.get(*zone as usize) {
+                Some(z) => {
+                    let start = controller.zones.iter().take(*zone as usize).map(|z| z.leds_count as usize).sum();
+                    (start..start + z.leds_count as usize).filter(|&led| led < controller.colors.len()).collect()
+                }
+                None => {
+                    warn!("Skipping alert on invalid zone {} of controller {} ({})", zone, controller_id, controller.name);
+                    Vec::new()
+                }
+            },
+            AlertTarget::Leds { leds, .. } => leds.iter().filter_map(|&led| match (led as usize) < controller.colors.len() {
+                true => Some(led as usize),
+                false => {
+                    warn!("Skipping alert on invalid LED {} of controller {} ({})", led, controller_id, controller.name);
+                    None
+                }
+            }).collect(),
+        };
+        if leds.is_empty() {
+            return Ok(());
+        }
+
+        debug!("Playing {:?} alert on {} LEDs of controller {} ({})", pattern.animation, leds.len(), controller_id, controller.name);
+        let state = ControllerState::of(controller);
+        let played = match self.set_custom_mode(controller_id).await {
+            Ok(()) => self.play_alert(controller_id, &state.colors, &leds, &pattern, &mut stop).await,
+            Err(e) => Err(e),
+        };
+        let restored = self.restore_controller(controller_id, &state).await;
+        // next alert starts from restored state
+        drop(turn);
+        played.and(restored)
+    }
+}
+
+impl<S: OpenRGBStream> OpenRGB<S> {
+    /// Play alert `pattern` on `leds` of controller with current colors `colors`, until `stop` fires.
+    async fn play_alert(&self, controller_id: u32, colors: &[Color], leds: &[usize], pattern: &AlertPattern, stop: &mut oneshot::Receiver<()>) -> Result<(), OpenRGBError> {
+        let base: Vec<Color> = leds.iter().map(|&led| colors[led]).collect();
+        let mut frame = colors.to_vec();
+        let mut sent = None;
+        let start = Instant::now();
+        let mut interval = tokio::time::interval(FRAME_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            if until_stopped(interval.tick(), stop).await.is_none() {
+                debug!("Alert on controller {} stopped", controller_id);
+                return Ok(());
+            }
+            let target = match pattern.colors(&base, start.elapsed()) {
+                Some(target) => target,
+                None => return Ok(()),
+            };
+            if sent.as_ref() != Some(&target) {
+                for (&led, color) in leds.iter().zip(&target) {
+                    frame[led] = *color;
+                }
+                self.update_leds(controller_id, frame.clone()).await?;
+                sent = Some(target);
+            }
+        }
+    }
+}
+
+/// Whether alert `stop` fired, ie: alert future was dropped.
+fn is_stopped(stop: &mut oneshot::Receiver<()>) -> bool {
+    matches!(stop.try_recv(), Err(oneshot::error::TryRecvError::Closed))
+}
+
+/// Wait for `future`, or return `None` once alert `stop` fired.
+async fn until_stopped<F: Future>(future: F, stop: &mut oneshot::Receiver<()>) -> Option<F::Output> {
+    let mut future = pin!(future);
+    poll_fn(|cx| match Pin::new(&mut *stop).poll(cx) {
+        Poll::Ready(_) => Poll::Ready(None),
+        Poll::Pending => future.as_mut().poll(cx).map(Some),
+    }).await
+}
+
+/// Queue of alerts waiting to play, shared by clients of a connection.
+#[derive(Debug, Default)]
+pub(crate) struct AlertQueue {
+    state: Mutex<QueueState>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    playing: bool,
+    calls: u64,
+    waiting: BinaryHeap<Waiting>,
+}
+
+/// Alert waiting for its turn.
+#[derive(Debug)]
+struct Waiting {
+    priority: u8,
+    call: u64,
+    start: oneshot::Sender<()>,
+}
+
+impl Waiting {
+    fn key(&self) -> (u8, std::cmp::Reverse<u64>) {
+        (self.priority, std::cmp::Reverse(self.call))
+    }
+}
+
+impl PartialEq for Waiting {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Waiting {}
+
+impl PartialOrd for Waiting {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiting {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl AlertQueue {
+    /// Take a turn with `priority`, starting right away if no alert is playing.
+    fn turn(self: &Arc<Self>, priority: u8) -> Turn {
+        let mut state = self.state.lock().unwrap();
+        if !state.playing {
+            state.playing = true;
+            return Turn { queue: self.clone(), waiting: None };
+        }
+        let (start, receiver) = oneshot::channel();
+        state.calls += 1;
+        let call = state.calls;
+        state.waiting.push(Waiting { priority, call, start });
+        Turn { queue: self.clone(), waiting: Some(receiver) }
+    }
+
+    /// Hand turn over to next waiting alert.
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(next) = state.waiting.pop() {
+            if next.start.send(()).is_ok() {
+                return;
+            }
+        }
+        state.playing = false;
+    }
+}
+
+/// Alert turn, handed over to next alert when dropped.
+struct Turn {
+    queue: Arc<AlertQueue>,
+    waiting: Option<oneshot::Receiver<()>>,
+}
+
+impl Turn {
+    /// Wait for turn to start.
+    async fn wait(&mut self) {
+        if let Some(receiver) = &mut self.waiting {
+            let _ = receiver.await;
+            self.waiting = None;
+        }
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        if let Some(mut receiver) = self.waiting.take() {
+            // cancelled while waiting, hand over turn only if it already started
+            receiver.close();
+            if receiver.try_recv().is_err() {
+                return;
+            }
+        }
+        self.queue.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::{Context, Poll, ready, Waker};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use crate::{DEFAULT_PROTOCOL, OpenRGB};
+    use crate::alert::{AlertPattern, AlertTarget};
+    use crate::data::{Color, fixtures};
+    use crate::message::{Request, Response};
+    use crate::mock::{MockBuilder, MockStream};
+    use crate::tests::setup;
+
+    const RED: Color = Color { r: 255, g: 0, b: 0 };
+    const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+
+    /// Gate blocking I/O of a [Gated] stream while closed.
+    #[derive(Default)]
+    struct Gate {
+        closed: AtomicBool,
+        waker: Mutex<Option<Waker>>,
+    }
+
+    impl Gate {
+        fn set_closed(&self, closed: bool) {
+            self.closed.store(closed, Ordering::SeqCst);
+            if let Some(waker) = self.waker.lock().unwrap().take() {
+                waker.wake();
+            }
+        }
+
+        fn poll_open(&self, cx: &mut Context<'_>) -> Poll<()> {
+            if !self.closed.load(Ordering::SeqCst) {
+                return Poll::Ready(());
+            }
+            *self.waker.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    /// Mock stream with a [Gate], to stop alerts in the middle of a request.
+    struct Gated(MockStream, Arc<Gate>);
+
+    impl AsyncRead for Gated {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            ready!(self.1.poll_open(cx));
+            Pin::new(&mut self.0).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for Gated {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            ready!(self.1.poll_open(cx));
+            Pin::new(&mut self.0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_shutdown(cx)
+        }
+    }
+
+    #[test]
+    fn test_pattern() {
+        let base = [Color::new(1, 2, 3), Color::new(4, 5, 6)];
+        let ms = Duration::from_millis;
+
+        let blink = AlertPattern::blink(RED, 2);
+        assert_eq!(blink.duration(), ms(1000));
+        assert_eq!(blink.colors(&base, ms(0)), Some(vec![RED; 2]));
+        assert_eq!(blink.colors(&base, ms(300)), Some(vec![BLACK; 2]));
+        assert_eq!(blink.colors(&base, ms(600)), Some(vec![RED; 2]));
+        assert_eq!(blink.colors(&base, ms(1000)), None);
+
+        let pulse = AlertPattern::pulse(RED, 1).period(ms(200));
+        assert_eq!(pulse.colors(&base, ms(0)), Some(vec![BLACK; 2]));
+        assert_eq!(pulse.colors(&base, ms(50)), Some(vec![Color::new(188, 0, 0); 2]));
+        assert_eq!(pulse.colors(&base, ms(100)), Some(vec![RED; 2]));
+        assert_eq!(pulse.colors(&base, ms(200)), None);
+
+        let wipe = AlertPattern::wipe(RED);
+        assert_eq!(wipe.colors(&base, ms(0)), Some(vec![RED, base[1]]));
+        assert_eq!(wipe.colors(&base, ms(500)), Some(vec![RED; 2]));
+        assert_eq!(wipe.colors(&base, ms(1500)), Some(vec![RED; 2]));
+        assert_eq!(wipe.colors(&base, ms(2000)), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_alert() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let green = controller.colors[0];
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::SetCustomMode { controller: 0 })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![green, RED] })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![green, BLACK] })
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: controller.modes[0].clone() })
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .to_client().await?;
+
+        let blink = AlertPattern::blink(RED, 1).period(Duration::from_millis(100));
+        client.alert(AlertTarget::Leds { controller: 0, leds: vec![1, 7] }, blink).await?;
+        client.alert(AlertTarget::Zone { controller: 0, zone: 1 }, blink).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_alert_cancelled() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let blink = AlertPattern::blink(RED, 1).period(Duration::from_millis(100));
+
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol()
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::SetCustomMode { controller: 0 })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![RED; 2] })
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: controller.modes[0].clone() });
+        // next alert starts once restored
+        mock.expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::SetCustomMode { controller: 0 })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![RED; 2] })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![BLACK; 2] })
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: controller.modes[0].clone() });
+        let client = mock.to_client().await?;
+
+        let target = AlertTarget::Controller { controller: 0 };
+        assert!(tokio::time::timeout(Duration::from_millis(20), client.alert(target.clone(), blink)).await.is_err());
+        client.alert(target, blink).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_alert_cancelled_during_request() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let blink = AlertPattern::blink(RED, 2).period(Duration::from_millis(100));
+        let target = AlertTarget::Controller { controller: 0 };
+
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol()
+            // dropped during get_controller: request completes and alert does not play
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            // dropped during third frame update: update completes, then controller is restored
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::SetCustomMode { controller: 0 })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![RED; 2] })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![BLACK; 2] })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![RED; 2] })
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: controller.modes[0].clone() })
+            // connection is still usable
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(1));
+        let gate = Arc::new(Gate::default());
+        let client = OpenRGB::new(Gated(mock.build(), gate.clone())).await?;
+
+        gate.set_closed(true);
+        assert!(tokio::time::timeout(Duration::from_millis(10), client.alert(target.clone(), blink)).await.is_err());
+        gate.set_closed(false);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // third frame is sent at 132 ms
+        tokio::spawn({
+            let gate = gate.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                gate.set_closed(true);
+            }
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(150), client.alert(target, blink)).await.is_err());
+        gate.set_closed(false);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(client.get_controller_count().await?, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_alert_queue() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let blink = |color| AlertPattern::blink(color, 1).period(Duration::from_millis(100));
+        let (first, second, third) = (Color::new(1, 1, 1), Color::new(2, 2, 2), Color::new(3, 3, 3));
+
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol();
+        for color in [first, third, second] {
+            mock.expect(Request::ControllerData { controller: 0 })
+                .respond(Response::ControllerData(controller.clone()))
+                .expect(Request::SetCustomMode { controller: 0 })
+                .expect(Request::UpdateLeds { controller: 0, colors: vec![color; 2] })
+                .expect(Request::UpdateLeds { controller: 0, colors: vec![BLACK; 2] })
+                .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: controller.modes[0].clone() });
+        }
+        let client = mock.to_client().await?;
+
+        let target = AlertTarget::Controller { controller: 0 };
+        let (a, b, c) = tokio::join!(
+            client.alert(target.clone(), blink(first)),
+            client.alert(target.clone(), blink(second)),
+            client.alert(target.clone(), blink(third).priority(1)),
+        );
+        a?;
+        b?;
+        c?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,534 @@
+//! Ambient light from image frames.
+//!
+//! [Ambilight] samples screen edge regions of RGB [frames](Frame), averaging pixels of each region and smoothing
+//! colors over time, and sends them to LED strip zones placed around a monitor with [OpenRGB::update_zone_leds].
+//!
+//! Strip placement is described by an [EdgeLayout]: corner of the first LED, direction, and LED count on each side.
+//!
+//! Frames come from any [FrameSource]: capture backends implement it separately, [RawFrames] reads raw `rgb24` frames
+//! from a stream (eg: piped from `ffmpeg -f rawvideo -pix_fmt rgb24`), and [TestPattern] generates a scrolling rainbow.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::ambilight::{Ambilight, Corner, Direction, EdgeLayout, RawFrames};
+//! # use std::error::Error;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! // 60 LEDs strip in zone 0 of controller 0, starting at bottom left corner and going clockwise
+//! let layout = EdgeLayout::new(Corner::BottomLeft, Direction::Clockwise).left(10).top(20).right(10).bottom(20);
+//! let mut ambilight = Ambilight::new(OpenRGB::connect().await?).zone(0, 0, layout).smoothing(0.5);
+//!
+//! // mkfifo /tmp/screen && ffmpeg -f x11grab -i :0 -vf scale=160:90 -f rawvideo -pix_fmt rgb24 -y /tmp/screen
+//! let fifo = tokio::fs::File::open("/tmp/screen").await?;
+//! ambilight.run(&mut RawFrames::new(fifo, 160, 90)).await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::debug;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{OpenRGB, OpenRGBError};
+use crate::data::Color;
+use crate::protocol::OpenRGBStream;
+
+/// Errors returned by [Ambilight] and frame sources.
+#[derive(Error, Debug)]
+pub enum AmbilightError {
+    /// OpenRGB request failed.
+    #[error(transparent)]
+    OpenRGB(#[from] OpenRGBError),
+
+    /// Frame data does not match its dimensions.
+    #[error("Frame of {width}x{height} pixels needs {expected} pixels, got {got}")]
+    InvalidFrame {
+
+        /// Frame width.
+        width: u32,
+
+        /// Frame height.
+        height: u32,
+
+        /// Expected number of pixels.
+        expected: usize,
+
+        /// Actual number of pixels.
+        got: usize,
+    },
+
+    /// Frame source failed.
+    #[error("Frame source failed")]
+    Source(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// RGB image frame, pixels stored row by row from top left corner.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Frame {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Frame {
+    /// Build a frame of `width` by `height` `pixels`.
+    pub fn new(width: u32, height: u32, pixels: Vec<Color>) -> Result<Self, AmbilightError> {
+        let expected = width as usize * height as usize;
+        if pixels.len() != expected || expected == 0 {
+            return Err(AmbilightError::InvalidFrame { width, height, expected, got: pixels.len() });
+        }
+        Ok(Self { width, height, pixels })
+    }
+
+    /// Build a frame of `width` by `height` pixels from packed `r`, `g`, `b` bytes.
+    pub fn from_rgb24(width: u32, height: u32, data: &[u8]) -> Result<Self, AmbilightError> {
+        if data.len() % 3 != 0 {
+            let expected = width as usize * height as usize;
+            return Err(AmbilightError::InvalidFrame { width, height, expected, got: data.len() / 3 });
+        }
+        Self::new(width, height, data.chunks_exact(3).map(|p| Color::new(p[0], p[1], p[2])).collect())
+    }
+
+    /// Frame width, in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Frame height, in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Frame pixels.
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Average color of region spanning `x` and `y` fractions of frame (between 0 and 1).
+    fn average(&self, (x0, x1): (f32, f32), (y0, y1): (f32, f32)) -> [f32; 3] {
+        let span = |from: f32, to: f32, len: u32| {
+            let start = ((from * len as f32).floor() as u32).min(len - 1);
+            let end = ((to * len as f32).ceil() as u32).clamp(start + 1, len);
+            start..end
+        };
+        let (xs, ys) = (span(x0, x1, self.width), span(y0, y1, self.height));
+        let mut sum = [0.0; 3];
+        for y in ys.clone() {
+            for pixel in &self.pixels[(y * self.width + xs.start) as usize..(y * self.width + xs.end) as usize] {
+                sum[0] += f32::from(pixel.r);
+                sum[1] += f32::from(pixel.g);
+                sum[2] += f32::from(pixel.b);
+            }
+        }
+        let count = (xs.len() * ys.len()) as f32;
+        [sum[0] / count, sum[1] / count, sum[2] / count]
+    }
+}
+
+/// Source of frames, implemented by capture backends.
+#[async_trait]
+pub trait FrameSource: Send {
+    /// Get next frame, or `None` at end of stream.
+    async fn next_frame(&mut self) -> Result<Option<Frame>, AmbilightError>;
+}
+
+/// Frame source reading raw `rgb24` frames of fixed dimensions from a stream.
+#[derive(Debug)]
+pub struct RawFrames<R: AsyncRead + Send + Unpin> {
+    reader: R,
+    width: u32,
+    height: u32,
+}
+
+impl<R: AsyncRead + Send + Unpin> RawFrames<R> {
+    /// Read frames of `width` by `height` pixels from `reader`.
+    pub fn new(reader: R, width: u32, height: u32) -> Self {
+        Self { reader, width, height }
+    }
+}
+
+#[async_trait]
+impl<R: AsyncRead + Send + Unpin> FrameSource for RawFrames<R> {
+    async fn next_frame(&mut self) -> Result<Option<Frame>, AmbilightError> {
+        let mut data = vec![0; self.width as usize * self.height as usize * 3];
+        let mut read = 0;
+        while read < data.len() {
+            match self.reader.read(&mut data[read..]).await.map_err(|e| AmbilightError::Source(Box::new(e)))? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(AmbilightError::Source(format!("stream ended in the middle of a frame after {} bytes", read).into())),
+                n => read += n,
+            }
+        }
+        Frame::from_rgb24(self.width, self.height, &data).map(Some)
+    }
+}
+
+/// Frame source generating a horizontally scrolling rainbow, for testing LED placement.
+#[derive(Debug)]
+pub struct TestPattern {
+    width: u32,
+    height: u32,
+    interval: Duration,
+    offset: u32,
+}
+
+impl TestPattern {
+    /// Generate frames of `width` by `height` pixels, every 1/30 s.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, interval: Duration::from_secs(1) / 30, offset: 0 }
+    }
+
+    /// Set interval between frames.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+#[async_trait]
+impl FrameSource for TestPattern {
+    async fn next_frame(&mut self) -> Result<Option<Frame>, AmbilightError> {
+        tokio::time::sleep(self.interval).await;
+        let row: Vec<_> = (0..self.width).map(|x| hue(((x + self.offset) % self.width) as f32 / self.width as f32)).collect();
+        self.offset = (self.offset + 1) % self.width.max(1);
+        let pixels = (0..self.height).flat_map(|_| row.iter().copied()).collect();
+        Frame::new(self.width, self.height, pixels).map(Some)
+    }
+}
+
+/// Fully saturated color of `hue`, between 0 and 1.
+fn hue(hue: f32) -> Color {
+    let h = hue.fract() * 6.0;
+    let x = ((1.0 - (h % 2.0 - 1.0).abs()) * 255.0).round() as u8;
+    match h as u32 {
+        0 => Color::new(255, x, 0),
+        1 => Color::new(x, 255, 0),
+        2 => Color::new(0, 255, x),
+        3 => Color::new(0, x, 255),
+        4 => Color::new(x, 0, 255),
+        _ => Color::new(255, 0, x),
+    }
+}
+
+/// Monitor corner.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Corner {
+    /// Top left corner.
+    TopLeft,
+
+    /// Top right corner.
+    TopRight,
+
+    /// Bottom right corner.
+    BottomRight,
+
+    /// Bottom left corner.
+    BottomLeft,
+}
+
+/// Strip direction around monitor, as seen from the front.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Direction {
+    /// Clockwise.
+    Clockwise,
+
+    /// Counter-clockwise.
+    CounterClockwise,
+}
+
+/// Monitor side.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Side {
+    /// Top side.
+    Top,
+
+    /// Right side.
+    Right,
+
+    /// Bottom side.
+    Bottom,
+
+    /// Left side.
+    Left,
+}
+
+/// Sides in clockwise order.
+const SIDES: [Side; 4] = [Side::Top, Side::Right, Side::Bottom, Side::Left];
+
+/// Placement of a LED strip around a monitor.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EdgeLayout {
+    start: Corner,
+    direction: Direction,
+    counts: [u32; 4],
+    depth: f32,
+}
+
+impl EdgeLayout {
+    /// Build a layout starting at `start` corner and going in `direction`, without LEDs.
+    ///
+    /// Edge regions span 10% of frame width or height, see [EdgeLayout::depth].
+    pub fn new(start: Corner, direction: Direction) -> Self {
+        Self { start, direction, counts: [0; 4], depth: 0.1 }
+    }
+
+    /// Set number of LEDs on `side`.
+    pub fn side(mut self, side: Side, count: u32) -> Self {
+        self.counts[SIDES.iter().position(|s| *s == side).unwrap_or_default()] = count;
+        self
+    }
+
+    /// Set number of LEDs on top side.
+    pub fn top(self, count: u32) -> Self {
+        self.side(Side::Top, count)
+    }
+
+    /// Set number of LEDs on right side.
+    pub fn right(self, count: u32) -> Self {
+        self.side(Side::Right, count)
+    }
+
+    /// Set number of LEDs on bottom side.
+    pub fn bottom(self, count: u32) -> Self {
+        self.side(Side::Bottom, count)
+    }
+
+    /// Set number of LEDs on left side.
+    pub fn left(self, count: u32) -> Self {
+        self.side(Side::Left, count)
+    }
+
+    /// Set fraction of frame width or height sampled from each edge, between 0 and 1.
+    pub fn depth(mut self, depth: f32) -> Self {
+        self.depth = depth.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Total number of LEDs.
+    pub fn len(&self) -> usize {
+        self.counts.iter().sum::<u32>() as usize
+    }
+
+    /// Whether layout has no LEDs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sides and position of LEDs along them in clockwise direction, in strip order.
+    pub fn leds(&self) -> Vec<(Side, u32)> {
+        let first = match self.start {
+            Corner::TopLeft => 0,
+            Corner::TopRight => 1,
+            Corner::BottomRight => 2,
+            Corner::BottomLeft => 3,
+        };
+        let mut leds = Vec::with_capacity(self.len());
+        for i in 0..4 {
+            let (side, clockwise) = match self.direction {
+                Direction::Clockwise => ((first + i) % 4, true),
+                Direction::CounterClockwise => ((first + 3 - i) % 4, false),
+            };
+            let count = self.counts[side];
+            for j in 0..count {
+                leds.push((SIDES[side], if clockwise { j } else { count - 1 - j }));
+            }
+        }
+        leds
+    }
+
+    /// Frame regions sampled for each LED, in strip order, as `x` and `y` fractions of frame.
+    fn regions(&self) -> Vec<((f32, f32), (f32, f32))> {
+        let d = self.depth;
+        self.leds().into_iter().map(|(side, position)| {
+            let n = self.counts[SIDES.iter().position(|s| *s == side).unwrap_or_default()] as f32;
+            let (from, to) = (position as f32 / n, (position + 1) as f32 / n);
+            match side {
+                Side::Top => ((from, to), (0.0, d)),
+                Side::Right => ((1.0 - d, 1.0), (from, to)),
+                Side::Bottom => ((1.0 - to, 1.0 - from), (1.0 - d, 1.0)),
+                Side::Left => ((0.0, d), (1.0 - to, 1.0 - from)),
+            }
+        }).collect()
+    }
+}
+
+/// Zone mapped to frame edges.
+#[derive(Debug)]
+struct ZoneMapping {
+    controller: u32,
+    zone: u32,
+    layout: EdgeLayout,
+    smoothed: Vec<[f32; 3]>,
+}
+
+/// Ambient light renderer, see [module documentation](self).
+pub struct Ambilight<S: OpenRGBStream> {
+    client: OpenRGB<S>,
+    zones: Vec<ZoneMapping>,
+    smoothing: f32,
+}
+
+impl<S: OpenRGBStream> Ambilight<S> {
+    /// Build a renderer sending to `client`, without zones nor smoothing.
+    pub fn new(client: OpenRGB<S>) -> Self {
+        Self { client, zones: Vec::new(), smoothing: 0.0 }
+    }
+
+    /// Map zone `zone_id` of controller `controller_id` to frame edges with `layout`.
+    ///
+    /// Layout LED count should match zone LED count.
+    pub fn zone(mut self, controller_id: u32, zone_id: u32, layout: EdgeLayout) -> Self {
+        self.zones.push(ZoneMapping { controller: controller_id, zone: zone_id, layout, smoothed: Vec::new() });
+        self
+    }
+
+    /// Set smoothing, between 0 (colors follow frames immediately) and 1 (colors never change).
+    ///
+    /// Each LED color is `smoothing * previous color + (1 - smoothing) * sampled color`.
+    pub fn smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Client used by renderer.
+    pub fn client(&self) -> &OpenRGB<S> {
+        &self.client
+    }
+
+    /// Sample `frame` and update smoothed colors, returning colors of each zone in mapping order.
+    pub fn sample(&mut self, frame: &Frame) -> Vec<Vec<Color>> {
+        let smoothing = self.smoothing;
+        self.zones.iter_mut().map(|mapping| {
+            let sampled: Vec<_> = mapping.layout.regions().into_iter().map(|(x, y)| frame.average(x, y)).collect();
+            if mapping.smoothed.len() == sampled.len() {
+                for (smoothed, sampled) in mapping.smoothed.iter_mut().zip(&sampled) {
+                    for c in 0..3 {
+                        smoothed[c] = smoothed[c] * smoothing + sampled[c] * (1.0 - smoothing);
+                    }
+                }
+            } else {
+                mapping.smoothed = sampled;
+            }
+            mapping.smoothed.iter().map(|c| Color::new(c[0].round() as u8, c[1].round() as u8, c[2].round() as u8)).collect()
+        }).collect()
+    }
+
+    /// Sample `frame` and send colors to mapped zones.
+    pub async fn render(&mut self, frame: &Frame) -> Result<(), OpenRGBError> {
+        for (colors, (controller, zone)) in self.sample(frame).into_iter().zip(self.zones.iter().map(|z| (z.controller, z.zone)).collect::<Vec<_>>()) {
+            self.client.update_zone_leds(controller, zone, colors).await?;
+        }
+        Ok(())
+    }
+
+    /// Render frames from `source` until it ends.
+    pub async fn run(&mut self, source: &mut impl FrameSource) -> Result<(), AmbilightError> {
+        while let Some(frame) = source.next_frame().await? {
+            self.render(&frame).await?;
+        }
+        debug!("Frame source ended");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::time::Duration;
+
+    use crate::ambilight::{Ambilight, AmbilightError, Corner, Direction, EdgeLayout, Frame, FrameSource, RawFrames, Side, TestPattern};
+    use crate::data::Color;
+    use crate::message::Request;
+    use crate::mock::MockBuilder;
+    use crate::tests::setup;
+
+    const RED: Color = Color { r: 255, g: 0, b: 0 };
+    const GREEN: Color = Color { r: 0, g: 255, b: 0 };
+    const BLUE: Color = Color { r: 0, g: 0, b: 255 };
+    const WHITE: Color = Color { r: 255, g: 255, b: 255 };
+    const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+
+    /// 10x10 frame with red top, green right, blue bottom and white left edges, left half of top edge being black.
+    fn frame() -> Frame {
+        let pixels = (0..10).flat_map(|y| (0..10).map(move |x| match (x, y) {
+            (0..=4, 0) => BLACK,
+            (_, 0) => RED,
+            (9, _) => GREEN,
+            (_, 9) => BLUE,
+            (0, _) => WHITE,
+            _ => Color::new(10, 10, 10),
+        })).collect();
+        Frame::new(10, 10, pixels).unwrap()
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = EdgeLayout::new(Corner::BottomLeft, Direction::Clockwise).left(1).top(2).right(1).bottom(2);
+        assert_eq!(layout.len(), 6);
+        assert_eq!(layout.leds(), vec![(Side::Left, 0), (Side::Top, 0), (Side::Top, 1), (Side::Right, 0), (Side::Bottom, 0), (Side::Bottom, 1)]);
+
+        let layout = EdgeLayout::new(Corner::TopLeft, Direction::CounterClockwise).left(1).top(2).right(1).bottom(2);
+        assert_eq!(layout.leds(), vec![(Side::Left, 0), (Side::Bottom, 1), (Side::Bottom, 0), (Side::Right, 0), (Side::Top, 1), (Side::Top, 0)]);
+    }
+
+    #[tokio::test]
+    async fn test_sample() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let layout = EdgeLayout::new(Corner::TopLeft, Direction::Clockwise).top(2).right(1).bottom(1).left(1).depth(0.05);
+        let mut ambilight = Ambilight::new(MockBuilder::new().negotiate_default_protocol().to_client().await?)
+            .zone(0, 0, layout)
+            .smoothing(0.5);
+
+        // corner pixels belong to both sides
+        let (right, bottom, left) = (Color::new(26, 230, 0), Color::new(0, 26, 230), Color::new(204, 204, 230));
+        assert_eq!(ambilight.sample(&frame()), vec![vec![BLACK, RED, right, bottom, left]]);
+
+        let black = Frame::new(10, 10, vec![BLACK; 100])?;
+        let (right, bottom, left) = (Color::new(13, 115, 0), Color::new(0, 13, 115), Color::new(102, 102, 115));
+        assert_eq!(ambilight.sample(&black), vec![vec![BLACK, Color::new(128, 0, 0), right, bottom, left]]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let layout = EdgeLayout::new(Corner::TopRight, Direction::Clockwise).right(1).left(1);
+        let mut ambilight = Ambilight::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::UpdateZoneLeds { controller: 1, zone: 2, colors: vec![Color::new(26, 230, 0), Color::new(204, 204, 230)] })
+            .to_client().await?)
+            .zone(1, 2, layout.depth(0.01));
+
+        let data: Vec<u8> = frame().pixels().iter().flat_map(|c| [c.r, c.g, c.b]).collect();
+        ambilight.run(&mut RawFrames::new(&data[..], 10, 10)).await?;
+
+        assert!(matches!(RawFrames::new(&data[..10], 10, 10).next_frame().await, Err(AmbilightError::Source(_))));
+        assert!(matches!(Frame::new(2, 2, vec![BLACK; 3]), Err(AmbilightError::InvalidFrame { expected: 4, got: 3, .. })));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pattern() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut pattern = TestPattern::new(6, 2).interval(Duration::from_millis(10));
+        let first = pattern.next_frame().await?.unwrap();
+        assert_eq!(first.pixels()[..6], [RED, Color::new(255, 255, 0), GREEN, Color::new(0, 255, 255), BLUE, Color::new(255, 0, 255)]);
+        assert_eq!(first.pixels()[..6], first.pixels()[6..]);
+        let second = pattern.next_frame().await?.unwrap();
+        assert_eq!(second.pixels()[0], first.pixels()[1]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+//! Decode OpenRGB packets from a pcap/pcapng capture, a session recording or a hex dump.
+//!
+//! Usage: `openrgb-dissect [--port PORT] [--protocol VERSION] [FILE]`, reads standard input if no file is given.
+
+use std::error::Error;
+use std::io::Read;
+
+use openrgb::dissect::Dissector;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut dissector = Dissector::new();
+    let mut path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => dissector = dissector.port(args.next().ok_or("missing port")?.parse()?),
+            "--protocol" => dissector = dissector.protocol(args.next().ok_or("missing protocol version")?.parse()?),
+            "-h" | "--help" => {
+                println!("usage: openrgb-dissect [--port PORT] [--protocol VERSION] [FILE]");
+                return Ok(());
+            }
+            "-" => path = None,
+            _ => path = Some(arg),
+        }
+    }
+
+    let capture = match path {
+        Some(path) => std::fs::read(path)?,
+        None => {
+            let mut capture = Vec::new();
+            std::io::stdin().read_to_end(&mut capture)?;
+            capture
+        }
+    };
+
+    let packets = tokio::runtime::Runtime::new()?.block_on(dissector.dissect(&capture))?;
+    for packet in packets {
+        println!("{}\n", packet);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! HTTP REST and WebSocket gateway daemon for an OpenRGB server, see `openrgb::gateway` module.
+//!
+//! Usage: `openrgb-gateway [--host HOST] [--port PORT] [--listen ADDRESS] [--poll SECONDS]`.
+//!
+//! Exits when the connection to the OpenRGB server is lost, so that a service manager can restart it.
+
+use std::error::Error;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use openrgb::{DEFAULT_ADDR, OpenRGB};
+use openrgb::gateway::Gateway;
+
+const USAGE: &str = "usage: openrgb-gateway [--host HOST] [--port PORT] [--listen ADDRESS] [--poll SECONDS]";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut host = None;
+    let mut port = DEFAULT_ADDR.1;
+    let mut listen = SocketAddr::from(([127, 0, 0, 1], 6780));
+    let mut poll = Duration::from_secs(2);
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--host" => host = Some(args.next().ok_or("missing host")?),
+            "--port" => port = args.next().ok_or("missing port")?.parse()?,
+            "--listen" => listen = args.next().ok_or("missing listen address")?.parse()?,
+            "--poll" => {
+                let seconds: f64 = args.next().ok_or("missing poll interval")?.parse()?;
+                // also rejects NaN, and durations `Duration::from_secs_f64` would panic on
+                if !(seconds > 0.0 && seconds <= f64::from(u32::MAX)) {
+                    return Err(format!("invalid poll interval {}, must be a positive number of seconds\n{}", seconds, USAGE).into());
+                }
+                poll = Duration::from_secs_f64(seconds);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => return Err(format!("unknown argument {:?}\n{}", arg, USAGE).into()),
+        }
+    }
+
+    let mut builder = OpenRGB::builder()
+        .client_name("openrgb-gateway")
+        .port(port);
+    if let Some(host) = host {
+        builder = builder.host(host);
+    }
+
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let gateway = Gateway::new(builder.connect().await?);
+        let listener = tokio::net::TcpListener::bind(listen).await?;
+        println!("Serving OpenRGB gateway on http://{}", listener.local_addr()?);
+
+        tokio::spawn({
+            let gateway = gateway.clone();
+            async move {
+                if let Err(e) = gateway.watch_devices(poll).await {
+                    eprintln!("Lost connection to OpenRGB server: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        });
+
+        axum::serve(listener, gateway.router()).await?;
+        Ok(())
+    })
+}
@@ -0,0 +1,295 @@
+use std::io;
+use std::net::SocketAddr;
+
+use log::debug;
+use tokio::net::{lookup_host, TcpStream};
+
+use crate::{DecodeLimits, DEFAULT_ADDR, DEFAULT_PROTOCOL, OpenRGB, OpenRGBError, Timeouts};
+use crate::OpenRGBError::ConnectionError;
+use crate::protocol::OpenRGBStream;
+use crate::timeouts::with_timeout;
+
+/// Builder for [OpenRGB] client, see [OpenRGB::builder].
+///
+/// # Example
+///
+/// ```no_run
+/// # use openrgb::OpenRGB;
+/// # use std::error::Error;
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let client = OpenRGB::builder()
+///     .host("::1")
+///     .port(6742)
+///     .max_protocol(2)
+///     .client_name("my client")
+///     .nodelay(true)
+///     .connect()
+///     .await?;
+/// #
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpenRGBBuilder {
+    addrs: Addrs,
+    port: u16,
+    max_protocol: u32,
+    client_name: Option<String>,
+    nodelay: bool,
+    timeouts: Timeouts,
+    limits: DecodeLimits,
+}
+
+#[derive(Debug, Clone)]
+enum Addrs {
+    Host(String),
+    List(Vec<SocketAddr>),
+}
+
+impl Default for OpenRGBBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenRGBBuilder {
+    /// Build a new builder with default options, connecting to [DEFAULT_ADDR] host on [OpenRGBBuilder::port].
+    pub fn new() -> Self {
+        Self {
+            addrs: Addrs::Host(DEFAULT_ADDR.0.to_string()),
+            port: DEFAULT_ADDR.1,
+            max_protocol: DEFAULT_PROTOCOL,
+            client_name: None,
+            nodelay: false,
+            timeouts: Timeouts::default(),
+            limits: DecodeLimits::default(),
+        }
+    }
+
+    /// Set server host name or IP address (IPv6 addresses may be enclosed in brackets), resolved on connection.
+    ///
+    /// All resolved addresses are tried in order, using [OpenRGBBuilder::port].
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        let host = host.into();
+        let host = match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+            Some(ipv6) => ipv6.to_owned(),
+            None => host,
+        };
+        self.addrs = Addrs::Host(host);
+        self
+    }
+
+    /// Set server port, used with [OpenRGBBuilder::host] or default host (default: 6742).
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Set server socket addresses, tried in order.
+    pub fn addrs(mut self, addrs: impl IntoIterator<Item=SocketAddr>) -> Self {
+        self.addrs = Addrs::List(addrs.into_iter().collect());
+        self
+    }
+
+    /// Set maximum protocol version to negotiate, capped to [DEFAULT_PROTOCOL] (default: [DEFAULT_PROTOCOL]).
+    pub fn max_protocol(mut self, protocol: u32) -> Self {
+        self.max_protocol = protocol;
+        self
+    }
+
+    /// Set client name, sent to server once connected.
+    pub fn client_name(mut self, name: impl Into<String>) -> Self {
+        self.client_name = Some(name.into());
+        self
+    }
+
+    /// Set `TCP_NODELAY` option on connection (default: `false`).
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Set timeouts (default: [Timeouts::default]).
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Set decode limits (default: [DecodeLimits::default]).
+    pub fn decode_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Connect to server.
+    pub async fn connect(self) -> Result<OpenRGB<TcpStream>, OpenRGBError> {
+        let (addrs, display) = match &self.addrs {
+            Addrs::Host(host) => (
+                lookup_host((host.as_str(), self.port)).await
+                    .map_err(|source| ConnectionError { addr: format!("{}:{}", host, self.port), source })?
+                    .collect(),
+                format!("{}:{}", host, self.port),
+            ),
+            Addrs::List(addrs) => (addrs.clone(), format!("{:?}", addrs)),
+        };
+
+        let mut last_error = ConnectionError {
+            addr: display,
+            source: io::Error::new(io::ErrorKind::NotFound, "no address to connect to"),
+        };
+        for addr in addrs {
+            debug!("Connecting to OpenRGB server at {}...", addr);
+            let stream = with_timeout(self.timeouts.connect, async {
+                let stream = TcpStream::connect(addr).await.map_err(|source| ConnectionError { addr: addr.to_string(), source })?;
+                stream.set_nodelay(self.nodelay).map_err(|source| ConnectionError { addr: addr.to_string(), source })?;
+                Ok(stream)
+            }).await;
+            match stream {
+                Ok(stream) => return self.connect_stream(stream).await,
+                Err(e) => {
+                    debug!("Failed connecting to OpenRGB server at {}: {}", addr, e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Build client from given connected stream, ignoring address options.
+    ///
+    /// See [OpenRGB::new].
+    pub async fn connect_stream<S: OpenRGBStream>(self, stream: S) -> Result<OpenRGB<S>, OpenRGBError> {
+        let client = OpenRGB::open(stream, self.max_protocol, self.timeouts, self.limits).await?;
+        if let Some(name) = self.client_name {
+            client.set_name(name).await?;
+        }
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::net::SocketAddr;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use crate::{DEFAULT_PROTOCOL, OpenRGB};
+    use crate::message::Request;
+    use crate::mock::MockBuilder;
+    use crate::tests::setup;
+
+    /// Accept a connection and answer protocol negotiation with `protocol`.
+    async fn serve(listener: TcpListener, protocol: u32) -> Result<(), std::io::Error> {
+        let (mut stream, _) = listener.accept().await?;
+        let mut request = [0_u8; 20];
+        stream.read_exact(&mut request).await?;
+        request[16..].copy_from_slice(&protocol.to_le_bytes());
+        stream.write_all(&request).await
+    }
+
+    #[tokio::test]
+    async fn test_connect_host() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let server = tokio::spawn(serve(listener, 2));
+
+        let client = OpenRGB::builder()
+            .host(String::from("localhost"))
+            .port(port)
+            .nodelay(true)
+            .connect().await?;
+
+        assert_eq!(client.get_protocol_version(), 2);
+        server.await??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_connect_default_host() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let server = tokio::spawn(serve(listener, DEFAULT_PROTOCOL));
+
+        let client = OpenRGB::builder()
+            .port(port)
+            .connect().await?;
+
+        assert_eq!(client.get_protocol_version(), DEFAULT_PROTOCOL);
+        server.await??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_connect_ipv6() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let listener = match TcpListener::bind("[::1]:0").await {
+            Ok(listener) => listener,
+            Err(_) => return Ok(()), // IPv6 not available
+        };
+        let port = listener.local_addr()?.port();
+        let server = tokio::spawn(serve(listener, DEFAULT_PROTOCOL));
+
+        let client = OpenRGB::builder()
+            .host("[::1]")
+            .port(port)
+            .connect().await?;
+
+        assert_eq!(client.get_protocol_version(), DEFAULT_PROTOCOL);
+        server.await??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_connect_addrs() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let unused = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = tokio::spawn(serve(listener, DEFAULT_PROTOCOL));
+
+        let client = OpenRGB::builder()
+            .addrs(vec![unused, addr])
+            .connect().await?;
+
+        assert_eq!(client.get_protocol_version(), DEFAULT_PROTOCOL);
+        server.await??;
+
+        assert!(OpenRGB::builder().addrs(Vec::<SocketAddr>::new()).connect().await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_protocol_and_name() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let stream = MockBuilder::new()
+            .negotiate(1, DEFAULT_PROTOCOL)
+            .expect(Request::SetClientName("test".to_string()))
+            .expect(Request::ControllerData { controller: 0 })
+            .build();
+
+        let client = OpenRGB::builder()
+            .max_protocol(1)
+            .client_name("test")
+            .connect_stream(stream).await?;
+
+        assert_eq!(client.get_protocol_version(), 1);
+        assert!(client.get_controller(0).await.is_err());
+
+        Ok(())
+    }
+}
@@ -1,17 +1,29 @@
 use std::fmt::Debug;
+use std::future::Future;
 use std::net::Ipv4Addr;
-use std::sync::Arc;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-use log::debug;
+use log::{debug, warn};
 use tokio::net::{TcpStream, ToSocketAddrs};
-use tokio::sync::Mutex;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::sync::{broadcast, Mutex, MutexGuard};
+use tokio::sync::broadcast::error::TryRecvError;
 
 use OpenRGBError::*;
 use PacketId::*;
 
-use crate::data::{Color, Controller, Mode, OpenRGBWritable, PacketId, RawString};
-use crate::OpenRGBError;
-use crate::protocol::OpenRGBStream;
+use crate::alert::AlertQueue;
+use crate::data::{Color, Controller, Mode, OpenRGBReadable, OpenRGBWritable, PacketId, RawString};
+use crate::{DecodeLimits, OpenRGBBuilder, OpenRGBError, Timeouts};
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::output::{OutputFilter, OutputTarget, SentFrames};
+use crate::protocol::{decode_payload, HEADER_LEN, OpenRGBStream, read_packet_payload};
+use crate::timeouts::with_timeout;
 
 /// Default protocol version used by [OpenRGB] client.
 pub static DEFAULT_PROTOCOL: u32 = 3;
@@ -22,10 +34,80 @@ pub static DEFAULT_ADDR: (Ipv4Addr, u16) = (Ipv4Addr::LOCALHOST, 6742);
 /// OpenRGB client.
 pub struct OpenRGB<S: OpenRGBStream> {
     protocol: u32,
+    limits: DecodeLimits,
+    timeouts: Timeouts,
     stream: Arc<Mutex<S>>,
+    poisoned: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+    filters: Arc<RwLock<Vec<Arc<dyn OutputFilter>>>>,
+    sent: Arc<SentFrames>,
+    pub(crate) alerts: Arc<AlertQueue>,
+    device_list_updated: broadcast::Sender<()>,
+}
+
+/// Device list update watcher, see [OpenRGB::watch_device_list].
+pub struct DeviceListWatcher<S: OpenRGBStream> {
+    client: OpenRGB<S>,
+    updates: broadcast::Receiver<()>,
+    poll: Duration,
+}
+
+impl<S: OpenRGBStream> DeviceListWatcher<S> {
+    /// Wait for device list to be updated since watcher creation or previous call.
+    ///
+    /// Updates received meanwhile are merged.
+    pub async fn changed(&mut self) -> Result<(), OpenRGBError> {
+        loop {
+            let mut updated = false;
+            while let Ok(()) | Err(TryRecvError::Lagged(_)) = self.updates.try_recv() {
+                updated = true;
+            }
+            if updated {
+                return Ok(());
+            }
+            tokio::time::sleep(self.poll).await;
+            self.client.get_controller_count().await?;
+        }
+    }
+}
+
+/// Request and response exchange on a connection, poisoning it if not completed.
+///
+/// Once a request starts being written, the connection is only usable again after the whole request was written and
+/// the whole response was read. Errors in between and dropped request futures (eg: by a caller timeout or an aborted
+/// task) leave a partial packet on the connection, later requests would read stale data.
+struct Exchange<'a> {
+    poisoned: &'a AtomicBool,
+    completed: bool,
+}
+
+impl<'a> Exchange<'a> {
+    fn start(poisoned: &'a AtomicBool) -> Self {
+        Self { poisoned, completed: false }
+    }
+
+    fn complete(mut self) {
+        self.completed = true;
+    }
+}
+
+impl Drop for Exchange<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            warn!("Request to OpenRGB server was interrupted, poisoning connection");
+            self.poisoned.store(true, Ordering::SeqCst);
+        }
+    }
 }
 
 impl OpenRGB<TcpStream> {
+    /// Build a client with custom options.
+    ///
+    /// See [OpenRGBBuilder] for available options.
+    pub fn builder() -> OpenRGBBuilder {
+        OpenRGBBuilder::new()
+    }
+
     /// Connect to default OpenRGB server.
     ///
     /// Use [OpenRGB::connect_to] to connect to a specific server.
@@ -67,30 +149,104 @@ impl OpenRGB<TcpStream> {
     /// # }
     /// ```
     pub async fn connect_to(addr: impl ToSocketAddrs + Debug + Copy) -> Result<Self, OpenRGBError> {
+        Self::connect_to_with_timeouts(addr, Timeouts::default()).await
+    }
+
+    /// Connect to OpenRGB server at given coordinates, with given [Timeouts].
+    ///
+    /// # Arguments
+    /// * `addr` - A socket address (eg: a `(host, port)` tuple)
+    /// * `timeouts` - Timeouts applied to connection and later requests
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use openrgb::{OpenRGB, Timeouts};
+    /// # use std::error::Error;
+    /// # use std::time::Duration;
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let timeouts = Timeouts { connect: Some(Duration::from_secs(1)), ..Default::default() };
+    /// let client = OpenRGB::connect_to_with_timeouts(("localhost", 6742), timeouts).await?;
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connect_to_with_timeouts(addr: impl ToSocketAddrs + Debug + Copy, timeouts: Timeouts) -> Result<Self, OpenRGBError> {
         debug!("Connecting to OpenRGB server at {:?}...", addr);
-        Self::new(
+        let stream = with_timeout(timeouts.connect, async {
             TcpStream::connect(addr)
                 .await
-                .map_err(|source| ConnectionError { addr: format!("{:?}", addr), source })?
-        ).await
+                .map_err(|source| ConnectionError { addr: format!("{:?}", addr), source })
+        }).await?;
+        Self::open(stream, DEFAULT_PROTOCOL, timeouts, DecodeLimits::default()).await
+    }
+}
+
+#[cfg(unix)]
+impl OpenRGB<UnixStream> {
+    /// Connect to OpenRGB server through a Unix domain socket.
+    ///
+    /// This is useful when the SDK port is exposed through a socket, eg: with `socat` or SSH forwarding.
+    ///
+    /// # Arguments
+    /// * `path` - Unix socket path
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use openrgb::OpenRGB;
+    /// # use std::error::Error;
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let client = OpenRGB::connect_unix("/run/openrgb.sock").await?;
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connect_unix(path: impl AsRef<Path>) -> Result<Self, OpenRGBError> {
+        let path = path.as_ref();
+        debug!("Connecting to OpenRGB server at {:?}...", path);
+        let timeouts = Timeouts::default();
+        let stream = with_timeout(timeouts.connect, async {
+            UnixStream::connect(path)
+                .await
+                .map_err(|source| ConnectionError { addr: format!("{:?}", path), source })
+        }).await?;
+        Self::open(stream, DEFAULT_PROTOCOL, timeouts, DecodeLimits::default()).await
     }
 }
 
 impl<S: OpenRGBStream> OpenRGB<S> {
     /// Build a new client from given stream.
     ///
-    /// This constructor expects a connected, ready to use stream.
-    pub async fn new(mut stream: S) -> Result<Self, OpenRGBError> {
-        let protocol = DEFAULT_PROTOCOL.min(stream.request(
-            DEFAULT_PROTOCOL,
-            0,
-            RequestProtocolVersion,
-            DEFAULT_PROTOCOL,
-        ).await?);
-
-        debug!("Connected to OpenRGB server using protocol version {:?}", protocol);
+    /// This constructor expects a connected, ready to use stream. Any [AsyncRead](tokio::io::AsyncRead) +
+    /// [AsyncWrite](tokio::io::AsyncWrite) type can be used, eg: a TLS stream or a [DuplexStream](tokio::io::DuplexStream).
+    pub async fn new(stream: S) -> Result<Self, OpenRGBError> {
+        Self::open(stream, DEFAULT_PROTOCOL, Timeouts::default(), DecodeLimits::default()).await
+    }
 
-        Ok(Self { protocol, stream: Arc::new(Mutex::new(stream)) })
+    /// Build a new client from given stream, negotiating a protocol version up to `max_protocol`.
+    pub(crate) async fn open(stream: S, max_protocol: u32, timeouts: Timeouts, limits: DecodeLimits) -> Result<Self, OpenRGBError> {
+        let max_protocol = max_protocol.min(DEFAULT_PROTOCOL);
+        let mut client = Self {
+            protocol: max_protocol,
+            limits,
+            timeouts,
+            stream: Arc::new(Mutex::new(stream)),
+            poisoned: Arc::new(AtomicBool::new(false)),
+            metrics: Arc::new(Metrics::default()),
+            filters: Arc::new(RwLock::new(Vec::new())),
+            sent: Arc::new(SentFrames::default()),
+            alerts: Arc::new(AlertQueue::default()),
+            device_list_updated: broadcast::channel(16).0,
+        };
+
+        client.protocol = max_protocol.min(client.request(0, RequestProtocolVersion, max_protocol).await?);
+
+        debug!("Connected to OpenRGB server using protocol version {:?}", client.protocol);
+
+        Ok(client)
     }
 
     /// Get protocol version negotiated with server.
@@ -102,12 +258,96 @@ impl<S: OpenRGBStream> OpenRGB<S> {
         self.protocol
     }
 
+    /// Get limits enforced when decoding data received from server.
+    pub fn get_decode_limits(&self) -> DecodeLimits {
+        self.limits
+    }
+
+    /// Set limits enforced when decoding data received from server.
+    ///
+    /// See [DecodeLimits] for default values.
+    pub fn set_decode_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    /// Get timeouts applied to requests.
+    pub fn get_timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    /// Set timeouts applied to requests.
+    ///
+    /// See [Timeouts] for default values.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Get a client sharing this client connection, with other timeouts.
+    ///
+    /// This is useful to override timeouts for a single call, eg: `client.with_timeouts(timeouts).get_controller(0)`.
+    pub fn with_timeouts(&self, timeouts: Timeouts) -> Self {
+        Self {
+            protocol: self.protocol,
+            limits: self.limits,
+            timeouts,
+            stream: self.stream.clone(),
+            poisoned: self.poisoned.clone(),
+            metrics: self.metrics.clone(),
+            filters: self.filters.clone(),
+            sent: self.sent.clone(),
+            alerts: self.alerts.clone(),
+            device_list_updated: self.device_list_updated.clone(),
+        }
+    }
+
+    /// Get metrics of packets exchanged on this client connection, see [metrics](crate::metrics) module.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Reset metrics of this client connection.
+    pub fn reset_metrics(&self) {
+        self.metrics.reset()
+    }
+
+    /// Add a filter applied to outgoing colors and modes of this client connection, see [output](crate::output)
+    /// module.
+    pub fn add_output_filter(&self, filter: Arc<dyn OutputFilter>) {
+        self.filters.write().unwrap().push(filter);
+    }
+
+    /// Remove all output filters of this client connection.
+    pub fn clear_output_filters(&self) {
+        self.filters.write().unwrap().clear();
+    }
+
+    /// Subscribe to device list updates of this client connection.
+    ///
+    /// The server notifies clients with [DeviceListUpdated](PacketId::DeviceListUpdated) packets when controllers
+    /// are added, removed or changed, eg: after a rescan or a zone resize. Notifications are received while reading
+    /// responses to requests, so they are only delivered on next request: see [OpenRGB::watch_device_list] to wait
+    /// for them while idle.
+    pub fn device_list_updates(&self) -> broadcast::Receiver<()> {
+        self.device_list_updated.subscribe()
+    }
+
+    /// Watch device list updates of this client connection, see [DeviceListWatcher].
+    ///
+    /// While no update is received through other requests, the watcher requests controller count every `poll`
+    /// interval, to read notifications sent by the server meanwhile.
+    pub fn watch_device_list(&self, poll: Duration) -> DeviceListWatcher<S> {
+        DeviceListWatcher {
+            client: self.with_timeouts(self.timeouts),
+            updates: self.device_list_updates(),
+            poll,
+        }
+    }
+
     /// Set client name.
     ///
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_set_client_name) for more information.
     pub async fn set_name(&self, name: impl Into<String>) -> Result<(), OpenRGBError> {
-        self.stream.lock().await.write_packet(
-            self.protocol,
+        self.send(
             0,
             SetClientName,
             RawString(name.into()),
@@ -118,8 +358,7 @@ impl<S: OpenRGBStream> OpenRGB<S> {
     ///
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_request_controller_count) for more information.
     pub async fn get_controller_count(&self) -> Result<u32, OpenRGBError> {
-        self.stream.lock().await.request(
-            self.protocol,
+        self.request(
             0,
             RequestControllerCount,
             (),
@@ -130,21 +369,45 @@ impl<S: OpenRGBStream> OpenRGB<S> {
     ///
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_request_controller_data) for more information.
     pub async fn get_controller(&self, controller_id: u32) -> Result<Controller, OpenRGBError> {
-        self.stream.lock().await.request(
-            self.protocol,
-            controller_id,
-            RequestControllerData,
-            self.protocol,
-        ).await
+        // protocol version is only sent since protocol 1
+        if self.protocol >= 1 {
+            self.request(controller_id, RequestControllerData, self.protocol).await
+        } else {
+            self.request(controller_id, RequestControllerData, ()).await
+        }
+    }
+
+    /// Get data of all controllers, by controller ID.
+    pub async fn get_controllers(&self) -> Result<Vec<Controller>, OpenRGBError> {
+        let mut controllers = Vec::new();
+        for controller_id in 0..self.get_controller_count().await? {
+            controllers.push(self.get_controller(controller_id).await?);
+        }
+        Ok(controllers)
+    }
+
+    /// Get controller data, with colors and active mode last sent by this client connection before output filters.
+    pub(crate) async fn get_controller_unfiltered(&self, controller_id: u32) -> Result<Controller, OpenRGBError> {
+        let mut controller = self.get_controller(controller_id).await?;
+        self.sent.unfilter(controller_id, &mut controller);
+        Ok(controller)
+    }
+
+    /// Get data of all controllers, by controller ID, see [OpenRGB::get_controller_unfiltered].
+    pub(crate) async fn get_controllers_unfiltered(&self) -> Result<Vec<Controller>, OpenRGBError> {
+        let mut controllers = Vec::new();
+        for controller_id in 0..self.get_controller_count().await? {
+            controllers.push(self.get_controller_unfiltered(controller_id).await?);
+        }
+        Ok(controllers)
     }
 
     /// Resize a controller zone.
     ///
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_rgbcontroller_resizezone) for more information.
-    pub async fn resize_zone(&self, zone_id: i32, new_size: i32) -> Result<(), OpenRGBError> {
-        self.stream.lock().await.write_packet(
-            self.protocol,
-            0,
+    pub async fn resize_zone(&self, controller_id: u32, zone_id: i32, new_size: i32) -> Result<(), OpenRGBError> {
+        self.send(
+            controller_id,
             RGBControllerResizeZone,
             (zone_id, new_size),
         ).await
@@ -154,36 +417,45 @@ impl<S: OpenRGBStream> OpenRGB<S> {
     ///
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_rgbcontroller_updatesingleled) for more information.
     pub async fn update_led(&self, controller_id: u32, led_id: i32, color: Color) -> Result<(), OpenRGBError> {
-        self.stream.lock().await.write_packet(
-            self.protocol,
+        let mut filtered = color;
+        self.filter_colors(controller_id, OutputTarget::Led(led_id), std::slice::from_mut(&mut filtered));
+        self.send(
             controller_id,
             RGBControllerUpdateSingleLed,
-            (led_id, color),
-        ).await
+            (led_id, filtered),
+        ).await?;
+        self.sent.colors_sent(controller_id, OutputTarget::Led(led_id), vec![color], vec![filtered]);
+        Ok(())
     }
 
     /// Update LEDs.
     ///
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_rgbcontroller_updateleds) for more information.
     pub async fn update_leds(&self, controller_id: u32, colors: Vec<Color>) -> Result<(), OpenRGBError> {
-        self.stream.lock().await.write_packet(
-            self.protocol,
+        let mut filtered = colors.clone();
+        self.filter_colors(controller_id, OutputTarget::Controller, &mut filtered);
+        self.send(
             controller_id,
             RGBControllerUpdateLeds,
-            (colors.size(self.protocol), colors),
-        ).await
+            (filtered.size(self.protocol), filtered.clone()),
+        ).await?;
+        self.sent.colors_sent(controller_id, OutputTarget::Controller, colors, filtered);
+        Ok(())
     }
 
     /// Update a zone LEDs.
     ///
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_rgbcontroller_updatezoneleds) for more information.
     pub async fn update_zone_leds(&self, controller_id: u32, zone_id: u32, colors: Vec<Color>) -> Result<(), OpenRGBError> {
-        self.stream.lock().await.write_packet(
-            self.protocol,
+        let mut filtered = colors.clone();
+        self.filter_colors(controller_id, OutputTarget::Zone(zone_id), &mut filtered);
+        self.send(
             controller_id,
             RGBControllerUpdateZoneLeds,
-            (zone_id.size(self.protocol) + colors.size(self.protocol), zone_id, colors),
-        ).await
+            (zone_id.size(self.protocol) + filtered.size(self.protocol), zone_id, filtered.clone()),
+        ).await?;
+        self.sent.colors_sent(controller_id, OutputTarget::Zone(zone_id), colors, filtered);
+        Ok(())
     }
 
     /// Get profiles.
@@ -191,15 +463,11 @@ impl<S: OpenRGBStream> OpenRGB<S> {
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_request_profile_list) for more information.
     pub async fn get_profiles(&self) -> Result<Vec<String>, OpenRGBError> {
         self.check_protocol_version_profile_control()?;
-        self.stream.lock().await
-            .request::<_, (u32, Vec<String>)>(
-                self.protocol,
-                0,
-                RequestProfileList,
-                (),
-            )
-            .await
-            .map(|(_size, profiles)| profiles)
+        self.request::<_, (u32, Vec<String>)>(
+            0,
+            RequestProfileList,
+            (),
+        ).await.map(|(_size, profiles)| profiles)
     }
 
     /// Load a profile.
@@ -207,8 +475,7 @@ impl<S: OpenRGBStream> OpenRGB<S> {
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_request_load_profile) for more information.
     pub async fn load_profile(&self, name: impl Into<String>) -> Result<(), OpenRGBError> {
         self.check_protocol_version_profile_control()?;
-        self.stream.lock().await.write_packet(
-            self.protocol,
+        self.send(
             0,
             RequestLoadProfile,
             RawString(name.into()),
@@ -220,11 +487,10 @@ impl<S: OpenRGBStream> OpenRGB<S> {
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_request_save_profile) for more information.
     pub async fn save_profile(&self, name: impl Into<String>) -> Result<(), OpenRGBError> {
         self.check_protocol_version_profile_control()?;
-        self.stream.lock().await.write_packet(
-            self.protocol,
+        self.send(
             0,
             RequestSaveProfile,
-            name.into(),
+            RawString(name.into()),
         ).await
     }
 
@@ -233,11 +499,10 @@ impl<S: OpenRGBStream> OpenRGB<S> {
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_request_delete_profile) for more information.
     pub async fn delete_profile(&self, name: impl Into<String>) -> Result<(), OpenRGBError> {
         self.check_protocol_version_profile_control()?;
-        self.stream.lock().await.write_packet(
-            self.protocol,
+        self.send(
             0,
             RequestDeleteProfile,
-            name.into(),
+            RawString(name.into()),
         ).await
     }
 
@@ -245,8 +510,7 @@ impl<S: OpenRGBStream> OpenRGB<S> {
     ///
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_rgbcontroller_setcustommode) for more information.
     pub async fn set_custom_mode(&self, controller_id: u32) -> Result<(), OpenRGBError> {
-        self.stream.lock().await.write_packet(
-            self.protocol,
+        self.send(
             controller_id,
             RGBControllerSetCustomMode,
             (),
@@ -257,27 +521,123 @@ impl<S: OpenRGBStream> OpenRGB<S> {
     ///
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_rgbcontroller_updatemode) for more information.
     pub async fn update_mode(&self, controller_id: u32, mode_id: i32, mode: Mode) -> Result<(), OpenRGBError> {
-        self.stream.lock().await.write_packet(
-            self.protocol,
+        let mut filtered = mode.clone();
+        for filter in self.filters.read().unwrap().iter() {
+            filter.filter_mode(controller_id, mode_id, &mut filtered);
+        }
+        self.send(
             controller_id,
             RGBControllerUpdateMode,
-            (mode_id.size(self.protocol) + mode.size(self.protocol), mode_id, mode),
-        ).await
+            (mode_id.size(self.protocol) + filtered.size(self.protocol), mode_id, filtered.clone()),
+        ).await?;
+        self.sent.mode_sent(controller_id, mode_id, mode, filtered);
+        Ok(())
     }
 
     /// Save a mode.
     ///
     /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_rgbcontroller_savemode) for more information.
-    pub async fn save_mode(&self, controller_id: u32, mode: Mode) -> Result<(), OpenRGBError> {
+    pub async fn save_mode(&self, controller_id: u32, mode_id: i32, mode: Mode) -> Result<(), OpenRGBError> {
         self.check_protocol_version_saving_modes()?;
-        self.stream.lock().await.write_packet(
-            self.protocol,
+        self.send(
             controller_id,
             RGBControllerSaveMode,
-            mode,
+            (mode_id.size(self.protocol) + mode.size(self.protocol), mode_id, mode),
         ).await
     }
 
+    fn filter_colors(&self, controller_id: u32, target: OutputTarget, colors: &mut [Color]) {
+        for filter in self.filters.read().unwrap().iter() {
+            filter.filter_colors(controller_id, target, colors);
+        }
+    }
+
+    async fn send<I: OpenRGBWritable>(&self, device_id: u32, packet_id: PacketId, data: I) -> Result<(), OpenRGBError> {
+        let size = data.size(self.protocol);
+        self.traced(device_id, packet_id, size, async {
+            let mut stream = self.lock().await?;
+            let exchange = Exchange::start(&self.poisoned);
+            self.guard(self.timeouts.write, stream.write_packet(self.protocol, device_id, packet_id, data)).await?;
+            exchange.complete();
+            self.metrics.sent(packet_id, HEADER_LEN + size);
+            Ok(())
+        }).await.map_err(|e| self.failed(e, device_id, packet_id))
+    }
+
+    async fn request<I: OpenRGBWritable, O: OpenRGBReadable>(&self, device_id: u32, packet_id: PacketId, data: I) -> Result<O, OpenRGBError> {
+        let size = data.size(self.protocol);
+        self.traced(device_id, packet_id, size, async {
+            let mut stream = self.lock().await?;
+            let exchange = Exchange::start(&self.poisoned);
+            let start = tokio::time::Instant::now();
+            self.guard(self.timeouts.write, stream.write_packet(self.protocol, device_id, packet_id, data)).await?;
+            self.metrics.sent(packet_id, HEADER_LEN + size);
+            let payload = self.guard(self.timeouts.read, self.limits.apply(read_packet_payload(&mut *stream, self.protocol, device_id, packet_id, |len| {
+                self.metrics.received(PacketId::DeviceListUpdated, HEADER_LEN + len, None);
+                let _ = self.device_list_updated.send(());
+            }))).await?;
+            exchange.complete();
+            drop(stream);
+            self.metrics.received(packet_id, HEADER_LEN + payload.len(), Some(start.elapsed()));
+            self.limits.apply(decode_payload(packet_id, &payload, self.protocol)).await
+        }).await.map_err(|e| self.failed(e, device_id, packet_id))
+    }
+
+    /// Record failed request error and attach its context.
+    fn failed(&self, error: OpenRGBError, device_id: u32, packet_id: PacketId) -> OpenRGBError {
+        self.metrics.error(&error);
+        error.with_context(device_id, packet_id, self.protocol)
+    }
+
+    /// Run request `f`, within a span recording its latency if `tracing` feature is enabled.
+    #[cfg(feature = "tracing")]
+    async fn traced<T>(&self, device_id: u32, packet_id: PacketId, request_size: usize, f: impl Future<Output=Result<T, OpenRGBError>>) -> Result<T, OpenRGBError> {
+        use tracing::{field::Empty, Instrument};
+
+        let span = tracing::debug_span!(
+            "openrgb_request",
+            device_id,
+            ?packet_id,
+            protocol = self.protocol,
+            request_size,
+            response_size = Empty,
+            latency_us = Empty,
+        );
+        let start = std::time::Instant::now();
+        let result = f.instrument(span.clone()).await;
+        let latency = start.elapsed();
+        span.record("latency_us", latency.as_micros() as u64);
+        let _enter = span.enter();
+        match &result {
+            Ok(_) => tracing::debug!(?latency, "request completed"),
+            Err(e) => tracing::warn!(?latency, error = %e, "request failed"),
+        }
+        result
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    async fn traced<T>(&self, _device_id: u32, _packet_id: PacketId, _request_size: usize, f: impl Future<Output=Result<T, OpenRGBError>>) -> Result<T, OpenRGBError> {
+        f.await
+    }
+
+    /// Lock connection, failing if it has been poisoned.
+    async fn lock(&self) -> Result<MutexGuard<'_, S>, OpenRGBError> {
+        let stream = self.stream.lock().await;
+        if self.poisoned.load(Ordering::SeqCst) {
+            return Err(ConnectionPoisoned);
+        }
+        Ok(stream)
+    }
+
+    /// Run `f` of an [Exchange] with `timeout`.
+    async fn guard<T>(&self, timeout: Option<Duration>, f: impl Future<Output=Result<T, OpenRGBError>>) -> Result<T, OpenRGBError> {
+        let result = with_timeout(timeout, f).await;
+        if let Err(Timeout { duration }) = result {
+            warn!("OpenRGB server did not answer within {:?}", duration);
+        }
+        result
+    }
+
     fn check_protocol_version_profile_control(&self) -> Result<(), OpenRGBError> {
         if self.protocol < 2 {
             return Err(UnsupportedOperation {
@@ -304,9 +664,17 @@ impl<S: OpenRGBStream> OpenRGB<S> {
 #[cfg(test)]
 mod tests {
     use std::error::Error;
+    use std::time::Duration;
 
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+    #[cfg(unix)]
+    use tokio::net::UnixListener;
     use tokio_test::io::Builder;
 
+    use crate::{DecodeLimits, DEFAULT_PROTOCOL, OpenRGB, OpenRGBError, RequestContext, Timeouts};
+    use crate::data::{Color, fixtures, PacketId};
+    use crate::message::{Request, Response};
+    use crate::mock::MockBuilder;
     use crate::tests::{OpenRGBMockBuilder, setup};
 
     #[tokio::test]
@@ -335,6 +703,146 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_duplex_stream() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (client_stream, mut server_stream) = duplex(64);
+
+        let server = tokio::spawn(async move {
+            let mut request = [0_u8; 20];
+            server_stream.read_exact(&mut request).await?;
+            server_stream.write_all(&request).await?;
+            Ok::<_, std::io::Error>(request)
+        });
+
+        let client = OpenRGB::new(client_stream).await?;
+
+        assert_eq!(client.get_protocol_version(), DEFAULT_PROTOCOL);
+        assert_eq!(&server.await??[..4], b"ORGB");
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_read_timeout() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (client_stream, mut server_stream) = duplex(64);
+
+        let server = tokio::spawn(async move {
+            let mut request = [0_u8; 20];
+            server_stream.read_exact(&mut request).await?;
+            server_stream.write_all(&request).await?;
+
+            // never answer next requests
+            let mut request = [0_u8; 16];
+            server_stream.read_exact(&mut request).await?;
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+            Ok::<_, std::io::Error>(())
+        });
+
+        let mut client = OpenRGB::new(client_stream).await?;
+        client.set_timeouts(Timeouts { read: Some(Duration::from_secs(2)), ..Default::default() });
+
+        let error = client.get_controller_count().await.unwrap_err();
+        assert!(matches!(error.cause(), OpenRGBError::Timeout { duration } if *duration == Duration::from_secs(2)));
+        assert!(matches!(client.get_controller_count().await.unwrap_err().cause(), OpenRGBError::ConnectionPoisoned));
+        assert!(matches!(client.with_timeouts(Timeouts::default()).update_leds(0, vec![]).await.unwrap_err().cause(), OpenRGBError::ConnectionPoisoned));
+
+        server.abort();
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dropped_request() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (client_stream, mut server_stream) = duplex(1024);
+
+        let server = tokio::spawn(async move {
+            let mut request = [0_u8; 20];
+            server_stream.read_exact(&mut request).await?;
+            server_stream.write_all(&request).await?;
+
+            // answer controller data request with part of the payload only
+            let mut request = [0_u8; 20];
+            server_stream.read_exact(&mut request).await?;
+            request[12..16].copy_from_slice(&100_u32.to_le_bytes());
+            server_stream.write_all(&request[..16]).await?;
+            server_stream.write_all(&[0; 10]).await?;
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+            Ok::<_, std::io::Error>(())
+        });
+
+        let client = OpenRGB::new(client_stream).await?;
+
+        // caller gives up while the response is being read
+        assert!(tokio::time::timeout(Duration::from_secs(1), client.get_controller(0)).await.is_err());
+        assert!(matches!(client.get_controller_count().await.unwrap_err().cause(), OpenRGBError::ConnectionPoisoned));
+
+        server.abort();
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeout_override() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (client_stream, mut server_stream) = duplex(64);
+
+        let server = tokio::spawn(async move {
+            let mut request = [0_u8; 20];
+            server_stream.read_exact(&mut request).await?;
+            server_stream.write_all(&request).await?;
+
+            // answer controller count after a delay
+            let mut request = [0_u8; 16];
+            server_stream.read_exact(&mut request).await?;
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            request[12..].copy_from_slice(&4_u32.to_le_bytes());
+            server_stream.write_all(&request).await?;
+            server_stream.write_all(&3_u32.to_le_bytes()).await
+        });
+
+        let mut client = OpenRGB::new(client_stream).await?;
+        client.set_timeouts(Timeouts { read: Some(Duration::from_secs(2)), ..Default::default() });
+
+        let slow = Timeouts { read: Some(Duration::from_secs(20)), ..client.get_timeouts() };
+        assert_eq!(client.with_timeouts(slow).get_controller_count().await?, 3);
+        server.await??;
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_connect_unix() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let path = std::env::temp_dir().join(format!("openrgb-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+
+        let server = tokio::spawn(async move {
+            let (mut server_stream, _) = listener.accept().await?;
+            let mut request = [0_u8; 20];
+            server_stream.read_exact(&mut request).await?;
+            request[16..].copy_from_slice(&2_u32.to_le_bytes());
+            server_stream.write_all(&request).await
+        });
+
+        let client = OpenRGB::connect_unix(&path).await?;
+        server.await??;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(client.get_protocol_version(), 2);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_set_name() -> Result<(), Box<dyn Error>> {
         setup()?;
@@ -354,158 +862,499 @@ mod tests {
     }
 
     #[tokio::test]
-    #[ignore]
+    async fn test_decode_limits() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut client = Builder::new()
+            .negotiate_default_protocol()
+            .write(b"ORGB") // magic
+            .write(&0_u32.to_le_bytes()) // device id
+            .write(&0_u32.to_le_bytes()) // packet id
+            .write(&0_u32.to_le_bytes()) // data size
+            .read(b"ORGB") // magic
+            .read(&0_u32.to_le_bytes()) // device id
+            .read(&0_u32.to_le_bytes()) // packet id
+            .read(&4_u32.to_le_bytes()) // data size
+            .to_client().await?;
+
+        client.set_decode_limits(DecodeLimits { max_packet_size: 2, ..Default::default() });
+
+        assert!(matches!(
+            client.get_controller_count().await.unwrap_err().cause(),
+            OpenRGBError::DecodeLimitExceeded { value: 4, limit: 2, .. }
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_controller_protocol_versions() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        for protocol in fixtures::PROTOCOLS {
+            let (data, controller) = fixtures::controller(protocol);
+            let mut builder = Builder::new();
+            builder
+                .negotiate_protocol(protocol)
+                .write(b"ORGB") // magic
+                .write(&3_u32.to_le_bytes()) // device id
+                .write(&1_u32.to_le_bytes()); // packet id
+            if protocol >= 1 {
+                builder
+                    .write(&4_u32.to_le_bytes()) // data size
+                    .write(&protocol.to_le_bytes()); // protocol version
+            } else {
+                builder.write(&0_u32.to_le_bytes()); // data size
+            }
+            let client = builder
+                .read(b"ORGB") // magic
+                .read(&3_u32.to_le_bytes()) // device id
+                .read(&1_u32.to_le_bytes()) // packet id
+                .read(&(data.len() as u32).to_le_bytes()) // data size
+                .read(&data) // controller data
+                .to_client().await?;
+
+            assert_eq!(client.get_controller(3).await?, controller, "protocol {}", protocol);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_skip_trailing_bytes() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = Builder::new()
+            .negotiate_default_protocol()
+            .write(b"ORGB") // magic
+            .write(&0_u32.to_le_bytes()) // device id
+            .write(&0_u32.to_le_bytes()) // packet id
+            .write(&0_u32.to_le_bytes()) // data size
+            .read(b"ORGB") // magic
+            .read(&0_u32.to_le_bytes()) // device id
+            .read(&0_u32.to_le_bytes()) // packet id
+            .read(&6_u32.to_le_bytes()) // data size
+            .read(&3_u32.to_le_bytes()) // controller count
+            .read(&[1, 2]) // unknown field
+            .write(b"ORGB") // magic
+            .write(&0_u32.to_le_bytes()) // device id
+            .write(&0_u32.to_le_bytes()) // packet id
+            .write(&0_u32.to_le_bytes()) // data size
+            .read(b"ORGB") // magic
+            .read(&0_u32.to_le_bytes()) // device id
+            .read(&0_u32.to_le_bytes()) // packet id
+            .read(&4_u32.to_le_bytes()) // data size
+            .read(&5_u32.to_le_bytes()) // controller count
+            .to_client().await?;
+
+        assert_eq!(client.get_controller_count().await?, 3);
+        assert_eq!(client.get_controller_count().await?, 5);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_truncated_payload() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = Builder::new()
+            .negotiate_default_protocol()
+            .write(b"ORGB") // magic
+            .write(&0_u32.to_le_bytes()) // device id
+            .write(&0_u32.to_le_bytes()) // packet id
+            .write(&0_u32.to_le_bytes()) // data size
+            .read(b"ORGB") // magic
+            .read(&0_u32.to_le_bytes()) // device id
+            .read(&0_u32.to_le_bytes()) // packet id
+            .read(&2_u32.to_le_bytes()) // data size
+            .read(&[3, 0]) // truncated controller count
+            .to_client().await?;
+
+        assert!(matches!(client.get_controller_count().await.unwrap_err().cause(), OpenRGBError::TruncatedPayload { len: 2, .. }));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_context() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = Builder::new()
+            .negotiate_default_protocol()
+            .write(b"ORGB") // magic
+            .write(&2_u32.to_le_bytes()) // device id
+            .write(&1_u32.to_le_bytes()) // packet id
+            .write(&4_u32.to_le_bytes()) // data size
+            .write(&DEFAULT_PROTOCOL.to_le_bytes()) // protocol version
+            .read(b"ORGB") // magic
+            .read(&2_u32.to_le_bytes()) // device id
+            .read(&0_u32.to_le_bytes()) // packet id
+            .read(&0_u32.to_le_bytes()) // data size
+            .to_client().await?;
+
+        let error = client.get_controller(2).await.unwrap_err();
+        assert!(matches!(
+            error.cause(),
+            OpenRGBError::UnexpectedPacket { expected: PacketId::RequestControllerData, got: PacketId::RequestControllerCount }
+        ));
+        assert_eq!(error.context(), Some(&RequestContext { device_id: 2, packet_id: PacketId::RequestControllerData, protocol: DEFAULT_PROTOCOL }));
+
+        // payload of unexpected packet may be left on the connection
+        let error = client.get_controller_count().await.unwrap_err();
+        assert!(matches!(error.cause(), OpenRGBError::ConnectionPoisoned));
+
+        let client = Builder::new()
+            .negotiate_default_protocol()
+            .write(b"ORGB") // magic
+            .write(&0_u32.to_le_bytes()) // device id
+            .write(&0_u32.to_le_bytes()) // packet id
+            .write(&0_u32.to_le_bytes()) // data size
+            .read(b"RGBO") // magic
+            .to_client().await?;
+
+        let error = client.get_controller_count().await.unwrap_err();
+        assert!(matches!(error.cause(), OpenRGBError::BadMagic { got } if got == b"RGBO"));
+        assert_eq!(error.context().map(|context| context.packet_id), Some(PacketId::RequestControllerCount));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_metrics() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(3))
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(3))
+            .expect(Request::UpdateLeds { controller: 1, colors: vec![Color::new(255, 0, 0)] })
+            .expect(Request::ControllerCount)
+            .to_client().await?;
+
+        client.get_controller_count().await?;
+        client.with_timeouts(Timeouts::default()).get_controller_count().await?;
+        client.update_leds(1, vec![Color::new(255, 0, 0)]).await?;
+        assert!(client.get_controller_count().await.is_err());
+
+        let metrics = client.metrics();
+        let count = metrics.packets[&PacketId::RequestControllerCount];
+        assert_eq!((count.sent, count.sent_bytes, count.received, count.received_bytes), (3, 48, 2, 40));
+        assert_eq!(metrics.latencies[&PacketId::RequestControllerCount].count, 2);
+        let update = metrics.packets[&PacketId::RGBControllerUpdateLeds];
+        assert_eq!((update.sent, update.sent_bytes, update.received), (1, 16 + 10, 0));
+        assert!(!metrics.latencies.contains_key(&PacketId::RGBControllerUpdateLeds));
+        assert_eq!(metrics.packets[&PacketId::RequestProtocolVersion].sent, 1);
+        assert_eq!(metrics.errors, [("CommunicationError", 1)].into_iter().collect());
+
+        client.reset_metrics();
+        assert!(client.metrics().packets.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_device_list_updates() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::DeviceListUpdated)
+            .respond(Response::ControllerCount(3))
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(3))
+            .expect(Request::ControllerCount)
+            .respond(Response::DeviceListUpdated)
+            .respond(Response::ControllerCount(2))
+            .to_client().await?;
+
+        let mut updates = client.device_list_updates();
+        assert_eq!(client.get_controller_count().await?, 3);
+        assert!(updates.try_recv().is_ok());
+        assert!(updates.try_recv().is_err());
+        let metrics = client.metrics();
+        let notified = metrics.packets[&PacketId::DeviceListUpdated];
+        assert_eq!((notified.received, notified.received_bytes), (1, 16));
+        assert!(!metrics.latencies.contains_key(&PacketId::DeviceListUpdated));
+
+        let mut watcher = client.watch_device_list(Duration::from_secs(1));
+        watcher.changed().await?;
+        assert!(updates.try_recv().is_ok());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_tracing() -> Result<(), Box<dyn Error>> {
+        use std::sync::Mutex;
+        use tracing::{Event, Id, Metadata, Subscriber};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Record};
+        use tracing_core::span::Current;
+
+        /// Collects recorded span fields and event fields, with a single span.
+        #[derive(Default)]
+        struct Collector(Mutex<Vec<String>>, Mutex<Option<&'static Metadata<'static>>>);
+
+        impl Visit for &Collector {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.lock().unwrap().push(format!("{}={:?}", field.name(), value));
+            }
+        }
+
+        impl Subscriber for &'static Collector {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool { true }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                span.record(&mut &**self);
+                *self.1.lock().unwrap() = Some(span.metadata());
+                Id::from_u64(1)
+            }
+            fn current_span(&self) -> Current {
+                match *self.1.lock().unwrap() {
+                    Some(metadata) => Current::new(Id::from_u64(1), metadata),
+                    None => Current::none(),
+                }
+            }
+            fn record(&self, _span: &Id, values: &Record<'_>) { values.record(&mut &**self) }
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+            fn event(&self, event: &Event<'_>) { event.record(&mut &**self) }
+            fn enter(&self, _span: &Id) {}
+            fn exit(&self, _span: &Id) {}
+        }
+
+        setup()?;
+
+        let collector: &'static Collector = Box::leak(Box::default());
+        let _guard = tracing::subscriber::set_default(collector);
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::DeviceListUpdated)
+            .respond(Response::ControllerCount(3))
+            .to_client().await?;
+        client.get_controller_count().await?;
+
+        let records = collector.0.lock().unwrap().clone();
+        for expected in ["packet_id=RequestControllerCount", "device_id=0", "request_size=0", "response_size=4", "message=received unsolicited packet", "message=request completed"] {
+            assert!(records.iter().any(|r| r == expected), "{} not found in {:?}", expected, records);
+        }
+        assert!(records.iter().any(|r| r.starts_with("latency_us=")));
+
+        Ok(())
+    }
+
+    #[tokio::test]
     async fn test_get_controller_count() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(3))
             .to_client().await?;
 
-        todo!("test not implemented")
+        assert_eq!(client.get_controller_count().await?, 3);
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_get_controller() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::ControllerData { controller: 2 })
+            .respond(Response::ControllerData(controller.clone()))
             .to_client().await?;
 
-        todo!("test not implemented")
+        assert_eq!(client.get_controller(2).await?, controller);
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_update_zone_leds() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let colors = vec![Color::new(37, 54, 126), Color::new(37, 54, 255)];
+
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::UpdateZoneLeds { controller: 2, zone: 1, colors: colors.clone() })
             .to_client().await?;
 
-        todo!("test not implemented")
+        client.update_zone_leds(2, 1, colors).await?;
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_resize_zone() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::ResizeZone { controller: 2, zone: 1, size: 18 })
             .to_client().await?;
 
-        todo!("test not implemented")
+        client.resize_zone(2, 1, 18).await?;
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_save_profile() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::SaveProfile("test".to_string()))
             .to_client().await?;
 
-        todo!("test not implemented")
+        client.save_profile("test").await?;
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_update_leds() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let colors = vec![Color::new(37, 54, 126), Color::new(37, 54, 255)];
+
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::UpdateLeds { controller: 2, colors: colors.clone() })
             .to_client().await?;
 
-        todo!("test not implemented")
+        client.update_leds(2, colors).await?;
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_delete_profile() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::DeleteProfile("test".to_string()))
             .to_client().await?;
 
-        todo!("test not implemented")
+        client.delete_profile("test").await?;
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_load_profile() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::LoadProfile("test".to_string()))
             .to_client().await?;
 
-        todo!("test not implemented")
+        client.load_profile("test").await?;
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_update_led() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::UpdateSingleLed { controller: 2, led: 5, color: Color::new(37, 54, 126) })
             .to_client().await?;
 
-        todo!("test not implemented")
+        client.update_led(2, 5, Color::new(37, 54, 126)).await?;
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_get_profiles() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::ProfileList)
+            .respond(Response::ProfileList(vec!["p1".to_string(), "p2".to_string()]))
             .to_client().await?;
 
-        todo!("test not implemented")
+        assert_eq!(client.get_profiles().await?, vec!["p1".to_string(), "p2".to_string()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_profiles_unsupported() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = MockBuilder::new()
+            .negotiate_protocol(1)
+            .to_client().await?;
+
+        assert!(matches!(
+            client.get_profiles().await,
+            Err(OpenRGBError::UnsupportedOperation { current_protocol_version: 1, min_protocol_version: 2, .. })
+        ));
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_update_mode() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let (_, mode) = fixtures::mode(DEFAULT_PROTOCOL);
+
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::UpdateMode { controller: 2, mode_id: 3, mode: mode.clone() })
             .to_client().await?;
 
-        todo!("test not implemented")
+        client.update_mode(2, 3, mode).await?;
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_set_custom_mode() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::SetCustomMode { controller: 2 })
             .to_client().await?;
 
-        todo!("test not implemented")
+        client.set_custom_mode(2).await?;
+
+        Ok(())
     }
 
     #[tokio::test]
-    #[ignore]
     async fn test_save_mode() -> Result<(), Box<dyn Error>> {
         setup()?;
 
-        let _client = Builder::new()
+        let (_, mode) = fixtures::mode(DEFAULT_PROTOCOL);
+
+        let client = MockBuilder::new()
             .negotiate_default_protocol()
+            .expect(Request::SaveMode { controller: 2, mode_id: 3, mode: mode.clone() })
             .to_client().await?;
 
-        todo!("test not implemented")
+        client.save_mode(2, 3, mode).await?;
+
+        Ok(())
     }
 }
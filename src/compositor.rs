@@ -0,0 +1,393 @@
+//! Priority-based layer compositing.
+//!
+//! When several sources (eg: a game effect, a notification and an ambient scene) write LEDs directly, the last writer
+//! wins. With a [Compositor], each source owns a named [Layer] instead, and the compositor merges all layers into the
+//! final colors of each controller, sending only the result.
+//!
+//! Layers are stacked by ascending priority, layers with the same priority in insertion order. Each layer is blended
+//! onto the result of the layers below with its [BlendMode], weighted by its opacity and optional per-LED mask. The
+//! bottom of the stack is black.
+//!
+//! Layers can expire: an expired layer is removed on next render, so that eg: an alert disappears after its timeout
+//! and the scene below shows again.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::compositor::{BlendMode, Compositor, Layer};
+//! # use openrgb::data::Color;
+//! # use std::error::Error;
+//! # use std::time::Duration;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let compositor = Compositor::new(OpenRGB::connect().await?);
+//!
+//! compositor.set_layer("scene", Layer::new(0).colors(0, vec![Color::new(0, 0, 255); 10]));
+//! compositor.set_layer("alert", Layer::new(10)
+//!     .colors(0, vec![Color::new(255, 0, 0); 10])
+//!     .blend(BlendMode::Screen)
+//!     .opacity(0.8)
+//!     .expire_after(Duration::from_secs(5)));
+//!
+//! compositor.run(Duration::from_millis(50)).await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::debug;
+use tokio::time::Instant;
+
+use crate::{OpenRGB, OpenRGBError};
+use crate::data::Color;
+use crate::protocol::OpenRGBStream;
+
+/// How a [Layer] color is combined with the color below it, before opacity is applied.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum BlendMode {
+    /// Layer color replaces color below.
+    #[default]
+    Normal,
+
+    /// Sum of colors, saturating.
+    Add,
+
+    /// Product of colors, darkening.
+    Multiply,
+
+    /// Inverse product of inverted colors, lightening.
+    Screen,
+
+    /// Brightest of colors, per channel.
+    Lighten,
+
+    /// Darkest of colors, per channel.
+    Darken,
+}
+
+impl BlendMode {
+    /// Blend `top` channel value onto `bottom` one, both between 0 and 1.
+    fn apply(self, bottom: f32, top: f32) -> f32 {
+        match self {
+            BlendMode::Normal => top,
+            BlendMode::Add => (bottom + top).min(1.0),
+            BlendMode::Multiply => bottom * top,
+            BlendMode::Screen => 1.0 - (1.0 - bottom) * (1.0 - top),
+            BlendMode::Lighten => bottom.max(top),
+            BlendMode::Darken => bottom.min(top),
+        }
+    }
+}
+
+/// Compositor layer, see [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct Layer {
+    priority: i32,
+    opacity: f32,
+    blend: BlendMode,
+    colors: HashMap<u32, Vec<Color>>,
+    masks: HashMap<u32, Vec<f32>>,
+    expire_after: Option<Duration>,
+}
+
+impl Layer {
+    /// Build an empty, fully opaque layer with [BlendMode::Normal] and given `priority`.
+    ///
+    /// Layers with higher priority are stacked above layers with lower priority.
+    pub fn new(priority: i32) -> Self {
+        Self {
+            priority,
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+            colors: HashMap::new(),
+            masks: HashMap::new(),
+            expire_after: None,
+        }
+    }
+
+    /// Set layer colors for controller `controller_id`.
+    ///
+    /// Controllers without colors in this layer show the layers below.
+    pub fn colors(mut self, controller_id: u32, colors: Vec<Color>) -> Self {
+        self.colors.insert(controller_id, colors);
+        self
+    }
+
+    /// Set layer opacity, between 0 (transparent) and 1 (opaque, default).
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set layer blend mode.
+    pub fn blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Set per-LED opacity mask for controller `controller_id`, values between 0 (hidden) and 1 (visible).
+    ///
+    /// LEDs beyond mask length are visible.
+    pub fn mask(mut self, controller_id: u32, mask: Vec<f32>) -> Self {
+        self.masks.insert(controller_id, mask);
+        self
+    }
+
+    /// Remove layer `duration` after it is set on compositor.
+    pub fn expire_after(mut self, duration: Duration) -> Self {
+        self.expire_after = Some(duration);
+        self
+    }
+
+    /// Blend this layer onto `colors` of controller `controller_id`.
+    fn blend_onto(&self, controller_id: u32, colors: &mut Vec<Color>) {
+        let layer = match self.colors.get(&controller_id) {
+            Some(layer) => layer,
+            None => return,
+        };
+        if colors.len() < layer.len() {
+            colors.resize(layer.len(), Color::new(0, 0, 0));
+        }
+        let mask = self.masks.get(&controller_id);
+        for (i, (bottom, top)) in colors.iter_mut().zip(layer).enumerate() {
+            let alpha = self.opacity * mask.and_then(|m| m.get(i)).map_or(1.0, |a| a.clamp(0.0, 1.0));
+            let channel = |bottom: u8, top: u8| {
+                let (bottom, top) = (f32::from(bottom) / 255.0, f32::from(top) / 255.0);
+                let blended = self.blend.apply(bottom, top);
+                ((bottom + (blended - bottom) * alpha) * 255.0).round() as u8
+            };
+            *bottom = Color::new(channel(bottom.r, top.r), channel(bottom.g, top.g), channel(bottom.b, top.b));
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    layer: Layer,
+    expires_at: Option<Instant>,
+}
+
+/// Layer compositor, see [module documentation](self).
+pub struct Compositor<S: OpenRGBStream> {
+    client: OpenRGB<S>,
+    layers: Mutex<Vec<Entry>>,
+    sent: tokio::sync::Mutex<HashMap<u32, Vec<Color>>>,
+}
+
+impl<S: OpenRGBStream> Compositor<S> {
+    /// Build a compositor sending to `client`.
+    pub fn new(client: OpenRGB<S>) -> Self {
+        Self {
+            client,
+            layers: Mutex::new(Vec::new()),
+            sent: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Client used by compositor.
+    pub fn client(&self) -> &OpenRGB<S> {
+        &self.client
+    }
+
+    /// Set layer `name`, replacing any layer with the same name.
+    ///
+    /// A replaced layer keeps its position among layers with the same priority, and its expiry is reset.
+    pub fn set_layer(&self, name: impl Into<String>, layer: Layer) {
+        let name = name.into();
+        let expires_at = layer.expire_after.map(|d| Instant::now() + d);
+        let mut layers = self.layers.lock().unwrap();
+        match layers.iter_mut().find(|e| e.name == name) {
+            Some(entry) => {
+                entry.layer = layer;
+                entry.expires_at = expires_at;
+            }
+            None => layers.push(Entry { name, layer, expires_at }),
+        }
+    }
+
+    /// Update colors of controller `controller_id` in layer `name`, keeping its other settings and expiry.
+    ///
+    /// Returns `false` if there is no such layer.
+    pub fn set_colors(&self, name: &str, controller_id: u32, colors: Vec<Color>) -> bool {
+        match self.layers.lock().unwrap().iter_mut().find(|e| e.name == name) {
+            Some(entry) => {
+                entry.layer.colors.insert(controller_id, colors);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove layer `name`, returning it if any.
+    pub fn remove_layer(&self, name: &str) -> Option<Layer> {
+        let mut layers = self.layers.lock().unwrap();
+        let index = layers.iter().position(|e| e.name == name)?;
+        Some(layers.remove(index).layer)
+    }
+
+    /// Names of current layers, bottom to top.
+    pub fn layer_names(&self) -> Vec<String> {
+        self.remove_expired();
+        self.sorted(|entries| entries.iter().map(|e| e.name.clone()).collect())
+    }
+
+    /// Merge current layers into final colors of each controller they cover.
+    pub fn render(&self) -> HashMap<u32, Vec<Color>> {
+        self.remove_expired();
+        self.sorted(|entries| {
+            let mut frames = HashMap::new();
+            for entry in entries {
+                for controller_id in entry.layer.colors.keys() {
+                    entry.layer.blend_onto(*controller_id, frames.entry(*controller_id).or_insert_with(Vec::new));
+                }
+            }
+            frames
+        })
+    }
+
+    /// Render layers and send colors of controllers that changed since last flush.
+    ///
+    /// Controllers no longer covered by any layer are sent black once.
+    pub async fn flush(&self) -> Result<(), OpenRGBError> {
+        let mut frames = self.render();
+        let covered: Vec<u32> = frames.keys().copied().collect();
+        let mut sent = self.sent.lock().await;
+        for (controller_id, colors) in sent.iter() {
+            frames.entry(*controller_id).or_insert_with(|| vec![Color::new(0, 0, 0); colors.len()]);
+        }
+        for (controller_id, colors) in frames {
+            if sent.get(&controller_id) != Some(&colors) {
+                debug!("Sending {} composited colors to controller {}", colors.len(), controller_id);
+                self.client.update_leds(controller_id, colors.clone()).await?;
+                sent.insert(controller_id, colors);
+            }
+        }
+        sent.retain(|controller_id, _| covered.contains(controller_id));
+        Ok(())
+    }
+
+    /// Flush every `interval` forever.
+    ///
+    /// Returns on first failed request, eg: if connection is lost.
+    pub async fn run(&self, interval: Duration) -> Result<(), OpenRGBError> {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.flush().await?;
+        }
+    }
+
+    fn remove_expired(&self) {
+        let now = Instant::now();
+        self.layers.lock().unwrap().retain(|e| match e.expires_at {
+            Some(expires_at) if expires_at <= now => {
+                debug!("Layer {:?} expired", e.name);
+                false
+            }
+            _ => true,
+        });
+    }
+
+    /// Call `f` with layers sorted bottom to top.
+    fn sorted<T>(&self, f: impl FnOnce(&[&Entry]) -> T) -> T {
+        let layers = self.layers.lock().unwrap();
+        let mut entries: Vec<_> = layers.iter().collect();
+        // stable sort keeps insertion order for equal priorities
+        entries.sort_by_key(|e| e.layer.priority);
+        f(&entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::time::Duration;
+
+    use crate::compositor::{BlendMode, Compositor, Layer};
+    use crate::data::Color;
+    use crate::message::Request;
+    use crate::mock::MockBuilder;
+    use crate::tests::setup;
+
+    #[tokio::test]
+    async fn test_render() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let compositor = Compositor::new(MockBuilder::new().negotiate_default_protocol().to_client().await?);
+        let gray = Color::new(128, 128, 128);
+
+        compositor.set_layer("top", Layer::new(1).colors(0, vec![Color::new(255, 0, 0); 3]).mask(0, vec![1.0, 0.5, 0.0]));
+        compositor.set_layer("bottom", Layer::new(0).colors(0, vec![Color::new(0, 0, 255); 4]));
+        assert_eq!(compositor.layer_names(), vec!["bottom", "top"]);
+        assert_eq!(compositor.render()[&0], vec![
+            Color::new(255, 0, 0),
+            Color::new(128, 0, 128),
+            Color::new(0, 0, 255),
+            Color::new(0, 0, 255),
+        ]);
+
+        for (blend, expected) in [
+            (BlendMode::Normal, Color::new(255, 0, 0)),
+            (BlendMode::Add, Color::new(255, 128, 128)),
+            (BlendMode::Multiply, Color::new(128, 0, 0)),
+            (BlendMode::Screen, Color::new(255, 128, 128)),
+            (BlendMode::Lighten, Color::new(255, 128, 128)),
+            (BlendMode::Darken, Color::new(128, 0, 0)),
+        ] {
+            compositor.set_layer("bottom", Layer::new(0).colors(0, vec![gray]));
+            compositor.set_layer("top", Layer::new(1).colors(0, vec![Color::new(255, 0, 0)]).blend(blend));
+            assert_eq!(compositor.render()[&0], vec![expected], "{:?}", blend);
+        }
+
+        compositor.set_layer("top", Layer::new(1).colors(0, vec![Color::new(255, 0, 0)]).opacity(0.25));
+        assert_eq!(compositor.render()[&0], vec![Color::new(160, 96, 96)]);
+
+        assert!(compositor.remove_layer("top").is_some());
+        assert!(!compositor.set_colors("top", 0, vec![]));
+        assert_eq!(compositor.render()[&0], vec![gray]);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flush() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let scene = vec![Color::new(0, 0, 255); 2];
+        let alert = vec![Color::new(255, 0, 0); 2];
+
+        let compositor = Compositor::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::UpdateLeds { controller: 0, colors: alert.clone() })
+            .expect(Request::UpdateLeds { controller: 0, colors: scene.clone() })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![Color::new(0, 0, 0); 2] })
+            .to_client().await?);
+
+        compositor.set_layer("scene", Layer::new(0).colors(0, scene));
+        compositor.set_layer("alert", Layer::new(10).colors(0, alert).expire_after(Duration::from_secs(5)));
+        compositor.flush().await?;
+
+        // unchanged result is not sent again
+        tokio::time::advance(Duration::from_secs(4)).await;
+        compositor.flush().await?;
+
+        // expired alert reveals scene
+        tokio::time::advance(Duration::from_secs(1)).await;
+        compositor.flush().await?;
+        assert_eq!(compositor.layer_names(), vec!["scene"]);
+
+        // uncovered controller is cleared
+        compositor.remove_layer("scene");
+        compositor.flush().await?;
+        compositor.flush().await?;
+
+        Ok(())
+    }
+}
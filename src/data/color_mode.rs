@@ -1,55 +1,21 @@
-use std::mem::size_of;
-
-use async_trait::async_trait;
-use num_traits::FromPrimitive;
-
-use crate::data::{OpenRGBReadable, OpenRGBWritable};
-use crate::OpenRGBError;
-use crate::OpenRGBError::ProtocolError;
-use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
-
-/// RGB controller color mode.
-///
-/// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation) for more information.
-#[derive(Primitive, Eq, PartialEq, Debug, Copy, Clone)]
-pub enum ColorMode {
-
-    /// No color mode.
-    None = 0,
-
-    /// Per LED colors.
-    PerLED = 1,
-
-    /// Mode specific colors.
-    ModeSpecific = 2,
-
-    /// Random colors.
-    Random = 3,
-}
-
-impl Default for ColorMode {
-    fn default() -> Self {
-        ColorMode::None
-    }
-}
-
-#[async_trait]
-impl OpenRGBWritable for ColorMode {
-    fn size(&self, _protocol: u32) -> usize {
-        size_of::<u32>()
-    }
-
-    async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
-        stream.write_value(self as u32, protocol).await
-    }
-}
-
-#[async_trait]
-impl OpenRGBReadable for ColorMode {
-    async fn read(stream: &mut impl OpenRGBReadableStream, protocol: u32) -> Result<Self, OpenRGBError> {
-        stream.read_value(protocol)
-            .await
-            .and_then(|id| ColorMode::from_u32(id).ok_or_else(|| ProtocolError(format!("unknown color mode \"{}\"", id))))
+u32_enum! {
+    /// RGB controller color mode.
+    ///
+    /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation) for more information.
+    #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Default)]
+    pub enum ColorMode {
+        /// No color mode.
+        #[default]
+        None = 0,
+
+        /// Per LED colors.
+        PerLED = 1,
+
+        /// Mode specific colors.
+        ModeSpecific = 2,
+
+        /// Random colors.
+        Random = 3,
     }
 }
 
@@ -89,4 +55,30 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_read_unknown_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .read(&4242_u32.to_le_bytes())
+            .build();
+
+        assert_eq!(stream.read_value::<ColorMode>(DEFAULT_PROTOCOL).await?, ColorMode::Other(4242));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_unknown_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .write(&4242_u32.to_le_bytes())
+            .build();
+
+        stream.write_value(ColorMode::Other(4242), DEFAULT_PROTOCOL).await?;
+
+        Ok(())
+    }
 }
@@ -1,13 +1,16 @@
+use std::mem::size_of;
+
 use async_trait::async_trait;
 
-use crate::data::{Color, DeviceType, LED, Mode, OpenRGBReadable, Zone};
-use crate::OpenRGBError;
-use crate::protocol::OpenRGBReadableStream;
+use crate::data::{Color, DeviceType, LED, Mode, OpenRGBReadable, OpenRGBWritable, read_n, read_vec, Zone};
+use crate::{DecodeLimits, limits, OpenRGBError};
+use crate::OpenRGBError::TooLargeToEncode;
+use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
 
 /// RGB controller.
 ///
 /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#net_packet_id_request_controller_data) for more information.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Controller {
     /// Controller type.
     pub r#type: DeviceType,
@@ -15,7 +18,7 @@ pub struct Controller {
     /// Controller name.
     pub name: String,
 
-    /// Controller vendor.
+    /// Controller vendor (empty for protocol versions below 1).
     pub vendor: String,
 
     /// Controller description.
@@ -49,23 +52,21 @@ pub struct Controller {
 #[async_trait]
 impl OpenRGBReadable for Controller {
     async fn read(stream: &mut impl OpenRGBReadableStream, protocol: u32) -> Result<Self, OpenRGBError> {
-        let _data_size = stream.read_value::<u32>(protocol).await?;
+        let limits = DecodeLimits::current();
+        let _data_size = limits::check_declared_size("controller data", stream.read_value::<u32>(protocol).await? as usize)?;
         let r#type = stream.read_value(protocol).await?;
         let name = stream.read_value(protocol).await?;
-        let vendor = stream.read_value(protocol).await?;
+        let vendor = if protocol >= 1 { stream.read_value(protocol).await? } else { String::new() };
         let description = stream.read_value(protocol).await?;
         let version = stream.read_value(protocol).await?;
         let serial = stream.read_value(protocol).await?;
         let location = stream.read_value(protocol).await?;
         let num_modes = stream.read_value::<u16>(protocol).await?;
         let active_mode = stream.read_value(protocol).await?;
-        let mut modes = Vec::with_capacity(num_modes as usize);
-        for _ in 0..num_modes {
-            modes.push(stream.read_value(protocol).await?);
-        }
-        let zones = stream.read_value(protocol).await?;
-        let leds = stream.read_value(protocol).await?;
-        let colors = stream.read_value(protocol).await?;
+        let modes = read_n(stream, protocol, "modes", num_modes as usize, limits.max_modes).await?;
+        let zones = read_vec(stream, protocol, "zones", limits.max_zones).await?;
+        let leds = read_vec(stream, protocol, "LEDs", limits.max_leds).await?;
+        let colors = read_vec(stream, protocol, "colors", limits.max_leds).await?;
 
         Ok(Controller {
             r#type,
@@ -84,6 +85,55 @@ impl OpenRGBReadable for Controller {
     }
 }
 
+#[async_trait]
+impl OpenRGBWritable for Controller {
+    fn size(&self, protocol: u32) -> usize {
+        let mut size = 0;
+        size += size_of::<u32>();
+        size += self.r#type.size(protocol);
+        size += self.name.size(protocol);
+        if protocol >= 1 {
+            size += self.vendor.size(protocol);
+        }
+        size += self.description.size(protocol);
+        size += self.version.size(protocol);
+        size += self.serial.size(protocol);
+        size += self.location.size(protocol);
+        size += size_of::<u16>();
+        size += self.active_mode.size(protocol);
+        size += self.modes.iter().map(|mode| mode.size(protocol)).sum::<usize>();
+        size += self.zones.size(protocol);
+        size += self.leds.size(protocol);
+        size += self.colors.size(protocol);
+        size
+    }
+
+    async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
+        let size = self.size(protocol);
+        let data_size = u32::try_from(size).map_err(|_| TooLargeToEncode { what: "controller".to_owned(), size })?;
+        let num_modes = u16::try_from(self.modes.len()).map_err(|_| TooLargeToEncode { what: "controller modes".to_owned(), size: self.modes.len() })?;
+        stream.write_value(data_size, protocol).await?;
+        stream.write_value(self.r#type, protocol).await?;
+        stream.write_value(self.name, protocol).await?;
+        if protocol >= 1 {
+            stream.write_value(self.vendor, protocol).await?;
+        }
+        stream.write_value(self.description, protocol).await?;
+        stream.write_value(self.version, protocol).await?;
+        stream.write_value(self.serial, protocol).await?;
+        stream.write_value(self.location, protocol).await?;
+        stream.write_value(num_modes, protocol).await?;
+        stream.write_value(self.active_mode, protocol).await?;
+        for mode in self.modes {
+            stream.write_value(mode, protocol).await?;
+        }
+        stream.write_value(self.zones, protocol).await?;
+        stream.write_value(self.leds, protocol).await?;
+        stream.write_value(self.colors, protocol).await?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::error::Error;
@@ -92,9 +142,9 @@ mod tests {
 
     use ModeFlag::*;
 
-    use crate::data::{Color, ColorMode, Controller, DeviceType, Mode, ModeFlag, Zone, ZoneType};
+    use crate::data::{fixtures, Color, ColorMode, Controller, DeviceType, Mode, ModeFlag, OpenRGBWritable, Zone, ZoneType};
     use crate::DEFAULT_PROTOCOL;
-    use crate::protocol::OpenRGBReadableStream;
+    use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
     use crate::tests::setup;
 
     #[tokio::test]
@@ -151,6 +201,7 @@ mod tests {
                     name: "Direct".to_string(),
                     value: 24,
                     flags: HasPerLEDColor.into(),
+                    raw_flags: 32,
                     speed_min: None,
                     speed_max: None,
                     brightness_min: None,
@@ -167,6 +218,7 @@ mod tests {
                     name: "Static".to_string(),
                     value: 25,
                     flags: HasModeSpecificColor.into(),
+                    raw_flags: 64,
                     speed_min: None,
                     speed_max: None,
                     brightness_min: None,
@@ -183,6 +235,7 @@ mod tests {
                     name: "Flow".to_string(),
                     value: 0,
                     flags: HasSpeed.into(),
+                    raw_flags: 1,
                     speed_min: Some(3),
                     speed_max: Some(0),
                     brightness_min: None,
@@ -199,6 +252,7 @@ mod tests {
                     name: "Spectrum".to_string(),
                     value: 4,
                     flags: HasSpeed.into(),
+                    raw_flags: 1,
                     speed_min: Some(3),
                     speed_max: Some(0),
                     brightness_min: None,
@@ -215,6 +269,7 @@ mod tests {
                     name: "Ripple".to_string(),
                     value: 8,
                     flags: HasSpeed | HasPerLEDColor,
+                    raw_flags: 33,
                     speed_min: Some(3),
                     speed_max: Some(0),
                     brightness_min: None,
@@ -231,6 +286,7 @@ mod tests {
                     name: "Blink".to_string(),
                     value: 12,
                     flags: HasSpeed | HasPerLEDColor,
+                    raw_flags: 33,
                     speed_min: Some(3),
                     speed_max: Some(0),
                     brightness_min: None,
@@ -247,6 +303,7 @@ mod tests {
                     name: "Pulse".to_string(),
                     value: 16,
                     flags: HasSpeed | HasPerLEDColor,
+                    raw_flags: 33,
                     speed_min: Some(3),
                     speed_max: Some(0),
                     brightness_min: None,
@@ -263,6 +320,7 @@ mod tests {
                     name: "Wave".to_string(),
                     value: 20,
                     flags: HasSpeed | HasPerLEDColor,
+                    raw_flags: 33,
                     speed_min: Some(3),
                     speed_max: Some(0),
                     brightness_min: None,
@@ -324,4 +382,22 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_protocol_versions_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        for protocol in fixtures::PROTOCOLS {
+            let (data, controller) = fixtures::controller(protocol);
+
+            assert_eq!((&data[..]).read_value::<Controller>(protocol).await?, controller, "protocol {}", protocol);
+
+            let mut buf = Vec::new();
+            assert_eq!(controller.size(protocol), data.len(), "protocol {}", protocol);
+            buf.write_value(controller, protocol).await?;
+            assert_eq!(buf, data, "protocol {}", protocol);
+        }
+
+        Ok(())
+    }
 }
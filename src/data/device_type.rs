@@ -1,78 +1,53 @@
-use std::mem::size_of;
+u32_enum! {
+    /// RGB controller device type.
+    ///
+    /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation) for more information.
+    #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
+    pub enum DeviceType {
+        /// Motherboard.
+        Motherboard = 0,
 
-use async_trait::async_trait;
-use num_traits::FromPrimitive;
+        /// DRAM.
+        DRAM = 1,
 
-use crate::data::{OpenRGBReadable, OpenRGBWritable};
-use crate::OpenRGBError;
-use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
+        /// GPU.
+        GPU = 2,
 
-/// RGB controller device type.
-///
-/// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation) for more information.
-#[derive(Primitive, Eq, PartialEq, Debug, Copy, Clone)]
-pub enum DeviceType {
-    /// Motherboard.
-    Motherboard = 0,
+        /// Cooler.
+        Cooler = 3,
 
-    /// DRAM.
-    DRAM = 1,
+        /// LED strip.
+        LEDStrip = 4,
 
-    /// GPU.
-    GPU = 2,
+        /// Keyboard.
+        Keyboard = 5,
 
-    /// Cooler.
-    Cooler = 3,
+        /// Mouse.
+        Mouse = 6,
 
-    /// LED strip.
-    LEDStrip = 4,
+        /// Mouse mat.
+        MouseMat = 7,
 
-    /// Keyboard.
-    Keyboard = 5,
+        /// Headset.
+        Headset = 8,
 
-    /// Mouse.
-    Mouse = 6,
+        /// Headset stand.
+        HeadsetStand = 9,
 
-    /// Mouse mat.
-    MouseMat = 7,
+        /// Gamepad.
+        Gamepad = 10,
 
-    /// Headset.
-    Headset = 8,
+        /// Light.
+        Light = 11,
 
-    /// Headset stand.
-    HeadsetStand = 9,
+        /// Speaker.
+        Speaker = 12,
 
-    /// Gamepad.
-    Gamepad = 10,
+        /// Virtual.
+        Virtual = 13,
 
-    /// Light.
-    Light = 11,
-
-    /// Speaker.
-    Speaker = 12,
-
-    /// Virtual.
-    Virtual = 13,
-
-    /// Unknown.
-    Unknown = 14,
-}
-
-#[async_trait]
-impl OpenRGBWritable for DeviceType {
-    fn size(&self, _protocol: u32) -> usize {
-        size_of::<u32>()
-    }
-
-    async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
-        stream.write_value(self as u32, protocol).await
-    }
-}
-
-#[async_trait]
-impl OpenRGBReadable for DeviceType {
-    async fn read(stream: &mut impl OpenRGBReadableStream, protocol: u32) -> Result<Self, OpenRGBError> {
-        Ok(DeviceType::from_u32(stream.read_value(protocol).await?).unwrap_or(DeviceType::Unknown))
+        /// Unknown.
+        Unknown = 14,
     }
 }
 
@@ -112,4 +87,30 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_read_unknown_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .read(&4242_u32.to_le_bytes())
+            .build();
+
+        assert_eq!(stream.read_value::<DeviceType>(DEFAULT_PROTOCOL).await?, DeviceType::Other(4242));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_unknown_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .write(&4242_u32.to_le_bytes())
+            .build();
+
+        stream.write_value(DeviceType::Other(4242), DEFAULT_PROTOCOL).await?;
+
+        Ok(())
+    }
 }
@@ -1,59 +1,25 @@
-use std::mem::size_of;
+u32_enum! {
+    /// Direction for [Mode](crate::data::Mode).
+    #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Default)]
+    pub enum Direction {
+        /// Left direction.
+        #[default]
+        Left = 0,
 
-use async_trait::async_trait;
-use num_traits::FromPrimitive;
+        /// Right direction.
+        Right = 1,
 
-use crate::data::{OpenRGBReadable, OpenRGBWritable};
-use crate::OpenRGBError;
-use crate::OpenRGBError::ProtocolError;
-use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
+        /// Up direction.
+        Up = 2,
 
-/// Direction for [Mode](crate::data::Mode).
-#[derive(Primitive, Eq, PartialEq, Debug, Copy, Clone)]
-pub enum Direction {
+        /// Down direction.
+        Down = 3,
 
-    /// Left direction.
-    Left = 0,
+        /// Horizontal direction.
+        Horizontal = 4,
 
-    /// Right direction.
-    Right = 1,
-
-    /// Up direction.
-    Up = 2,
-
-    /// Down direction.
-    Down = 3,
-
-    /// Horizontal direction.
-    Horizontal = 4,
-
-    /// Vertical direction.
-    Vertical = 5,
-}
-
-impl Default for Direction {
-    fn default() -> Self {
-        Direction::Left
-    }
-}
-
-#[async_trait]
-impl OpenRGBWritable for Direction {
-    fn size(&self, _protocol: u32) -> usize {
-        size_of::<u32>()
-    }
-
-    async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
-        stream.write_value(self as u32, protocol).await
-    }
-}
-
-#[async_trait]
-impl OpenRGBReadable for Direction {
-    async fn read(stream: &mut impl OpenRGBReadableStream, protocol: u32) -> Result<Self, OpenRGBError> {
-        stream.read_value(protocol)
-            .await
-            .and_then(|id| Direction::from_u32(id).ok_or_else(|| ProtocolError(format!("unknown direction \"{}\"", id))))
+        /// Vertical direction.
+        Vertical = 5,
     }
 }
 
@@ -93,4 +59,30 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_read_unknown_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .read(&4242_u32.to_le_bytes())
+            .build();
+
+        assert_eq!(stream.read_value::<Direction>(DEFAULT_PROTOCOL).await?, Direction::Other(4242));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_unknown_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .write(&4242_u32.to_le_bytes())
+            .build();
+
+        stream.write_value(Direction::Other(4242), DEFAULT_PROTOCOL).await?;
+
+        Ok(())
+    }
 }
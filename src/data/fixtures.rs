@@ -0,0 +1,139 @@
+//! Payload fixtures for each supported protocol version.
+
+use array2d::Array2D;
+
+use crate::data::{Color, ColorMode, Controller, DeviceType, Direction, LED, Mode, ModeFlag::*, Zone, ZoneType};
+
+/// Protocol versions covered by fixtures.
+pub(crate) const PROTOCOLS: [u32; 4] = [0, 1, 2, 3];
+
+/// Mode payload and its decoded value.
+pub(crate) fn mode(protocol: u32) -> (Vec<u8>, Mode) {
+    let mut data = [
+        &7_u16.to_le_bytes()[..], // name len
+        b"Breath\0", // name
+        &2_i32.to_le_bytes(), // value
+        &(HasSpeed | HasBrightness | HasDirection | HasModeSpecificColor).bits().to_le_bytes(), // flags
+        &1_u32.to_le_bytes(), // speed_min
+        &5_u32.to_le_bytes(), // speed_max
+    ].concat();
+    if protocol >= 3 {
+        data.extend_from_slice(&0_u32.to_le_bytes()); // brightness_min
+        data.extend_from_slice(&100_u32.to_le_bytes()); // brightness_max
+    }
+    data.extend_from_slice(&1_u32.to_le_bytes()); // colors_min
+    data.extend_from_slice(&2_u32.to_le_bytes()); // colors_max
+    data.extend_from_slice(&3_u32.to_le_bytes()); // speed
+    if protocol >= 3 {
+        data.extend_from_slice(&80_u32.to_le_bytes()); // brightness
+    }
+    data.extend_from_slice(&1_u32.to_le_bytes()); // direction
+    data.extend_from_slice(&2_u32.to_le_bytes()); // color_mode
+    data.extend_from_slice(&1_u16.to_le_bytes()); // colors len
+    data.extend_from_slice(&[255, 0, 0, 0]); // colors[0]
+
+    (data, Mode {
+        name: "Breath".to_string(),
+        value: 2,
+        flags: HasSpeed | HasBrightness | HasDirection | HasModeSpecificColor,
+        raw_flags: (HasSpeed | HasBrightness | HasDirection | HasModeSpecificColor).bits(),
+        speed_min: Some(1),
+        speed_max: Some(5),
+        brightness_min: if protocol >= 3 { Some(0) } else { None },
+        brightness_max: if protocol >= 3 { Some(100) } else { None },
+        colors_min: Some(1),
+        colors_max: Some(2),
+        speed: Some(3),
+        brightness: if protocol >= 3 { Some(80) } else { None },
+        direction: Some(Direction::Right),
+        color_mode: Some(ColorMode::ModeSpecific),
+        colors: vec![Color { r: 255, g: 0, b: 0 }],
+    })
+}
+
+/// Zone payload and its decoded value.
+pub(crate) fn zone(_protocol: u32) -> (Vec<u8>, Zone) {
+    let data = [
+        &5_u16.to_le_bytes()[..], // name len
+        b"Keys\0", // name
+        &2_u32.to_le_bytes(), // type
+        &2_u32.to_le_bytes(), // leds_min
+        &2_u32.to_le_bytes(), // leds_max
+        &2_u32.to_le_bytes(), // leds_count
+        &16_u16.to_le_bytes(), // matrix_len
+        &1_u32.to_le_bytes(), // matrix_height
+        &2_u32.to_le_bytes(), // matrix_width
+        &0_u32.to_le_bytes(), // matrix[0]
+        &1_u32.to_le_bytes(), // matrix[1]
+    ].concat();
+
+    (data, Zone {
+        name: "Keys".to_string(),
+        r#type: ZoneType::Matrix,
+        leds_min: 2,
+        leds_max: 2,
+        leds_count: 2,
+        matrix: Some(Array2D::from_rows(&[vec![0, 1]])),
+    })
+}
+
+/// Controller payload and its decoded value.
+pub(crate) fn controller(protocol: u32) -> (Vec<u8>, Controller) {
+    let (mode_data, mode) = mode(protocol);
+    let (zone_data, zone) = zone(protocol);
+
+    let mut data = [
+        &5_u32.to_le_bytes()[..], // type
+        &9_u16.to_le_bytes(), // name len
+        b"Keyboard\0", // name
+    ].concat();
+    if protocol >= 1 {
+        data.extend_from_slice(&5_u16.to_le_bytes()); // vendor len
+        data.extend_from_slice(b"ACME\0"); // vendor
+    }
+    data.extend_from_slice(&[
+        &5_u16.to_le_bytes()[..], // description len
+        b"Desc\0", // description
+        &4_u16.to_le_bytes(), // version len
+        b"1.0\0", // version
+        &1_u16.to_le_bytes(), // serial len
+        b"\0", // serial
+        &5_u16.to_le_bytes(), // location len
+        b"HID:\0", // location
+        &1_u16.to_le_bytes(), // num_modes
+        &0_i32.to_le_bytes(), // active_mode
+        &mode_data, // modes[0]
+        &1_u16.to_le_bytes(), // zones len
+        &zone_data, // zones[0]
+        &2_u16.to_le_bytes(), // leds len
+        &4_u16.to_le_bytes(), // leds[0].name len
+        b"Key\0", // leds[0].name
+        &4_u32.to_le_bytes(), // leds[0].value
+        &4_u16.to_le_bytes(), // leds[1].name len
+        b"Esc\0", // leds[1].name
+        &1_u32.to_le_bytes(), // leds[1].value
+        &2_u16.to_le_bytes(), // colors len
+        &[0, 255, 0, 0], // colors[0]
+        &[0, 0, 255, 0], // colors[1]
+    ].concat());
+    let data_size = (data.len() + 4) as u32;
+    let data = [&data_size.to_le_bytes()[..], &data].concat();
+
+    (data, Controller {
+        r#type: DeviceType::Keyboard,
+        name: "Keyboard".to_string(),
+        vendor: if protocol >= 1 { "ACME".to_string() } else { String::new() },
+        description: "Desc".to_string(),
+        version: "1.0".to_string(),
+        serial: "".to_string(),
+        location: "HID:".to_string(),
+        active_mode: 0,
+        modes: vec![mode],
+        zones: vec![zone],
+        leds: vec![
+            LED { name: "Key".to_string(), value: 4 },
+            LED { name: "Esc".to_string(), value: 1 },
+        ],
+        colors: vec![Color { r: 0, g: 255, b: 0 }, Color { r: 0, g: 0, b: 255 }],
+    })
+}
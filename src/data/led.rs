@@ -1,11 +1,11 @@
 use async_trait::async_trait;
 
-use crate::data::OpenRGBReadable;
+use crate::data::{OpenRGBReadable, OpenRGBWritable};
 use crate::OpenRGBError;
-use crate::protocol::OpenRGBReadableStream;
+use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
 
 /// A single LED.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct LED {
     /// LED name.
     pub name: String,
@@ -24,6 +24,18 @@ impl OpenRGBReadable for LED {
     }
 }
 
+#[async_trait]
+impl OpenRGBWritable for LED {
+    fn size(&self, protocol: u32) -> usize {
+        self.name.size(protocol) + self.value.size(protocol)
+    }
+
+    async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
+        stream.write_value(self.name, protocol).await?;
+        stream.write_value(self.value, protocol).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::error::Error;
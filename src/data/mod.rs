@@ -13,16 +13,113 @@ pub use mode::*;
 pub use mode_flag::*;
 #[doc(hidden)]
 pub use packet::*;
+#[allow(unused_imports)]
 pub use primitive::*;
 pub use string::*;
+#[allow(unused_imports)]
 pub use tuple::*;
+#[allow(unused_imports)]
 pub use vec::*;
 pub use zone::*;
 pub use zone_type::*;
+pub(crate) use vec::{read_n, read_vec};
 
 use crate::OpenRGBError;
 use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
 
+/// Declare a `u32` backed enum, with an `Other` variant preserving values unknown to this client.
+///
+/// Generates conversions from/to `u32`, variant names and protocol encoding: unknown values are decoded to `Other` and encoded back
+/// unchanged, so that data from newer servers goes through the client without loss.
+macro_rules! u32_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident = $value:literal,
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        pub enum $name {
+            $(
+                $(#[$variant_meta])*
+                $variant,
+            )*
+
+            /// Value unknown to this client.
+            Other(u32),
+        }
+
+        impl From<u32> for $name {
+            fn from(value: u32) -> Self {
+                match value {
+                    $($value => $name::$variant,)*
+                    value => $name::Other(value),
+                }
+            }
+        }
+
+        impl $name {
+            /// Variant name, eg: for logs or metrics labels, `"Other"` for values unknown to this client.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $($name::$variant => stringify!($variant),)*
+                    $name::Other(_) => "Other",
+                }
+            }
+        }
+
+        impl From<$name> for u32 {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => $value,)*
+                    $name::Other(value) => value,
+                }
+            }
+        }
+
+        impl num_traits::FromPrimitive for $name {
+            fn from_i64(n: i64) -> Option<Self> {
+                u32::try_from(n).ok().map(Self::from)
+            }
+
+            fn from_u64(n: u64) -> Option<Self> {
+                u32::try_from(n).ok().map(Self::from)
+            }
+        }
+
+        impl num_traits::ToPrimitive for $name {
+            fn to_i64(&self) -> Option<i64> {
+                Some(u32::from(*self).into())
+            }
+
+            fn to_u64(&self) -> Option<u64> {
+                Some(u32::from(*self).into())
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::data::OpenRGBWritable for $name {
+            fn size(&self, _protocol: u32) -> usize {
+                std::mem::size_of::<u32>()
+            }
+
+            async fn write(self, stream: &mut impl $crate::protocol::OpenRGBWritableStream, protocol: u32) -> Result<(), $crate::OpenRGBError> {
+                stream.write_value(u32::from(self), protocol).await
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::data::OpenRGBReadable for $name {
+            async fn read(stream: &mut impl $crate::protocol::OpenRGBReadableStream, protocol: u32) -> Result<Self, $crate::OpenRGBError> {
+                stream.read_value::<u32>(protocol).await.map(Self::from)
+            }
+        }
+    };
+}
+
 mod controller;
 mod direction;
 mod color_mode;
@@ -39,6 +136,9 @@ mod primitive;
 mod tuple;
 mod packet;
 
+#[cfg(test)]
+pub(crate) mod fixtures;
+
 #[async_trait]
 #[doc(hidden)]
 pub trait OpenRGBReadable: Sized + Send + Sync {
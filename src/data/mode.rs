@@ -1,15 +1,14 @@
 use async_trait::async_trait;
 use flagset::FlagSet;
-use num_traits::FromPrimitive;
 
-use crate::{OpenRGBError::{self, ProtocolError}};
-use crate::data::{Color, ColorMode, Direction, ModeFlag::{self, *}, OpenRGBReadable, OpenRGBWritable};
+use crate::{DecodeLimits, OpenRGBError};
+use crate::data::{Color, ColorMode, Direction, ModeFlag::{self, *}, OpenRGBReadable, OpenRGBWritable, read_vec};
 use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
 
 /// RGB controller mode.
 ///
 /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#mode-data) for more information.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Mode {
     /// Mode name.
     pub name: String,
@@ -20,6 +19,11 @@ pub struct Mode {
     /// Mode flags set.
     pub flags: FlagSet<ModeFlag>,
 
+    /// Raw mode flags, including bits unknown to this client that are not in [Mode::flags].
+    ///
+    /// Unknown bits are sent back unchanged, known bits are always taken from [Mode::flags].
+    pub raw_flags: u32,
+
     /// Mode minimum speed (if mode has [ModeFlag::HasSpeed] flag).
     pub speed_min: Option<u32>,
 
@@ -59,7 +63,8 @@ impl OpenRGBReadable for Mode {
     async fn read(stream: &mut impl OpenRGBReadableStream, protocol: u32) -> Result<Self, OpenRGBError> {
         let name = stream.read_value(protocol).await?;
         let value = stream.read_value(protocol).await?;
-        let flags = stream.read_value(protocol).await?;
+        let raw_flags = stream.read_value(protocol).await?;
+        let flags = FlagSet::<ModeFlag>::new_truncated(raw_flags);
         let speed_min = stream.read_value(protocol).await?;
         let speed_max = stream.read_value(protocol).await?;
         let brightness_min = if protocol >= 3 { Some(stream.read_value(protocol).await?) } else { None };
@@ -68,14 +73,15 @@ impl OpenRGBReadable for Mode {
         let colors_max = stream.read_value(protocol).await?;
         let speed = stream.read_value(protocol).await?;
         let brightness = if protocol >= 3 { Some(stream.read_value(protocol).await?) } else { None };
-        let direction = stream.read_value(protocol).await?;
+        let direction = stream.read_value::<Direction>(protocol).await?;
         let color_mode = stream.read_value(protocol).await?;
-        let colors = stream.read_value::<Vec<Color>>(protocol).await?;
+        let colors = read_vec::<Color>(stream, protocol, "mode colors", DecodeLimits::current().max_leds).await?;
 
         Ok(Mode {
             name,
             value,
             flags,
+            raw_flags,
             speed_min: if flags.contains(HasSpeed) { Some(speed_min) } else { None },
             speed_max: if flags.contains(HasSpeed) { Some(speed_max) } else { None },
             brightness_min: if flags.contains(HasBrightness) { brightness_min } else { None },
@@ -84,7 +90,7 @@ impl OpenRGBReadable for Mode {
             colors_max: if colors.is_empty() { None } else { Some(colors_max) },
             speed: if flags.contains(HasSpeed) { Some(speed) } else { None },
             brightness: if flags.contains(HasBrightness) { brightness } else { None },
-            direction: if flags.contains(HasDirection) { Some(Direction::from_u32(direction).ok_or_else(|| ProtocolError(format!("unknown direction \"{}\"", direction)))?) } else { None },
+            direction: if flags.contains(HasDirection) { Some(direction) } else { None },
             color_mode: Some(color_mode),
             colors,
         })
@@ -119,7 +125,7 @@ impl OpenRGBWritable for Mode {
     async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
         stream.write_value(self.name, protocol).await?;
         stream.write_value(self.value, protocol).await?;
-        stream.write_value(self.flags, protocol).await?;
+        stream.write_value(self.raw_flags & !FlagSet::<ModeFlag>::full().bits() | self.flags.bits(), protocol).await?;
         stream.write_value(self.speed_min.unwrap_or_default(), protocol).await?;
         stream.write_value(self.speed_max.unwrap_or_default(), protocol).await?;
         if protocol >= 3 {
@@ -145,7 +151,7 @@ mod tests {
 
     use tokio_test::io::Builder;
 
-    use crate::data::{Color, ColorMode, Direction, Mode, ModeFlag::*};
+    use crate::data::{fixtures, Color, ColorMode, Direction, Mode, ModeFlag::*, OpenRGBWritable};
     use crate::DEFAULT_PROTOCOL;
     use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
     use crate::tests::setup;
@@ -178,6 +184,7 @@ mod tests {
             name: "test".to_string(),
             value: 46,
             flags: HasDirection | HasSpeed | HasBrightness,
+            raw_flags: 31,
             speed_min: Some(10),
             speed_max: Some(1000),
             brightness_min: Some(1),
@@ -223,6 +230,7 @@ mod tests {
             name: "test".to_string(),
             value: 46,
             flags: Default::default(),
+            raw_flags: 0,
             speed_min: None,
             speed_max: None,
             brightness_min: None,
@@ -264,6 +272,7 @@ mod tests {
             name: "test".to_string(),
             value: 46,
             flags: HasDirection | HasSpeed | HasBrightness,
+            raw_flags: 31,
             speed_min: Some(10),
             speed_max: Some(1000),
             brightness_min: None,
@@ -311,6 +320,7 @@ mod tests {
             name: "test".to_string(),
             value: 46,
             flags: HasDirection | HasSpeed | HasBrightness,
+            raw_flags: 31,
             speed_min: Some(10),
             speed_max: Some(1000),
             brightness_min: Some(1),
@@ -329,4 +339,48 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_unknown_flags_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (mut data, _) = fixtures::mode(DEFAULT_PROTOCOL);
+        let flags_offset = 2 + "Breath\0".len() + 4;
+        let flags = u32::from_le_bytes(data[flags_offset..flags_offset + 4].try_into()?);
+        data[flags_offset..flags_offset + 4].copy_from_slice(&(flags | 1 << 31).to_le_bytes());
+
+        let mut mode = (&data[..]).read_value::<Mode>(DEFAULT_PROTOCOL).await?;
+        assert_eq!(mode.flags.bits(), flags);
+        assert_eq!(mode.raw_flags, flags | 1 << 31);
+
+        let mut buf = Vec::new();
+        buf.write_value(mode.clone(), DEFAULT_PROTOCOL).await?;
+        assert_eq!(buf, data);
+
+        // known flags are taken from flags set
+        mode.flags |= HasSpeed;
+        let mut buf = Vec::new();
+        buf.write_value(mode, DEFAULT_PROTOCOL).await?;
+        assert_eq!(buf[flags_offset..flags_offset + 4], (flags | 1 << 31 | 1).to_le_bytes());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_protocol_versions_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        for protocol in fixtures::PROTOCOLS {
+            let (data, mode) = fixtures::mode(protocol);
+
+            assert_eq!((&data[..]).read_value::<Mode>(protocol).await?, mode, "protocol {}", protocol);
+
+            let mut buf = Vec::new();
+            assert_eq!(mode.size(protocol), data.len(), "protocol {}", protocol);
+            buf.write_value(mode, protocol).await?;
+            assert_eq!(buf, data, "protocol {}", protocol);
+        }
+
+        Ok(())
+    }
 }
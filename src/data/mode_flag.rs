@@ -5,7 +5,7 @@ use flagset::{flags, FlagSet};
 
 use crate::data::{OpenRGBReadable, OpenRGBWritable};
 use crate::OpenRGBError;
-use crate::OpenRGBError::ProtocolError;
+use crate::OpenRGBError::UnknownEnumValue;
 use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
 
 flags! {
@@ -63,7 +63,7 @@ impl OpenRGBWritable for FlagSet<ModeFlag> {
 impl OpenRGBReadable for FlagSet<ModeFlag> {
     async fn read(stream: &mut impl OpenRGBReadableStream, protocol: u32) -> Result<Self, OpenRGBError> {
         let value = stream.read_value(protocol).await?;
-        FlagSet::<ModeFlag>::new(value).map_err(|e| ProtocolError(format!("Received invalid mode flag set: {} ({})", value, e)))
+        FlagSet::<ModeFlag>::new(value).map_err(|_| UnknownEnumValue { kind: "mode flags".to_owned(), value })
     }
 }
 
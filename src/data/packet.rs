@@ -1,83 +1,56 @@
-use std::mem::size_of;
+u32_enum! {
+    /// OpenRGB protocol packet ID.
+    ///
+    /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#packet-ids) for more information.
+    #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
+    pub enum PacketId {
+        /// Request RGBController device count from server.
+        RequestControllerCount = 0,
 
-use async_trait::async_trait;
-use num_traits::FromPrimitive;
+        /// Request RGBController data block.
+        RequestControllerData = 1,
 
-use crate::data::{OpenRGBReadable, OpenRGBWritable};
-use crate::OpenRGBError::{self, ProtocolError};
-use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
+        /// Request OpenRGB SDK protocol version from server.
+        RequestProtocolVersion = 40,
 
-/// OpenRGB protocol packet ID.
-///
-/// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#packet-ids) for more information.
-#[derive(Primitive, PartialEq, Debug, Copy, Clone)]
-pub enum PacketId {
-    /// Request RGBController device count from server.
-    RequestControllerCount = 0,
+        /// Send client name string to server.
+        SetClientName = 50,
 
-    /// Request RGBController data block.
-    RequestControllerData = 1,
+        /// Indicate to clients that device list has updated.
+        DeviceListUpdated = 100,
 
-    /// Request OpenRGB SDK protocol version from server.
-    RequestProtocolVersion = 40,
+        /// Request profile list.
+        RequestProfileList = 150,
 
-    /// Send client name string to server.
-    SetClientName = 50,
+        /// Save current configuration in a new profile.
+        RequestSaveProfile = 151,
 
-    /// Indicate to clients that device list has updated.
-    DeviceListUpdated = 100,
+        /// Load a given profile.
+        RequestLoadProfile = 152,
 
-    /// Request profile list.
-    RequestProfileList = 150,
+        /// Delete a given profile.
+        RequestDeleteProfile = 153,
 
-    /// Save current configuration in a new profile.
-    RequestSaveProfile = 151,
+        /// RGBController::ResizeZone().
+        RGBControllerResizeZone = 1000,
 
-    /// Load a given profile.
-    RequestLoadProfile = 152,
+        /// RGBController::UpdateLEDs().
+        RGBControllerUpdateLeds = 1050,
 
-    /// Delete a given profile.
-    RequestDeleteProfile = 153,
+        /// RGBController::UpdateZoneLEDs().
+        RGBControllerUpdateZoneLeds = 1051,
 
-    /// RGBController::ResizeZone().
-    RGBControllerResizeZone = 1000,
+        /// RGBController::UpdateSingleLED().
+        RGBControllerUpdateSingleLed = 1052,
 
-    /// RGBController::UpdateLEDs().
-    RGBControllerUpdateLeds = 1050,
+        /// RGBController::SetCustomMode().
+        RGBControllerSetCustomMode = 1100,
 
-    /// RGBController::UpdateZoneLEDs().
-    RGBControllerUpdateZoneLeds = 1051,
+        /// RGBController::UpdateMode().
+        RGBControllerUpdateMode = 1101,
 
-    /// RGBController::UpdateSingleLED().
-    RGBControllerUpdateSingleLed = 1052,
-
-    /// RGBController::SetCustomMode().
-    RGBControllerSetCustomMode = 1100,
-
-    /// RGBController::UpdateMode().
-    RGBControllerUpdateMode = 1101,
-
-    /// RGBController::SaveMode().
-    RGBControllerSaveMode = 1102,
-}
-
-#[async_trait]
-impl OpenRGBWritable for PacketId {
-    fn size(&self, _protocol: u32) -> usize {
-        size_of::<u32>()
-    }
-
-    async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
-        stream.write_value(self as u32, protocol).await
-    }
-}
-
-#[async_trait]
-impl OpenRGBReadable for PacketId {
-    async fn read(stream: &mut impl OpenRGBReadableStream, protocol: u32) -> Result<Self, OpenRGBError> {
-        stream.read_value::<u32>(protocol)
-            .await
-            .and_then(|id| PacketId::from_u32(id).ok_or_else(|| ProtocolError(format!("unknown packed ID \"{}\"", id))))
+        /// RGBController::SaveMode().
+        RGBControllerSaveMode = 1102,
     }
 }
 
@@ -129,4 +102,36 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_read_unknown_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .read(&4242_u32.to_le_bytes())
+            .build();
+
+        assert_eq!(stream.read_value::<PacketId>(DEFAULT_PROTOCOL).await?, PacketId::Other(4242));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_unknown_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .write(&4242_u32.to_le_bytes())
+            .build();
+
+        stream.write_value(PacketId::Other(4242), DEFAULT_PROTOCOL).await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_name() {
+        assert_eq!(PacketId::RGBControllerUpdateLeds.name(), "RGBControllerUpdateLeds");
+        assert_eq!(PacketId::Other(4242).name(), "Other");
+    }
 }
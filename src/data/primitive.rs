@@ -4,7 +4,7 @@ use async_trait::async_trait;
 
 use crate::data::{OpenRGBReadable, OpenRGBWritable};
 use crate::OpenRGBError;
-use crate::OpenRGBError::ProtocolError;
+use crate::OpenRGBError::TooLargeToEncode;
 use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
 
 #[async_trait]
@@ -106,7 +106,7 @@ impl OpenRGBWritable for usize {
     async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
         stream.write_value(
             u32::try_from(self)
-                .map_err(|e| ProtocolError(format!("Data size is too large to encode: {} ({})", self, e)))?,
+                .map_err(|_| TooLargeToEncode { what: "data size".to_owned(), size: self })?,
             protocol,
         ).await
     }
@@ -3,8 +3,8 @@ use std::mem::size_of;
 use async_trait::async_trait;
 
 use crate::data::{OpenRGBReadable, OpenRGBWritable};
-use crate::OpenRGBError;
-use crate::OpenRGBError::ProtocolError;
+use crate::{DecodeLimits, limits, OpenRGBError};
+use crate::OpenRGBError::InvalidString;
 use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
 
 // FIXME buggy for non ASCII strings
@@ -24,14 +24,16 @@ impl OpenRGBWritable for String {
 #[async_trait]
 impl OpenRGBReadable for String {
     async fn read(stream: &mut impl OpenRGBReadableStream, protocol: u32) -> Result<Self, OpenRGBError> {
-        let mut buf = vec![Default::default(); stream.read_value::<u16>(protocol).await? as usize];
+        let len = limits::check("string", stream.read_value::<u16>(protocol).await? as usize, DecodeLimits::current().max_string_len)?;
+        let mut buf = vec![Default::default(); len];
         stream.read_exact(&mut buf).await?;
         buf.pop();
-        String::from_utf8(buf).map_err(|e| ProtocolError(format!("Failed decoding string as UTF-8: {}", e)))
+        String::from_utf8(buf).map_err(|source| InvalidString { source })
     }
 }
 
 #[doc(hidden)]
+#[derive(Debug, Eq, PartialEq)]
 pub struct RawString(pub String);
 
 #[async_trait]
@@ -45,6 +47,24 @@ impl OpenRGBWritable for RawString {
     }
 }
 
+#[async_trait]
+impl OpenRGBReadable for RawString {
+    async fn read(stream: &mut impl OpenRGBReadableStream, _protocol: u32) -> Result<Self, OpenRGBError> {
+        let max_len = DecodeLimits::current().max_string_len;
+        let mut buf = Vec::new();
+        loop {
+            match stream.read_u8().await? {
+                0 => break,
+                c => buf.push(c),
+            }
+            limits::check("string", buf.len(), max_len)?;
+        }
+        String::from_utf8(buf)
+            .map(RawString)
+            .map_err(|source| InvalidString { source })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::error::Error;
@@ -52,7 +72,8 @@ mod tests {
     use tokio_test::io::Builder;
 
     use crate::data::RawString;
-    use crate::DEFAULT_PROTOCOL;
+    use crate::{DecodeLimits, DEFAULT_PROTOCOL};
+    use crate::OpenRGBError::DecodeLimitExceeded;
     use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
     use crate::tests::setup;
 
@@ -70,6 +91,23 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_read_002() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .read(&5_u16.to_le_bytes())
+            .build();
+
+        let limits = DecodeLimits { max_string_len: 4, ..Default::default() };
+        assert!(matches!(
+            limits.apply(stream.read_value::<String>(DEFAULT_PROTOCOL)).await,
+            Err(DecodeLimitExceeded { value: 5, limit: 4, .. })
+        ));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_write_001() -> Result<(), Box<dyn Error>> {
         setup()?;
@@ -84,6 +122,19 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_read_raw_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .read(b"test\0")
+            .build();
+
+        assert_eq!(stream.read_value::<RawString>(DEFAULT_PROTOCOL).await?, RawString("test".to_string()));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_write_raw_001() -> Result<(), Box<dyn Error>> {
         setup()?;
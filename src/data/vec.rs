@@ -3,8 +3,8 @@ use std::mem::size_of;
 use async_trait::async_trait;
 
 use crate::data::{OpenRGBReadable, OpenRGBWritable};
-use crate::OpenRGBError;
-use crate::OpenRGBError::ProtocolError;
+use crate::{DecodeLimits, limits, OpenRGBError};
+use crate::OpenRGBError::TooLargeToEncode;
 use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
 
 #[async_trait]
@@ -14,7 +14,7 @@ impl<T: OpenRGBWritable> OpenRGBWritable for Vec<T> {
     }
 
     async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
-        stream.write_value(u16::try_from(self.len()).map_err(|e| ProtocolError(format!("Vec is too large to encode: {}", e)))?, protocol).await?;
+        stream.write_value(u16::try_from(self.len()).map_err(|_| TooLargeToEncode { what: "list".to_owned(), size: self.len() })?, protocol).await?;
         for elem in self {
             stream.write_value(elem, protocol).await?;
         }
@@ -25,13 +25,24 @@ impl<T: OpenRGBWritable> OpenRGBWritable for Vec<T> {
 #[async_trait]
 impl<T: OpenRGBReadable> OpenRGBReadable for Vec<T> {
     async fn read(stream: &mut impl OpenRGBReadableStream, protocol: u32) -> Result<Self, OpenRGBError> {
-        let len = stream.read_value::<u16>(protocol).await? as usize;
-        let mut vec = Vec::with_capacity(len);
-        for _ in 0..len {
-            vec.push(stream.read_value(protocol).await?);
-        }
-        Ok(vec)
+        read_vec(stream, protocol, "list", DecodeLimits::current().max_list_len).await
+    }
+}
+
+/// Read a `u16` length prefixed list of `what`, checking its length against `limit`.
+pub(crate) async fn read_vec<T: OpenRGBReadable>(stream: &mut impl OpenRGBReadableStream, protocol: u32, what: &str, limit: usize) -> Result<Vec<T>, OpenRGBError> {
+    let len = stream.read_value::<u16>(protocol).await? as usize;
+    read_n(stream, protocol, what, len, limit).await
+}
+
+/// Read `len` elements of `what`, checking `len` against `limit`.
+pub(crate) async fn read_n<T: OpenRGBReadable>(stream: &mut impl OpenRGBReadableStream, protocol: u32, what: &str, len: usize, limit: usize) -> Result<Vec<T>, OpenRGBError> {
+    let len = limits::check(what, len, limit)?;
+    let mut vec = Vec::with_capacity(len);
+    for _ in 0..len {
+        vec.push(stream.read_value(protocol).await?);
     }
+    Ok(vec)
 }
 
 #[cfg(test)]
@@ -40,7 +51,8 @@ mod tests {
 
     use tokio_test::io::Builder;
 
-    use crate::{DEFAULT_PROTOCOL};
+    use crate::DEFAULT_PROTOCOL;
+    use crate::OpenRGBError::DecodeLimitExceeded;
     use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
     use crate::tests::setup;
 
@@ -58,6 +70,22 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_read_002() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .read(&65535_u16.to_le_bytes())
+            .build();
+
+        assert!(matches!(
+            stream.read_value::<Vec<u8>>(DEFAULT_PROTOCOL).await,
+            Err(DecodeLimitExceeded { value: 65535, limit: 4096, .. })
+        ));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_write_001() -> Result<(), Box<dyn Error>> {
         setup()?;
@@ -1,14 +1,17 @@
+use std::mem::size_of;
+
 use array2d::Array2D;
 use async_trait::async_trait;
 
-use crate::data::{OpenRGBReadable, ZoneType};
-use crate::OpenRGBError;
-use crate::protocol::OpenRGBReadableStream;
+use crate::data::{OpenRGBReadable, OpenRGBWritable, read_n, ZoneType};
+use crate::{DecodeLimits, OpenRGBError};
+use crate::OpenRGBError::{InvalidZoneMatrix, TooLargeToEncode};
+use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
 
 /// RGB controller zone.
 ///
 /// See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#zone-data) for more information.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Zone {
     /// Zone name.
     pub name: String,
@@ -43,11 +46,10 @@ impl OpenRGBReadable for Zone {
             _ => Some({
                 let matrix_height = stream.read_value::<u32>(protocol).await? as usize;
                 let matrix_width = stream.read_value::<u32>(protocol).await? as usize;
-                let matrix_size = matrix_height * matrix_width;
-                let mut matrix_data = Vec::with_capacity(matrix_size);
-                for _ in 0..matrix_size {
-                    matrix_data.push(stream.read_value(protocol).await?);
-                }
+                let matrix_size = matrix_height.checked_mul(matrix_width)
+                    .filter(|size| size.checked_mul(size_of::<u32>()).and_then(|s| s.checked_add(2 * size_of::<u32>())) == Some(matrix_len))
+                    .ok_or(InvalidZoneMatrix { height: matrix_height, width: matrix_width, len: matrix_len })?;
+                let matrix_data = read_n(stream, protocol, "zone matrix", matrix_size, DecodeLimits::current().max_matrix_size).await?;
                 Array2D::from_row_major(&matrix_data, matrix_height, matrix_width)
             })
         };
@@ -62,6 +64,47 @@ impl OpenRGBReadable for Zone {
     }
 }
 
+#[async_trait]
+impl OpenRGBWritable for Zone {
+    fn size(&self, protocol: u32) -> usize {
+        let mut size = 0;
+        size += self.name.size(protocol);
+        size += self.r#type.size(protocol);
+        size += self.leds_min.size(protocol);
+        size += self.leds_max.size(protocol);
+        size += self.leds_count.size(protocol);
+        size += size_of::<u16>();
+        size += self.matrix.as_ref().map_or(0, matrix_len);
+        size
+    }
+
+    async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
+        stream.write_value(self.name, protocol).await?;
+        stream.write_value(self.r#type, protocol).await?;
+        stream.write_value(self.leds_min, protocol).await?;
+        stream.write_value(self.leds_max, protocol).await?;
+        stream.write_value(self.leds_count, protocol).await?;
+        match self.matrix {
+            None => stream.write_value(0_u16, protocol).await?,
+            Some(matrix) => {
+                let size = matrix_len(&matrix);
+                let len = u16::try_from(size).map_err(|_| TooLargeToEncode { what: "zone matrix".to_owned(), size })?;
+                stream.write_value(len, protocol).await?;
+                stream.write_value(matrix.num_rows() as u32, protocol).await?;
+                stream.write_value(matrix.num_columns() as u32, protocol).await?;
+                for value in matrix.elements_row_major_iter() {
+                    stream.write_value(*value, protocol).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn matrix_len(matrix: &Array2D<u32>) -> usize {
+    (2 + matrix.num_elements()) * size_of::<u32>()
+}
+
 #[cfg(test)]
 mod tests {
     use std::error::Error;
@@ -69,9 +112,10 @@ mod tests {
     use array2d::Array2D;
     use tokio_test::io::Builder;
 
-    use crate::data::{Zone, ZoneType};
-    use crate::DEFAULT_PROTOCOL;
-    use crate::protocol::OpenRGBReadableStream;
+    use crate::data::{fixtures, OpenRGBWritable, Zone, ZoneType};
+    use crate::{DecodeLimits, DEFAULT_PROTOCOL};
+    use crate::OpenRGBError::{DecodeLimitExceeded, InvalidZoneMatrix};
+    use crate::protocol::{OpenRGBReadableStream, OpenRGBWritableStream};
     use crate::tests::setup;
 
     #[tokio::test]
@@ -133,4 +177,68 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_read_003() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .read(&5_u16.to_le_bytes()) // name len
+            .read(b"test\0") // name
+            .read(&2_u32.to_le_bytes()) // type
+            .read(&3_u32.to_le_bytes()) // leds_min
+            .read(&18_u32.to_le_bytes()) // leds_max
+            .read(&15_u32.to_le_bytes()) // leds_count
+            .read(&32_u16.to_le_bytes()) // matrix_len
+            .read(&u32::MAX.to_le_bytes()) // matrix_height
+            .read(&u32::MAX.to_le_bytes()) // matrix_width
+            .build();
+
+        assert!(matches!(stream.read_value::<Zone>(DEFAULT_PROTOCOL).await, Err(InvalidZoneMatrix { len: 32, .. })));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_004() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut stream = Builder::new()
+            .read(&5_u16.to_le_bytes()) // name len
+            .read(b"test\0") // name
+            .read(&2_u32.to_le_bytes()) // type
+            .read(&3_u32.to_le_bytes()) // leds_min
+            .read(&18_u32.to_le_bytes()) // leds_max
+            .read(&15_u32.to_le_bytes()) // leds_count
+            .read(&32_u16.to_le_bytes()) // matrix_len
+            .read(&2_u32.to_le_bytes()) // matrix_height
+            .read(&3_u32.to_le_bytes()) // matrix_width
+            .build();
+
+        let limits = DecodeLimits { max_matrix_size: 4, ..Default::default() };
+        assert!(matches!(
+            limits.apply(stream.read_value::<Zone>(DEFAULT_PROTOCOL)).await,
+            Err(DecodeLimitExceeded { value: 6, limit: 4, .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_protocol_versions_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        for protocol in fixtures::PROTOCOLS {
+            let (data, zone) = fixtures::zone(protocol);
+
+            assert_eq!((&data[..]).read_value::<Zone>(protocol).await?, zone, "protocol {}", protocol);
+
+            let mut buf = Vec::new();
+            assert_eq!(zone.size(protocol), data.len(), "protocol {}", protocol);
+            buf.write_value(zone, protocol).await?;
+            assert_eq!(buf, data, "protocol {}", protocol);
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,818 @@
+//! Offline OpenRGB packet dissector.
+//!
+//! Decodes OpenRGB packets from raw captures, which can be:
+//! * [pcap](https://wiki.wireshark.org/Development/LibpcapFileFormat) or
+//!   [pcapng](https://pcapng.com) files, TCP streams to the server port are reassembled,
+//! * [session recordings](crate::session),
+//! * hex dumps of a stream, either plain or as output by `xxd` or `hexdump -C`.
+//!
+//! See `openrgb-dissect` binary for a command line interface.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use openrgb::dissect::Dissector;
+//! use std::error::Error;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn Error>> {
+//!     let capture = std::fs::read("capture.pcapng")?;
+//!     for packet in Dissector::new().dissect(&capture).await? {
+//!         println!("{}", packet);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::data::PacketId;
+use crate::DecodeLimits;
+use crate::message::{Request, Response};
+use crate::OpenRGBError;
+use crate::OpenRGBError::InvalidCapture;
+use crate::protocol::HEADER_LEN;
+use crate::session::{from_hex, Origin, Recording};
+use crate::{DEFAULT_ADDR, DEFAULT_PROTOCOL};
+
+/// Maximum size of data in a hex dump, once repeated lines (`*`) are expanded.
+const MAX_HEX_DUMP_LEN: usize = 64 * 1024 * 1024;
+
+/// A dissected OpenRGB packet.
+#[derive(Debug, Clone)]
+pub struct Packet {
+    /// Capture timestamp, if known.
+    pub timestamp: Option<Duration>,
+
+    /// Client and server addresses, if known.
+    pub addrs: Option<(SocketAddr, SocketAddr)>,
+
+    /// Packet origin, if known (else it is guessed from packet content).
+    pub origin: Option<Origin>,
+
+    /// Device ID from packet header.
+    pub device_id: u32,
+
+    /// Packet ID from packet header.
+    pub packet_id: u32,
+
+    /// Payload length from packet header.
+    pub length: u32,
+
+    /// Protocol version used to decode packet.
+    pub protocol: u32,
+
+    /// Decoded payload.
+    pub payload: Payload,
+}
+
+/// Dissected packet payload.
+#[derive(Debug, Clone)]
+pub enum Payload {
+    /// Payload decoded as a request.
+    Request(Request),
+
+    /// Payload decoded as a response.
+    Response(Response),
+
+    /// Payload that could not be decoded.
+    Raw {
+        /// Payload bytes.
+        data: Vec<u8>,
+
+        /// Decoding error.
+        error: String,
+    },
+}
+
+impl Display for Packet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(timestamp) = self.timestamp {
+            write!(f, "[{}.{:06}] ", timestamp.as_secs(), timestamp.subsec_micros())?;
+        }
+        if let Some((client, server)) = self.addrs {
+            match self.origin {
+                Some(Origin::Server) => write!(f, "{} > {} ", server, client)?,
+                _ => write!(f, "{} > {} ", client, server)?,
+            }
+        } else if let Some(origin) = self.origin {
+            write!(f, "{:?} ", origin)?;
+        }
+        match PacketId::from(self.packet_id) {
+            PacketId::Other(packet_id) => write!(f, "packet {}", packet_id)?,
+            packet_id => write!(f, "{:?}", packet_id)?,
+        }
+        writeln!(f, " device={} protocol={} length={}", self.device_id, self.protocol, self.length)?;
+        match &self.payload {
+            Payload::Request(request) => write!(f, "{:#?}", request),
+            Payload::Response(response) => write!(f, "{:#?}", response),
+            Payload::Raw { data, error } => write!(f, "undecoded ({}): {}", error, crate::session::to_hex(data)),
+        }
+    }
+}
+
+/// OpenRGB packet dissector.
+#[derive(Debug, Clone)]
+pub struct Dissector {
+    port: u16,
+    protocol: u32,
+    limits: DecodeLimits,
+}
+
+impl Default for Dissector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dissector {
+    /// Build a new dissector with default options.
+    pub fn new() -> Self {
+        Self {
+            port: DEFAULT_ADDR.1,
+            protocol: DEFAULT_PROTOCOL,
+            limits: DecodeLimits::default(),
+        }
+    }
+
+    /// Set OpenRGB server TCP port, used to filter pcap captures (default: 6742).
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Set protocol version assumed until a protocol negotiation is seen (default: [DEFAULT_PROTOCOL]).
+    pub fn protocol(mut self, protocol: u32) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Set limits enforced on dissected packets (default: [DecodeLimits::default]).
+    ///
+    /// Packets declaring a payload larger than [DecodeLimits::max_packet_size] are reported undecoded, and the stream is
+    /// resynchronized on the next packet magic value.
+    pub fn decode_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Dissect a capture, detecting its format.
+    pub async fn dissect(&self, capture: &[u8]) -> Result<Vec<Packet>, OpenRGBError> {
+        if capture.len() >= 4 {
+            let magic = [capture[0], capture[1], capture[2], capture[3]];
+            if PCAP_MAGICS.contains(&magic) || magic == PCAPNG_MAGIC {
+                return self.dissect_pcap(capture).await;
+            }
+        }
+        let text = std::str::from_utf8(capture).map_err(|_| InvalidCapture { reason: "unrecognized capture format".to_owned() })?;
+        match Recording::parse(text) {
+            Ok(recording) if !recording.records.is_empty() => self.dissect_recording(&recording).await,
+            _ => self.dissect_hex_dump(text).await,
+        }
+    }
+
+    /// Dissect a pcap or pcapng capture.
+    pub async fn dissect_pcap(&self, capture: &[u8]) -> Result<Vec<Packet>, OpenRGBError> {
+        let mut connections: HashMap<(SocketAddr, SocketAddr), Connection> = HashMap::new();
+        let mut packets = Vec::new();
+        for frame in read_pcap(capture)? {
+            let segment = match parse_frame(frame.link_type, frame.data) {
+                Some(segment) => segment,
+                None => continue,
+            };
+            let (origin, key) = if segment.dst.port() == self.port {
+                (Origin::Client, (segment.src, segment.dst))
+            } else if segment.src.port() == self.port {
+                (Origin::Server, (segment.dst, segment.src))
+            } else {
+                continue;
+            };
+            let connection = connections.entry(key).or_insert_with(|| Connection::new(self.protocol, self.limits));
+            let data = match origin {
+                Origin::Client => connection.client.push(segment.seq, segment.syn, segment.payload),
+                Origin::Server => connection.server.push(segment.seq, segment.syn, segment.payload),
+            };
+            for mut packet in connection.feed(Some(origin), &data).await {
+                packet.timestamp = Some(frame.timestamp);
+                packet.addrs = Some(key);
+                packets.push(packet);
+            }
+        }
+        Ok(packets)
+    }
+
+    /// Dissect a [session recording](crate::session::Recording).
+    pub async fn dissect_recording(&self, recording: &Recording) -> Result<Vec<Packet>, OpenRGBError> {
+        let mut connection = Connection::new(self.protocol, self.limits);
+        let mut packets = Vec::new();
+        for record in &recording.records {
+            for mut packet in connection.feed(Some(record.origin), &record.data).await {
+                packet.timestamp = Some(record.elapsed);
+                packets.push(packet);
+            }
+        }
+        Ok(packets)
+    }
+
+    /// Dissect a hex dump of an OpenRGB stream.
+    ///
+    /// Packets origin is guessed from their content.
+    pub async fn dissect_hex_dump(&self, text: &str) -> Result<Vec<Packet>, OpenRGBError> {
+        let data = parse_hex_dump(text)?;
+        Ok(Connection::new(self.protocol, self.limits).feed(None, &data).await)
+    }
+}
+
+/// Parse a hex dump, either plain or as output by `xxd` or `hexdump -C`.
+///
+/// Repeated lines collapsed to `*` are expanded up to the offset of the next line.
+pub fn parse_hex_dump(text: &str) -> Result<Vec<u8>, OpenRGBError> {
+    let mut data = Vec::new();
+    let mut offsets = false;
+    let mut first_offset = None;
+    let mut previous = Vec::new();
+    let mut repeated = false;
+    for (n, line) in text.lines().enumerate() {
+        let invalid = || InvalidCapture { reason: format!("invalid hex dump at line {}", n + 1) };
+        let mut line = line.split('|').next().unwrap_or_default().trim_end();
+        if line.trim_start() == "*" {
+            repeated = true;
+            continue;
+        }
+        let mut offset = None;
+        if offsets && line.len() >= 4 && line.chars().all(|c| c.is_ascii_hexdigit()) {
+            // hexdump -C: trailing line with total length offset only
+            offset = Some(line);
+            line = "";
+        } else if let Some((prefix, rest)) = line.split_once(':') {
+            // xxd: offset, colon, hex groups, then ASCII column after two spaces
+            if !prefix.is_empty() && prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+                offset = Some(prefix);
+                line = rest.trim_start().split("  ").next().unwrap_or_default();
+            }
+        } else if let Some((prefix, rest)) = line.split_once("  ") {
+            // hexdump -C: offset, two spaces, hex bytes
+            if prefix.len() >= 4 && prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+                offset = Some(prefix);
+                line = rest;
+                offsets = true;
+            }
+        }
+        if let Some(offset) = offset {
+            let offset = usize::from_str_radix(offset, 16).map_err(|_| invalid())?;
+            let end = offset.checked_sub(*first_offset.get_or_insert(offset)).ok_or_else(invalid)?;
+            if repeated {
+                if end > MAX_HEX_DUMP_LEN || previous.is_empty() || end < data.len() || (end - data.len()) % previous.len() != 0 {
+                    return Err(invalid());
+                }
+                while data.len() < end {
+                    data.extend_from_slice(&previous);
+                }
+            }
+        }
+        repeated = false;
+        let hex = line
+            .split_whitespace()
+            .map(|token| token.trim_start_matches("0x"))
+            .collect::<String>();
+        previous = from_hex(&hex).ok_or_else(invalid)?;
+        data.extend_from_slice(&previous);
+    }
+    Ok(data)
+}
+
+struct Connection {
+    protocol: u32,
+    limits: DecodeLimits,
+    client_protocol: Option<u32>,
+    client: TcpStream,
+    server: TcpStream,
+    unknown: Vec<u8>,
+}
+
+impl Connection {
+    fn new(protocol: u32, limits: DecodeLimits) -> Self {
+        Self {
+            protocol,
+            limits,
+            client_protocol: None,
+            client: TcpStream::default(),
+            server: TcpStream::default(),
+            unknown: Vec::new(),
+        }
+    }
+
+    /// Feed stream data, returning the packets it completed.
+    async fn feed(&mut self, origin: Option<Origin>, data: &[u8]) -> Vec<Packet> {
+        let mut buf = std::mem::take(match origin {
+            Some(Origin::Client) => &mut self.client.buffer,
+            Some(Origin::Server) => &mut self.server.buffer,
+            None => &mut self.unknown,
+        });
+        buf.extend_from_slice(data);
+
+        let mut packets = Vec::new();
+        loop {
+            // resynchronize on next magic value if stream does not start with one
+            match buf.windows(4).position(|w| w == b"ORGB") {
+                Some(0) => {}
+                Some(pos) => {
+                    buf.drain(..pos);
+                }
+                None => {
+                    buf.drain(..buf.len().saturating_sub(3));
+                    break;
+                }
+            }
+            if buf.len() < HEADER_LEN {
+                break;
+            }
+            let field = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+            let (device_id, packet_id, length) = (field(4), field(8), field(12));
+            if length as usize > self.limits.max_packet_size {
+                let error = format!("payload length exceeds limit of {} bytes, skipping header", self.limits.max_packet_size);
+                packets.push(Packet {
+                    timestamp: None,
+                    addrs: None,
+                    origin,
+                    device_id,
+                    packet_id,
+                    length,
+                    protocol: self.protocol,
+                    payload: Payload::Raw { data: Vec::new(), error },
+                });
+                // resynchronize on next magic value
+                buf.drain(..4);
+                continue;
+            }
+            if buf.len() < HEADER_LEN + length as usize {
+                break;
+            }
+            let payload = buf.drain(..HEADER_LEN + length as usize).skip(HEADER_LEN).collect::<Vec<_>>();
+            packets.push(self.decode(origin, device_id, packet_id, length, payload).await);
+        }
+
+        match origin {
+            Some(Origin::Client) => self.client.buffer = buf,
+            Some(Origin::Server) => self.server.buffer = buf,
+            None => self.unknown = buf,
+        }
+        packets
+    }
+
+    async fn decode(&mut self, origin: Option<Origin>, device_id: u32, packet_id: u32, length: u32, data: Vec<u8>) -> Packet {
+        let protocol = self.protocol;
+        let payload = match PacketId::from(packet_id) {
+            PacketId::Other(_) => Payload::Raw { data, error: format!("unknown packet ID {}", packet_id) },
+            id => {
+                let result = match origin.unwrap_or_else(|| self.guess_origin(id, &data)) {
+                    Origin::Client => Request::decode(device_id, id, &data, protocol).await.map(Payload::Request),
+                    Origin::Server => Response::decode(id, &data, protocol).await.map(Payload::Response),
+                };
+                result.unwrap_or_else(|e| Payload::Raw { data, error: e.to_string() })
+            }
+        };
+
+        // track protocol negotiation
+        match &payload {
+            Payload::Request(Request::ProtocolVersion(version)) => self.client_protocol = Some(*version),
+            Payload::Response(Response::ProtocolVersion(version)) => {
+                self.protocol = (*version).min(self.client_protocol.take().unwrap_or(*version));
+            }
+            _ => {}
+        }
+
+        Packet {
+            timestamp: None,
+            addrs: None,
+            origin,
+            device_id,
+            packet_id,
+            length,
+            protocol,
+            payload,
+        }
+    }
+
+    fn guess_origin(&self, packet_id: PacketId, data: &[u8]) -> Origin {
+        match packet_id {
+            PacketId::DeviceListUpdated => Origin::Server,
+            PacketId::RequestControllerCount | PacketId::RequestProfileList if !data.is_empty() => Origin::Server,
+            PacketId::RequestControllerData if data.len() > 4 => Origin::Server,
+            PacketId::RequestProtocolVersion if self.client_protocol.is_some() => Origin::Server,
+            _ => Origin::Client,
+        }
+    }
+}
+
+/// One direction of a TCP connection being reassembled.
+#[derive(Default)]
+struct TcpStream {
+    next_seq: Option<u32>,
+    pending: Vec<(u32, Vec<u8>)>,
+    buffer: Vec<u8>,
+}
+
+impl TcpStream {
+    /// Push a segment, returning newly available in-order data.
+    fn push(&mut self, seq: u32, syn: bool, payload: &[u8]) -> Vec<u8> {
+        if syn {
+            self.next_seq = Some(seq.wrapping_add(1));
+            return Vec::new();
+        }
+        if payload.is_empty() {
+            return Vec::new();
+        }
+        self.pending.push((seq, payload.to_vec()));
+
+        let mut data = Vec::new();
+        let next_seq = self.next_seq.get_or_insert(seq);
+        while let Some(i) = self.pending.iter().position(|(seq, _)| next_seq.wrapping_sub(*seq) as i32 >= 0) {
+            let (seq, segment) = self.pending.swap_remove(i);
+            let overlap = next_seq.wrapping_sub(seq) as usize;
+            if overlap < segment.len() {
+                data.extend_from_slice(&segment[overlap..]);
+                *next_seq = seq.wrapping_add(segment.len() as u32);
+            }
+        }
+        data
+    }
+}
+
+const PCAP_MAGICS: [[u8; 4]; 4] = [
+    [0xd4, 0xc3, 0xb2, 0xa1],
+    [0xa1, 0xb2, 0xc3, 0xd4],
+    [0x4d, 0x3c, 0xb2, 0xa1],
+    [0xa1, 0xb2, 0x3c, 0x4d],
+];
+
+const PCAPNG_MAGIC: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+
+struct Frame<'a> {
+    timestamp: Duration,
+    link_type: u32,
+    data: &'a [u8],
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let b = self.data.get(offset..offset + 2)?;
+        Some(if self.big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let b = self.data.get(offset..offset + 4)?;
+        let b = [b[0], b[1], b[2], b[3]];
+        Some(if self.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    }
+
+    fn slice(&self, offset: usize, len: usize) -> Option<&'a [u8]> {
+        self.data.get(offset..offset.checked_add(len)?)
+    }
+}
+
+fn read_pcap(capture: &[u8]) -> Result<Vec<Frame<'_>>, OpenRGBError> {
+    let truncated = || InvalidCapture { reason: "truncated capture".to_owned() };
+    let mut frames = Vec::new();
+    let magic: [u8; 4] = capture.get(..4).and_then(|magic| magic.try_into().ok()).ok_or_else(truncated)?;
+
+    if magic == PCAPNG_MAGIC {
+        let mut reader = Reader { data: capture, big_endian: false };
+        let mut link_types = Vec::new();
+        let mut offset = 0;
+        while offset < capture.len() {
+            if capture.get(offset..offset + 4) == Some(&PCAPNG_MAGIC[..]) {
+                // section header block, defines byte order of the following blocks
+                reader.big_endian = capture.get(offset + 8..offset + 12).ok_or_else(truncated)? == [0x1a, 0x2b, 0x3c, 0x4d];
+                link_types.clear();
+            }
+            let block_type = reader.u32(offset).ok_or_else(truncated)?;
+            let block_len = reader.u32(offset + 4).ok_or_else(truncated)? as usize;
+            if block_len < 12 {
+                return Err(InvalidCapture { reason: format!("invalid pcapng block length {}", block_len) });
+            }
+            let body = offset + 8;
+            match block_type {
+                // interface description block
+                0x00000001 => link_types.push(u32::from(reader.u16(body).ok_or_else(truncated)?)),
+                // enhanced packet block
+                0x00000006 => {
+                    let interface = reader.u32(body).ok_or_else(truncated)? as usize;
+                    let ts = (u64::from(reader.u32(body + 4).ok_or_else(truncated)?) << 32) | u64::from(reader.u32(body + 8).ok_or_else(truncated)?);
+                    let len = reader.u32(body + 12).ok_or_else(truncated)? as usize;
+                    frames.push(Frame {
+                        timestamp: Duration::from_micros(ts),
+                        link_type: *link_types.get(interface).ok_or_else(|| InvalidCapture { reason: format!("unknown pcapng interface {}", interface) })?,
+                        data: reader.slice(body + 20, len).ok_or_else(truncated)?,
+                    });
+                }
+                // simple packet block
+                0x00000003 => {
+                    let max_len = block_len.checked_sub(16).ok_or_else(|| InvalidCapture { reason: format!("invalid pcapng simple packet block length {}", block_len) })?;
+                    let len = (reader.u32(body).ok_or_else(truncated)? as usize).min(max_len);
+                    frames.push(Frame {
+                        timestamp: Duration::ZERO,
+                        link_type: *link_types.first().ok_or_else(|| InvalidCapture { reason: "unknown pcapng interface 0".to_owned() })?,
+                        data: reader.slice(body + 4, len).ok_or_else(truncated)?,
+                    });
+                }
+                _ => {}
+            }
+            offset += block_len;
+        }
+    } else {
+        let reader = Reader { data: capture, big_endian: magic[0] == 0xa1 };
+        let nanos = magic == PCAP_MAGICS[2] || magic == PCAP_MAGICS[3];
+        let link_type = reader.u32(20).ok_or_else(truncated)?;
+        let mut offset = 24;
+        while offset < capture.len() {
+            let secs = u64::from(reader.u32(offset).ok_or_else(truncated)?);
+            let fraction = reader.u32(offset + 4).ok_or_else(truncated)?;
+            let len = reader.u32(offset + 8).ok_or_else(truncated)? as usize;
+            frames.push(Frame {
+                timestamp: Duration::from_secs(secs) + if nanos { Duration::from_nanos(fraction.into()) } else { Duration::from_micros(fraction.into()) },
+                link_type,
+                data: reader.slice(offset + 16, len).ok_or_else(truncated)?,
+            });
+            offset += 16 + len;
+        }
+    }
+
+    Ok(frames)
+}
+
+struct Segment<'a> {
+    src: SocketAddr,
+    dst: SocketAddr,
+    seq: u32,
+    syn: bool,
+    payload: &'a [u8],
+}
+
+/// Parse a link layer frame down to its TCP segment, if any.
+fn parse_frame(link_type: u32, frame: &[u8]) -> Option<Segment<'_>> {
+    let be16 = |data: &[u8], i: usize| data.get(i..i + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let (ether_type, packet) = match link_type {
+        // Ethernet, possibly with 802.1Q VLAN tags
+        1 => {
+            let mut offset = 12;
+            while matches!(be16(frame, offset)?, 0x8100 | 0x88a8) {
+                offset += 4;
+            }
+            (be16(frame, offset)?, frame.get(offset + 2..)?)
+        }
+        // BSD loopback
+        0 => (if *frame.first()? == 2 || *frame.get(3)? == 2 { 0x0800 } else { 0x86dd }, frame.get(4..)?),
+        // raw IP
+        101 | 228 | 229 => (if *frame.first()? >> 4 == 4 { 0x0800 } else { 0x86dd }, frame),
+        // Linux cooked capture v1 and v2
+        113 => (be16(frame, 14)?, frame.get(16..)?),
+        276 => (be16(frame, 0)?, frame.get(20..)?),
+        _ => return None,
+    };
+
+    let (src, dst, tcp) = match ether_type {
+        0x0800 => {
+            let header_len = usize::from(*packet.first()? & 0x0f) * 4;
+            let total_len = usize::from(be16(packet, 2)?);
+            if *packet.get(9)? != 6 || be16(packet, 6)? & 0x1fff != 0 {
+                return None;
+            }
+            let addr = |i: usize| -> Option<IpAddr> {
+                let b: [u8; 4] = packet.get(i..i + 4)?.try_into().ok()?;
+                Some(Ipv4Addr::from(b).into())
+            };
+            let (src, dst) = (addr(12)?, addr(16)?);
+            (src, dst, packet.get(header_len..total_len.min(packet.len()))?)
+        }
+        0x86dd => {
+            if *packet.get(6)? != 6 {
+                return None;
+            }
+            let addr = |i: usize| -> Option<IpAddr> {
+                let b: [u8; 16] = packet.get(i..i + 16)?.try_into().ok()?;
+                Some(Ipv6Addr::from(b).into())
+            };
+            let payload_len = usize::from(be16(packet, 4)?);
+            (addr(8)?, addr(24)?, packet.get(40..(40 + payload_len).min(packet.len()))?)
+        }
+        _ => return None,
+    };
+
+    let data_offset = usize::from(*tcp.get(12)? >> 4) * 4;
+    Some(Segment {
+        src: SocketAddr::new(src, be16(tcp, 0)?),
+        dst: SocketAddr::new(dst, be16(tcp, 2)?),
+        seq: u32::from_be_bytes(tcp.get(4..8)?.try_into().ok()?),
+        syn: *tcp.get(13)? & 0x02 != 0,
+        payload: tcp.get(data_offset..)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::data::Color;
+    use crate::dissect::{Dissector, parse_hex_dump, Payload};
+    use crate::message::{Request, Response};
+    use crate::session::Origin;
+    use crate::tests::setup;
+
+    fn packet(device_id: u32, packet_id: u32, payload: &[u8]) -> Vec<u8> {
+        [b"ORGB", &device_id.to_le_bytes()[..], &packet_id.to_le_bytes(), &(payload.len() as u32).to_le_bytes(), payload].concat()
+    }
+
+    fn ethernet_frame(src_port: u16, dst_port: u16, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let tcp = [
+            &src_port.to_be_bytes()[..],
+            &dst_port.to_be_bytes(),
+            &seq.to_be_bytes(),
+            &0_u32.to_be_bytes(), // ack
+            &[5 << 4, flags],
+            &[0; 6], // window, checksum, urgent pointer
+            payload,
+        ].concat();
+        let ip = [
+            &[0x45, 0][..],
+            &(20 + tcp.len() as u16).to_be_bytes(),
+            &[0; 4], // identification, fragment
+            &[64, 6, 0, 0], // ttl, protocol, checksum
+            &[127, 0, 0, 1],
+            &[127, 0, 0, 1],
+            &tcp,
+        ].concat();
+        [&[0_u8; 12][..], &0x0800_u16.to_be_bytes(), &ip].concat()
+    }
+
+    fn pcap(frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut capture = [
+            &[0xd4_u8, 0xc3, 0xb2, 0xa1][..],
+            &2_u16.to_le_bytes(),
+            &4_u16.to_le_bytes(),
+            &[0; 8], // timezone, sigfigs
+            &65535_u32.to_le_bytes(),
+            &1_u32.to_le_bytes(), // ethernet
+        ].concat();
+        for (i, frame) in frames.iter().enumerate() {
+            capture.extend_from_slice(&(i as u32).to_le_bytes());
+            capture.extend_from_slice(&0_u32.to_le_bytes());
+            capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            capture.extend_from_slice(frame);
+        }
+        capture
+    }
+
+    #[tokio::test]
+    async fn test_dissect_pcap_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let version_request = packet(0, 40, &3_u32.to_le_bytes());
+        let version_response = packet(0, 40, &2_u32.to_le_bytes());
+        let update_leds = packet(5, 1050, &[&10_u32.to_le_bytes()[..], &1_u16.to_le_bytes(), &[1, 2, 3, 0]].concat());
+        let (update_leds_1, update_leds_2) = update_leds.split_at(10);
+
+        let capture = pcap(&[
+            ethernet_frame(50000, 6742, 999, 0x02, &[]), // SYN
+            ethernet_frame(6742, 50000, 4999, 0x12, &[]), // SYN ACK
+            ethernet_frame(50000, 6742, 1000, 0x18, &version_request),
+            ethernet_frame(6742, 50000, 5000, 0x18, &version_response),
+            ethernet_frame(50000, 6742, 1020 + 10, 0x18, update_leds_2), // out of order
+            ethernet_frame(50000, 6742, 1020, 0x18, update_leds_1),
+            ethernet_frame(50000, 6742, 1020, 0x18, update_leds_1), // retransmission
+            ethernet_frame(50000, 8080, 1000, 0x18, &version_request), // other port
+        ]);
+
+        let packets = Dissector::new().dissect(&capture).await?;
+
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[0].origin, Some(Origin::Client));
+        assert!(matches!(packets[0].payload, Payload::Request(Request::ProtocolVersion(3))));
+        assert_eq!(packets[1].origin, Some(Origin::Server));
+        assert!(matches!(packets[1].payload, Payload::Response(Response::ProtocolVersion(2))));
+        assert_eq!(packets[2].protocol, 2);
+        assert_eq!(packets[2].addrs.map(|(client, server)| (client.port(), server.port())), Some((50000, 6742)));
+        match &packets[2].payload {
+            Payload::Request(request) => assert_eq!(request, &Request::UpdateLeds { controller: 5, colors: vec![Color { r: 1, g: 2, b: 3 }] }),
+            payload => panic!("unexpected payload {:?}", payload),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dissect_pcap_002() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let dissector = Dissector::new();
+        assert!(dissector.dissect_pcap(&[]).await.is_err());
+        assert!(dissector.dissect_pcap(&[0xd4, 0xc3]).await.is_err());
+        assert!(dissector.dissect_pcap(&pcap(&[])[..20]).await.is_err());
+
+        // truncated IPv4 header is skipped
+        let frame = ethernet_frame(50000, 6742, 1000, 0x18, &packet(0, 40, &3_u32.to_le_bytes()));
+        assert!(dissector.dissect_pcap(&pcap(&[frame[..14 + 12].to_vec()])).await?.is_empty());
+
+        let pcapng = [
+            // section header block
+            &[0x0a, 0x0d, 0x0d, 0x0a][..], &28_u32.to_le_bytes(), &[0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0], &[0xff; 8], &28_u32.to_le_bytes(),
+            // interface description block
+            &1_u32.to_le_bytes(), &20_u32.to_le_bytes(), &1_u16.to_le_bytes(), &[0; 2], &65535_u32.to_le_bytes(), &20_u32.to_le_bytes(),
+        ].concat();
+        assert!(dissector.dissect_pcap(&pcapng).await?.is_empty());
+        assert!(dissector.dissect_pcap(&pcapng[..10]).await.is_err());
+        for block_len in 12_u32..16 {
+            // simple packet block too short for its own header
+            let capture = [&pcapng[..], &3_u32.to_le_bytes(), &block_len.to_le_bytes(), &0_u32.to_le_bytes(), &block_len.to_le_bytes()].concat();
+            assert!(dissector.dissect_pcap(&capture).await.is_err());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dissect_oversized_packet() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        // corrupt header declaring a huge payload, followed by a valid packet
+        let data = [&packet(0, 40, &[])[..12], &u32::MAX.to_le_bytes(), &packet(0, 40, &3_u32.to_le_bytes())].concat();
+        let packets = Dissector::new().dissect_hex_dump(&crate::session::to_hex(&data)).await?;
+
+        assert_eq!(packets.len(), 2);
+        assert!(matches!(&packets[0].payload, Payload::Raw { error, .. } if error.contains("limit")));
+        assert!(matches!(packets[1].payload, Payload::Request(Request::ProtocolVersion(3))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dissect_hex_dump_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let packets = Dissector::new().dissect(b"
+00000000  4f 52 47 42 00 00 00 00  28 00 00 00 04 00 00 00  |ORGB....(.......|
+00000010  03 00 00 00 4f 52 47 42  00 00 00 00 28 00 00 00  |....ORGB....(...|
+00000020  04 00 00 00 03 00 00 00                           |........|
+00000028
+").await?;
+
+        assert_eq!(packets.len(), 2);
+        assert!(matches!(packets[0].payload, Payload::Request(Request::ProtocolVersion(3))));
+        assert!(matches!(packets[1].payload, Payload::Response(Response::ProtocolVersion(3))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_hex_dump_001() -> Result<(), Box<dyn Error>> {
+        assert_eq!(parse_hex_dump("00000000: 4f52 4742 0000  ORGB..\n")?, vec![0x4f, 0x52, 0x47, 0x42, 0, 0]);
+        assert_eq!(parse_hex_dump("0x4f 0x52\n47 42")?, b"ORGB".to_vec());
+        assert_eq!(parse_hex_dump("00000000  4f 52 47 42                                       |ORGB|\n00000004\n")?, b"ORGB".to_vec());
+        assert_eq!(parse_hex_dump("4f524742")?, b"ORGB".to_vec());
+        assert!(parse_hex_dump("4f5").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_hex_dump_002() -> Result<(), Box<dyn Error>> {
+        // repeated lines
+        let zeros = [0_u8; 16];
+        let expected = [&b"ORGB"[..], &zeros[..12], &zeros, &zeros, &zeros, b"ORGB"].concat();
+        assert_eq!(parse_hex_dump("
+00000000  4f 52 47 42 00 00 00 00  00 00 00 00 00 00 00 00  |ORGB............|
+00000010  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|
+*
+00000040  4f 52 47 42                                       |ORGB|
+00000044
+")?, expected);
+        assert_eq!(parse_hex_dump("
+00000000: 4f52 4742 0000 0000 0000 0000 0000 0000  ORGB............
+00000010: 0000 0000 0000 0000 0000 0000 0000 0000  ................
+*
+00000040: 4f52 4742                                ORGB
+")?, expected);
+
+        // repeated lines at end of dump
+        assert_eq!(parse_hex_dump("
+00000000  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|
+*
+00000020
+")?, vec![0; 32]);
+
+        // inconsistent offsets
+        assert!(parse_hex_dump("00000000  00 01\n*\n00000003\n").is_err());
+        assert!(parse_hex_dump("00000000  00 01\n*\nffffffff\n").is_err());
+
+        Ok(())
+    }
+}
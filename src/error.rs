@@ -1,5 +1,12 @@
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use std::string::FromUtf8Error;
+use std::time::Duration;
+
 use thiserror::Error;
 
+use crate::data::PacketId;
+
 /// Errors returned by [OpenRGB client](crate::OpenRGB).
 #[derive(Error, Debug)]
 pub enum OpenRGBError {
@@ -24,9 +31,171 @@ pub enum OpenRGBError {
         source: std::io::Error,
     },
 
-    /// Invalid encountered while communicating with OpenRGB server.
-    #[error("Invalid data encountered while communicating with OpenRGB server: {0}")]
-    ProtocolError(String),
+    /// Received packet payload is too short to be decoded.
+    #[error("{packet_id:?} packet payload of {len} bytes is too short to be decoded with protocol version {protocol}")]
+    TruncatedPayload {
+
+        /// Packet ID.
+        packet_id: PacketId,
+
+        /// Payload length.
+        len: usize,
+
+        /// Protocol version used to decode payload.
+        protocol: u32,
+    },
+
+    /// Received a zone matrix whose dimensions do not match its declared size.
+    #[error("Zone matrix of {height}x{width} does not match its declared size of {len} bytes")]
+    InvalidZoneMatrix {
+
+        /// Matrix height.
+        height: usize,
+
+        /// Matrix width.
+        width: usize,
+
+        /// Declared matrix size in bytes.
+        len: usize,
+    },
+
+    /// Received a packet that is not a known request or response.
+    #[error("{packet_id:?} packet is not a known {kind}")]
+    UnknownPacket {
+
+        /// Packet ID.
+        packet_id: PacketId,
+
+        /// Expected message kind, `"request"` or `"response"`.
+        kind: String,
+    },
+
+    /// Value is too large to be encoded in protocol data.
+    #[error("{what} of size {size} is too large to encode")]
+    TooLargeToEncode {
+
+        /// Encoded element.
+        what: String,
+
+        /// Element size.
+        size: usize,
+    },
+
+    /// Controller has no mode with requested name.
+    #[error("Controller {controller} has no mode {mode:?}")]
+    UnknownMode {
+
+        /// Controller ID.
+        controller: u32,
+
+        /// Requested mode name.
+        mode: String,
+    },
+
+    /// File is not a [client-side profile](crate::profile_store::ProfileStore).
+    #[error("{path:?} is not an OpenRGB client-side profile")]
+    NotAProfile {
+
+        /// Profile path.
+        path: PathBuf,
+    },
+
+    /// [Client-side profile](crate::profile_store::ProfileStore) was saved with a protocol version unsupported by this
+    /// client.
+    #[error("Profile {path:?} was saved with unsupported protocol version {protocol}")]
+    UnsupportedProfileVersion {
+
+        /// Profile path.
+        path: PathBuf,
+
+        /// Protocol version used to save profile.
+        protocol: u32,
+    },
+
+    /// Capture cannot be [dissected](crate::dissect).
+    #[error("Invalid capture: {reason}")]
+    InvalidCapture {
+
+        /// Why capture is invalid.
+        reason: String,
+    },
+
+    /// Received packet does not start with OpenRGB magic value.
+    #[error("Expected OpenRGB magic value, got {got:?}")]
+    BadMagic {
+
+        /// Received bytes.
+        got: [u8; 4],
+    },
+
+    /// Received packet for another device than expected.
+    #[error("Expected packet for device ID {expected}, got {got}")]
+    UnexpectedDevice {
+
+        /// Expected device ID.
+        expected: u32,
+
+        /// Received device ID.
+        got: u32,
+    },
+
+    /// Received another packet than expected.
+    #[error("Expected {expected:?} packet, got {got:?}")]
+    UnexpectedPacket {
+
+        /// Expected packet ID.
+        expected: PacketId,
+
+        /// Received packet ID.
+        got: PacketId,
+    },
+
+    /// Received a value unknown to this client.
+    #[error("Received unknown {kind} value {value}")]
+    UnknownEnumValue {
+
+        /// Decoded type.
+        kind: String,
+
+        /// Received value.
+        value: u32,
+    },
+
+    /// Received a string that is not valid UTF-8.
+    #[error("Failed decoding string as UTF-8")]
+    InvalidString {
+
+        /// Source error.
+        #[source]
+        source: FromUtf8Error,
+    },
+
+    /// Operation did not complete in time.
+    #[error("Timed out after {duration:?}")]
+    Timeout {
+
+        /// Elapsed timeout.
+        duration: Duration,
+    },
+
+    /// Server declared a size exceeding configured [DecodeLimits](crate::DecodeLimits), or the length of the packet
+    /// being decoded.
+    #[error("Received {what} of size {value}, exceeding limit of {limit}")]
+    DecodeLimitExceeded {
+
+        /// Decoded element.
+        what: String,
+
+        /// Size declared by server.
+        value: usize,
+
+        /// Configured limit.
+        limit: usize,
+    },
+
+    /// Connection is unusable since a previous request timed out or was interrupted, a new client must be connected.
+    #[error("Connection to OpenRGB server is unusable since a previous request timed out or was interrupted")]
+    ConnectionPoisoned,
 
     /// Server does not support operation.
     #[error("{operation:?} is only supported since protocol version {min_protocol_version:?}, but version {current_protocol_version:?} is in use. Try upgrading the OpenRGB server.")]
@@ -41,4 +210,124 @@ pub enum OpenRGBError {
         /// Minimum required protocol version to use operation.
         min_protocol_version: u32,
     },
+
+    /// Failed accessing [client-side profile store](crate::profile_store::ProfileStore).
+    #[error("Failed accessing profile store at {path:?}")]
+    ProfileStoreError {
+
+        /// Accessed path.
+        path: PathBuf,
+
+        /// Source error.
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// Request to OpenRGB server failed.
+    ///
+    /// Wraps errors returned by [client](crate::OpenRGB) requests, see [OpenRGBError::cause] to branch on the
+    /// underlying error.
+    #[error("{error} ({context})")]
+    RequestFailed {
+
+        /// Request context.
+        context: RequestContext,
+
+        /// Underlying error.
+        error: Box<OpenRGBError>,
+    },
+}
+
+impl OpenRGBError {
+    /// Underlying error, without [request context](OpenRGBError::RequestFailed).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use openrgb::{OpenRGB, OpenRGBError};
+    /// # use std::error::Error;
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let client = OpenRGB::connect().await?;
+    /// match client.get_controller(0).await {
+    ///     Ok(controller) => println!("{}", controller.name),
+    ///     Err(e) if matches!(e.cause(), OpenRGBError::CommunicationError { .. }) => println!("connection lost: {}", e),
+    ///     Err(e) => return Err(e.into()),
+    /// }
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn cause(&self) -> &OpenRGBError {
+        match self {
+            OpenRGBError::RequestFailed { error, .. } => error.cause(),
+            e => e,
+        }
+    }
+
+    /// Context of failed request, if any.
+    pub fn context(&self) -> Option<&RequestContext> {
+        match self {
+            OpenRGBError::RequestFailed { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Name of [underlying error](OpenRGBError::cause) variant, eg: `"Timeout"`.
+    pub fn kind(&self) -> &'static str {
+        match self.cause() {
+            OpenRGBError::ConnectionError { .. } => "ConnectionError",
+            OpenRGBError::CommunicationError { .. } => "CommunicationError",
+            OpenRGBError::TruncatedPayload { .. } => "TruncatedPayload",
+            OpenRGBError::InvalidZoneMatrix { .. } => "InvalidZoneMatrix",
+            OpenRGBError::UnknownPacket { .. } => "UnknownPacket",
+            OpenRGBError::TooLargeToEncode { .. } => "TooLargeToEncode",
+            OpenRGBError::UnknownMode { .. } => "UnknownMode",
+            OpenRGBError::NotAProfile { .. } => "NotAProfile",
+            OpenRGBError::UnsupportedProfileVersion { .. } => "UnsupportedProfileVersion",
+            OpenRGBError::InvalidCapture { .. } => "InvalidCapture",
+            OpenRGBError::BadMagic { .. } => "BadMagic",
+            OpenRGBError::UnexpectedDevice { .. } => "UnexpectedDevice",
+            OpenRGBError::UnexpectedPacket { .. } => "UnexpectedPacket",
+            OpenRGBError::UnknownEnumValue { .. } => "UnknownEnumValue",
+            OpenRGBError::InvalidString { .. } => "InvalidString",
+            OpenRGBError::Timeout { .. } => "Timeout",
+            OpenRGBError::DecodeLimitExceeded { .. } => "DecodeLimitExceeded",
+            OpenRGBError::ConnectionPoisoned => "ConnectionPoisoned",
+            OpenRGBError::UnsupportedOperation { .. } => "UnsupportedOperation",
+            OpenRGBError::ProfileStoreError { .. } => "ProfileStoreError",
+            OpenRGBError::RequestFailed { .. } => "RequestFailed",
+        }
+    }
+
+    /// Attach request context to this error, unless it already has one.
+    pub(crate) fn with_context(self, device_id: u32, packet_id: PacketId, protocol: u32) -> Self {
+        match self {
+            e @ OpenRGBError::RequestFailed { .. } => e,
+            e => OpenRGBError::RequestFailed {
+                context: RequestContext { device_id, packet_id, protocol },
+                error: Box::new(e),
+            },
+        }
+    }
+}
+
+/// Context of a request to OpenRGB server.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RequestContext {
+    /// Device ID (controller ID for controller requests).
+    pub device_id: u32,
+
+    /// Packet ID.
+    pub packet_id: PacketId,
+
+    /// Protocol version in use.
+    pub protocol: u32,
+}
+
+impl Display for RequestContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} packet for device {} using protocol version {}", self.packet_id, self.device_id, self.protocol)
+    }
 }
@@ -0,0 +1,793 @@
+//! HTTP REST and WebSocket gateway.
+//!
+//! Requires the `gateway` feature. The `openrgb-gateway` daemon serves this gateway for a single server connection.
+//!
+//! [Gateway] exposes a [client](OpenRGB) connection as JSON endpoints, for tools that cannot speak the binary SDK
+//! protocol:
+//!
+//! | Method   | Path                                              | Description                                      |
+//! |----------|---------------------------------------------------|--------------------------------------------------|
+//! | `GET`    | `/api/controllers`                                | Controllers summaries                            |
+//! | `GET`    | `/api/controllers/{id}`                           | Controller with modes, zones, LEDs and colors    |
+//! | `GET`    | `/api/controllers/{id}/modes`                     | Controller modes                                 |
+//! | `PUT`    | `/api/controllers/{id}/mode`                      | Set mode, see [ModeUpdate]                       |
+//! | `GET`    | `/api/controllers/{id}/zones`                     | Controller zones                                 |
+//! | `PUT`    | `/api/controllers/{id}/zones/{zone}/colors`       | Set zone colors, see [ColorsUpdate]              |
+//! | `GET`    | `/api/controllers/{id}/colors`                    | Controller LED colors                            |
+//! | `PUT`    | `/api/controllers/{id}/colors`                    | Set LED colors, see [ColorsUpdate]               |
+//! | `PUT`    | `/api/controllers/{id}/leds/{led}`                | Set single LED color, see [ColorsUpdate]         |
+//! | `GET`    | `/api/profiles`                                   | Server profile names                             |
+//! | `PUT`    | `/api/profiles/{name}`                            | Save server profile                              |
+//! | `POST`   | `/api/profiles/{name}/load`                       | Load server profile                              |
+//! | `DELETE` | `/api/profiles/{name}`                            | Delete server profile                            |
+//! | `GET`    | `/api/ws`                                         | WebSocket, see below                             |
+//!
+//! Colors are `"#rrggbb"` strings. Errors are returned as `{"error": "..."}` with a 4xx or 5xx status.
+//!
+//! The WebSocket endpoint accepts LED frames, either as [LedFrame] JSON text messages or as binary messages made of a
+//! little endian `u32` controller ID followed by `r`, `g`, `b` bytes for each LED. It pushes `{"type": "devices",
+//! "controllers": [...]}` messages on connection, whenever [Gateway::watch_devices] is notified of a change in the
+//! device list and when the client missed events by reading too slowly, and `{"type": "error", "error": "..."}`
+//! messages when a frame fails.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::gateway::Gateway;
+//! # use std::error::Error;
+//! # use std::time::Duration;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let gateway = Gateway::new(OpenRGB::connect().await?);
+//! tokio::spawn({
+//!     let gateway = gateway.clone();
+//!     async move { gateway.watch_devices(Duration::from_secs(2)).await }
+//! });
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:6780").await?;
+//! axum::serve(listener, gateway.router()).await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{Json, Router};
+use axum::extract::{Path, State, WebSocketUpgrade};
+use axum::extract::ws::{Message, WebSocket};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast;
+
+use crate::{OpenRGB, OpenRGBError};
+use crate::data::{Color, Controller, Direction, Mode, ModeFlag, Zone};
+use crate::protocol::OpenRGBStream;
+
+/// HTTP REST and WebSocket gateway to a client connection, see [module documentation](self).
+pub struct Gateway<S: OpenRGBStream + 'static> {
+    client: Arc<OpenRGB<S>>,
+    events: broadcast::Sender<String>,
+}
+
+impl<S: OpenRGBStream + 'static> Clone for Gateway<S> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            events: self.events.clone(),
+        }
+    }
+}
+
+impl<S: OpenRGBStream + 'static> Gateway<S> {
+    /// Build a gateway to `client`.
+    pub fn new(client: OpenRGB<S>) -> Self {
+        Self {
+            client: Arc::new(client),
+            events: broadcast::channel(16).0,
+        }
+    }
+
+    /// Client used by gateway.
+    pub fn client(&self) -> &OpenRGB<S> {
+        &self.client
+    }
+
+    /// Build gateway HTTP router.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/api/controllers", get(list_controllers::<S>))
+            .route("/api/controllers/{id}", get(get_controller::<S>))
+            .route("/api/controllers/{id}/modes", get(get_modes::<S>))
+            .route("/api/controllers/{id}/mode", put(set_mode::<S>))
+            .route("/api/controllers/{id}/zones", get(get_zones::<S>))
+            .route("/api/controllers/{id}/zones/{zone}/colors", put(set_zone_colors::<S>))
+            .route("/api/controllers/{id}/colors", get(get_colors::<S>).put(set_colors::<S>))
+            .route("/api/controllers/{id}/leds/{led}", put(set_led::<S>))
+            .route("/api/profiles", get(list_profiles::<S>))
+            .route("/api/profiles/{name}", put(save_profile::<S>).delete(delete_profile::<S>))
+            .route("/api/profiles/{name}/load", post(load_profile::<S>))
+            .route("/api/ws", get(websocket::<S>))
+            .with_state(self.clone())
+    }
+
+    /// Watch server device list updates, pushing new device list to WebSocket clients.
+    ///
+    /// See [OpenRGB::watch_device_list] for `poll` interval. Returns on first failed request, eg: if connection is lost.
+    pub async fn watch_devices(&self, poll: Duration) -> Result<(), OpenRGBError> {
+        let mut watcher = self.client.watch_device_list(poll);
+        loop {
+            watcher.changed().await?;
+            let summaries = self.summaries().await?;
+            debug!("Device list changed, notifying {} WebSocket clients", self.events.receiver_count());
+            // no subscriber is not an error
+            let _ = self.events.send(devices_event(&summaries));
+        }
+    }
+
+    async fn summaries(&self) -> Result<Vec<ControllerSummary>, OpenRGBError> {
+        Ok((0..).zip(self.client.get_controllers().await?).map(|(id, controller)| ControllerSummary::new(id, &controller)).collect())
+    }
+
+    /// Get controller `id`, failing with 404 if it does not exist.
+    ///
+    /// The server answers requests for unknown controllers with another device ID, or does not answer at all.
+    async fn controller(&self, id: u32) -> Result<Controller, ApiError> {
+        self.client.get_controller(id).await.map_err(|e| match e.cause() {
+            OpenRGBError::UnexpectedDevice { .. } | OpenRGBError::Timeout { .. } => {
+                ApiError(StatusCode::NOT_FOUND, format!("no controller with ID {}", id))
+            }
+            _ => e.into(),
+        })
+    }
+
+    /// Apply LED frame received on WebSocket.
+    async fn apply_frame(&self, message: Message) -> Result<(), ApiError> {
+        match message {
+            Message::Text(text) => {
+                let frame: LedFrame = serde_json::from_str(&text).map_err(|e| ApiError::bad_request(e.to_string()))?;
+                let colors = parse_colors(&frame.colors)?;
+                match frame.zone {
+                    Some(zone) => self.client.update_zone_leds(frame.controller, zone, colors).await?,
+                    None => self.client.update_leds(frame.controller, colors).await?,
+                }
+            }
+            Message::Binary(data) => {
+                if data.len() < 4 || (data.len() - 4) % 3 != 0 {
+                    return Err(ApiError::bad_request(format!("invalid binary LED frame of {} bytes", data.len())));
+                }
+                let controller = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+                let colors = data[4..].chunks(3).map(|c| Color::new(c[0], c[1], c[2])).collect();
+                self.client.update_leds(controller, colors).await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Controller summary, as returned by `GET /api/controllers`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ControllerSummary {
+    /// Controller ID.
+    pub id: u32,
+
+    /// Controller type.
+    pub r#type: String,
+
+    /// Controller name.
+    pub name: String,
+
+    /// Controller vendor.
+    pub vendor: String,
+
+    /// Controller description.
+    pub description: String,
+
+    /// Controller serial.
+    pub serial: String,
+
+    /// Controller location.
+    pub location: String,
+
+    /// Active mode name.
+    pub active_mode: Option<String>,
+
+    /// Number of zones.
+    pub zones: usize,
+
+    /// Number of LEDs.
+    pub leds: usize,
+}
+
+impl ControllerSummary {
+    fn new(id: u32, controller: &Controller) -> Self {
+        Self {
+            id,
+            r#type: format!("{:?}", controller.r#type),
+            name: controller.name.clone(),
+            vendor: controller.vendor.clone(),
+            description: controller.description.clone(),
+            serial: controller.serial.clone(),
+            location: controller.location.clone(),
+            active_mode: usize::try_from(controller.active_mode).ok()
+                .and_then(|i| controller.modes.get(i))
+                .map(|mode| mode.name.clone()),
+            zones: controller.zones.len(),
+            leds: controller.leds.len(),
+        }
+    }
+}
+
+/// Body of `PUT /api/controllers/{id}/mode`.
+///
+/// Parameters left unset keep their current value. Parameters the mode does not support and values out of the mode
+/// ranges are rejected with a 400 status.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModeUpdate {
+    /// Mode name or index.
+    pub mode: ModeRef,
+
+    /// Mode speed.
+    pub speed: Option<u32>,
+
+    /// Mode brightness.
+    pub brightness: Option<u32>,
+
+    /// Mode direction value.
+    pub direction: Option<u32>,
+
+    /// Mode colors.
+    pub colors: Option<Vec<String>>,
+}
+
+/// Reference to a mode, by index or name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ModeRef {
+    /// Mode index.
+    Index(i32),
+
+    /// Mode name.
+    Name(String),
+}
+
+/// Body of color updates: either a color for each LED, or a single `color` for all of them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColorsUpdate {
+    /// Color of each LED.
+    pub colors: Option<Vec<String>>,
+
+    /// Color of all LEDs.
+    pub color: Option<String>,
+}
+
+/// LED frame received on WebSocket.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LedFrame {
+    /// Controller ID.
+    pub controller: u32,
+
+    /// Zone ID, to update a single zone.
+    pub zone: Option<u32>,
+
+    /// LED colors.
+    pub colors: Vec<String>,
+}
+
+/// Error response.
+#[derive(Debug)]
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        ApiError(StatusCode::BAD_REQUEST, message.into())
+    }
+}
+
+impl From<OpenRGBError> for ApiError {
+    fn from(e: OpenRGBError) -> Self {
+        let status = match e.cause() {
+            OpenRGBError::UnsupportedOperation { .. } => StatusCode::NOT_IMPLEMENTED,
+            OpenRGBError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        ApiError(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult = Result<Response, ApiError>;
+
+fn color_json(color: &Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn parse_color(color: &str) -> Result<Color, ApiError> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let value = match hex.len() {
+        6 => u32::from_str_radix(hex, 16).ok(),
+        _ => None,
+    }.ok_or_else(|| ApiError::bad_request(format!("invalid color {:?}, expected \"#rrggbb\"", color)))?;
+    Ok(Color::new((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+fn parse_colors(colors: &[String]) -> Result<Vec<Color>, ApiError> {
+    colors.iter().map(|c| parse_color(c)).collect()
+}
+
+fn mode_json(index: usize, mode: &Mode) -> serde_json::Value {
+    json!({
+        "id": index,
+        "name": mode.name,
+        "flags": mode.flags.into_iter().map(|flag| format!("{:?}", flag)).collect::<Vec<_>>(),
+        "speed_min": mode.speed_min,
+        "speed_max": mode.speed_max,
+        "speed": mode.speed,
+        "brightness_min": mode.brightness_min,
+        "brightness_max": mode.brightness_max,
+        "brightness": mode.brightness,
+        "direction": mode.direction.map(|d| format!("{:?}", d)),
+        "color_mode": mode.color_mode.map(|c| format!("{:?}", c)),
+        "colors_min": mode.colors_min,
+        "colors_max": mode.colors_max,
+        "colors": mode.colors.iter().map(color_json).collect::<Vec<_>>(),
+    })
+}
+
+fn zone_json(index: usize, zone: &Zone) -> serde_json::Value {
+    json!({
+        "id": index,
+        "name": zone.name,
+        "type": format!("{:?}", zone.r#type),
+        "leds_min": zone.leds_min,
+        "leds_max": zone.leds_max,
+        "leds_count": zone.leds_count,
+        "matrix": zone.matrix.as_ref().map(|m| m.as_rows()),
+    })
+}
+
+fn devices_event(summaries: &[ControllerSummary]) -> String {
+    json!({ "type": "devices", "controllers": summaries }).to_string()
+}
+
+async fn list_controllers<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>) -> ApiResult {
+    Ok(Json(gateway.summaries().await?).into_response())
+}
+
+async fn get_controller<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path(id): Path<u32>) -> ApiResult {
+    let controller = gateway.controller(id).await?;
+    let mut json = serde_json::to_value(ControllerSummary::new(id, &controller)).map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    json["version"] = json!(controller.version);
+    json["modes"] = controller.modes.iter().enumerate().map(|(i, m)| mode_json(i, m)).collect();
+    json["zones"] = controller.zones.iter().enumerate().map(|(i, z)| zone_json(i, z)).collect();
+    json["leds"] = controller.leds.iter().enumerate().map(|(i, l)| json!({ "id": i, "name": l.name, "value": l.value })).collect();
+    json["colors"] = json!(controller.colors.iter().map(color_json).collect::<Vec<_>>());
+    Ok(Json(json).into_response())
+}
+
+async fn get_modes<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path(id): Path<u32>) -> ApiResult {
+    let controller = gateway.controller(id).await?;
+    Ok(Json(controller.modes.iter().enumerate().map(|(i, m)| mode_json(i, m)).collect::<Vec<_>>()).into_response())
+}
+
+async fn set_mode<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path(id): Path<u32>, Json(update): Json<ModeUpdate>) -> ApiResult {
+    let controller = gateway.controller(id).await?;
+    let index = match &update.mode {
+        ModeRef::Index(index) => usize::try_from(*index).ok().filter(|i| *i < controller.modes.len()),
+        ModeRef::Name(name) => controller.modes.iter().position(|m| &m.name == name),
+    }.ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("no mode {:?} on controller {}", update.mode, id)))?;
+    let mut mode = controller.modes[index].clone();
+    if let Some(speed) = update.speed {
+        check_range(&mode, "speed", speed, ModeFlag::HasSpeed, mode.speed_min, mode.speed_max)?;
+        mode.speed = Some(speed);
+    }
+    if let Some(brightness) = update.brightness {
+        check_range(&mode, "brightness", brightness, ModeFlag::HasBrightness, mode.brightness_min, mode.brightness_max)?;
+        mode.brightness = Some(brightness);
+    }
+    if let Some(direction) = update.direction {
+        let flag = match Direction::from(direction) {
+            Direction::Left | Direction::Right => ModeFlag::HasDirectionLR,
+            Direction::Up | Direction::Down => ModeFlag::HasDirectionUD,
+            Direction::Horizontal | Direction::Vertical => ModeFlag::HasDirectionHV,
+            Direction::Other(_) => return Err(ApiError::bad_request(format!("invalid direction {}", direction))),
+        };
+        if !mode.flags.contains(flag) {
+            return Err(ApiError::bad_request(format!("mode {:?} does not support direction {}", mode.name, direction)));
+        }
+        mode.direction = Some(direction.into());
+    }
+    if let Some(colors) = &update.colors {
+        let colors = parse_colors(colors)?;
+        let (min, max) = mode.colors_min.zip(mode.colors_max)
+            .ok_or_else(|| ApiError::bad_request(format!("mode {:?} has no colors", mode.name)))?;
+        if !(min as usize..=max as usize).contains(&colors.len()) {
+            return Err(ApiError::bad_request(format!("mode {:?} takes {} to {} colors, got {}", mode.name, min, max, colors.len())));
+        }
+        mode.colors = colors;
+    }
+    gateway.client.update_mode(id, index as i32, mode).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Check `mode` has `flag` parameter `name`, and `value` is within its range.
+fn check_range(mode: &Mode, name: &str, value: u32, flag: ModeFlag, min: Option<u32>, max: Option<u32>) -> Result<(), ApiError> {
+    if !mode.flags.contains(flag) {
+        return Err(ApiError::bad_request(format!("mode {:?} has no {}", mode.name, name)));
+    }
+    if let (Some(min), Some(max)) = (min, max) {
+        // some devices have inverted ranges, eg: speed from slow to fast with decreasing values
+        if !(min.min(max)..=min.max(max)).contains(&value) {
+            return Err(ApiError::bad_request(format!("mode {:?} {} must be between {} and {}, got {}", mode.name, name, min, max, value)));
+        }
+    }
+    Ok(())
+}
+
+async fn get_zones<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path(id): Path<u32>) -> ApiResult {
+    let controller = gateway.controller(id).await?;
+    Ok(Json(controller.zones.iter().enumerate().map(|(i, z)| zone_json(i, z)).collect::<Vec<_>>()).into_response())
+}
+
+async fn get_colors<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path(id): Path<u32>) -> ApiResult {
+    let controller = gateway.controller(id).await?;
+    Ok(Json(controller.colors.iter().map(color_json).collect::<Vec<_>>()).into_response())
+}
+
+/// Resolve colors update to `count` LEDs.
+fn update_colors(update: &ColorsUpdate, count: usize) -> Result<Vec<Color>, ApiError> {
+    match (&update.colors, &update.color) {
+        (Some(colors), None) => parse_colors(colors),
+        (None, Some(color)) => Ok(vec![parse_color(color)?; count]),
+        _ => Err(ApiError::bad_request("expected either \"colors\" or \"color\"")),
+    }
+}
+
+async fn set_colors<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path(id): Path<u32>, Json(update): Json<ColorsUpdate>) -> ApiResult {
+    let controller = gateway.controller(id).await?;
+    gateway.client.update_leds(id, update_colors(&update, controller.leds.len())?).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn set_zone_colors<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path((id, zone)): Path<(u32, u32)>, Json(update): Json<ColorsUpdate>) -> ApiResult {
+    let controller = gateway.controller(id).await?;
+    let count = controller.zones.get(zone as usize)
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("no zone {} on controller {}", zone, id)))?
+        .leds_count;
+    gateway.client.update_zone_leds(id, zone, update_colors(&update, count as usize)?).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn set_led<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path((id, led)): Path<(u32, i32)>, Json(update): Json<ColorsUpdate>) -> ApiResult {
+    let controller = gateway.controller(id).await?;
+    if usize::try_from(led).map_or(true, |led| led >= controller.leds.len()) {
+        return Err(ApiError(StatusCode::NOT_FOUND, format!("no LED {} on controller {}", led, id)));
+    }
+    let color = match &update.color {
+        Some(color) => parse_color(color)?,
+        None => return Err(ApiError::bad_request("expected \"color\"")),
+    };
+    gateway.client.update_led(id, led, color).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn list_profiles<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>) -> ApiResult {
+    Ok(Json(gateway.client.get_profiles().await?).into_response())
+}
+
+async fn save_profile<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path(name): Path<String>) -> ApiResult {
+    gateway.client.save_profile(name).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn load_profile<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path(name): Path<String>) -> ApiResult {
+    gateway.client.load_profile(name).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn delete_profile<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, Path(name): Path<String>) -> ApiResult {
+    gateway.client.delete_profile(name).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn websocket<S: OpenRGBStream + 'static>(State(gateway): State<Gateway<S>>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| serve_websocket(gateway, socket))
+}
+
+/// Send current devices to WebSocket client, returning whether the socket is still open.
+async fn send_devices<S: OpenRGBStream + 'static>(gateway: &Gateway<S>, socket: &mut WebSocket) -> bool {
+    match gateway.summaries().await {
+        Ok(summaries) => socket.send(Message::Text(devices_event(&summaries).into())).await.is_ok(),
+        Err(e) => {
+            warn!("Failed listing devices for WebSocket client: {}", e);
+            true
+        }
+    }
+}
+
+async fn serve_websocket<S: OpenRGBStream + 'static>(gateway: Gateway<S>, mut socket: WebSocket) {
+    let mut events = gateway.events.subscribe();
+    if !send_devices(&gateway, &mut socket).await {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => {
+                let message = match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(message)) => message,
+                };
+                if let Err(ApiError(_, error)) = gateway.apply_frame(message).await {
+                    let event = json!({ "type": "error", "error": error }).to_string();
+                    if socket.send(Message::Text(event.into())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            event = events.recv() => {
+                match event {
+                    Ok(event) => {
+                        if socket.send(Message::Text(event.into())).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        // missed events are outdated, send current devices instead
+                        debug!("WebSocket client missed {} events, sending devices snapshot", skipped);
+                        if !send_devices(&gateway, &mut socket).await {
+                            return;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::time::Duration;
+
+    use axum::body::{Body, to_bytes};
+    use axum::extract::ws::Message;
+    use axum::http::{Request as HttpRequest, StatusCode};
+    use serde_json::{json, Value};
+    use tokio_test::io::Builder;
+    use tower::ServiceExt;
+
+    use crate::DEFAULT_PROTOCOL;
+    use crate::data::{Color, fixtures, ModeFlag::*};
+    use crate::gateway::{Gateway, parse_color};
+    use crate::message::{Request, Response};
+    use crate::mock::MockBuilder;
+    use crate::protocol::OpenRGBStream;
+    use crate::tests::{OpenRGBMockBuilder, setup};
+
+    async fn call<S: OpenRGBStream + 'static>(gateway: &Gateway<S>, method: &str, uri: &str, body: Option<Value>) -> Result<(StatusCode, Value), Box<dyn Error>> {
+        let request = HttpRequest::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))?;
+        let response = gateway.router().oneshot(request).await?;
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await?;
+        Ok((status, if body.is_empty() { Value::Null } else { serde_json::from_slice(&body)? }))
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff8000").unwrap(), Color::new(255, 128, 0));
+        assert_eq!(parse_color("0000ff").unwrap(), Color::new(0, 0, 255));
+        assert!(parse_color("red").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_controllers() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let gateway = Gateway::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(1))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .to_client().await?);
+
+        let (status, body) = call(&gateway, "GET", "/api/controllers", None).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["name"], "Keyboard");
+        assert_eq!(body[0]["active_mode"], "Breath");
+        assert_eq!(body[0]["leds"], 2);
+
+        let (status, body) = call(&gateway, "GET", "/api/controllers/0", None).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["modes"][0]["speed"], 3);
+        assert_eq!(body["zones"][0]["matrix"], json!([[0, 1]]));
+        assert_eq!(body["colors"], json!(["#00ff00", "#0000ff"]));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unknown_controller() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let request = [&b"ORGB"[..], &1_u32.to_le_bytes(), &1_u32.to_le_bytes(), &4_u32.to_le_bytes(), &DEFAULT_PROTOCOL.to_le_bytes()].concat();
+
+        // server answers for another device
+        let gateway = Gateway::new(Builder::new()
+            .negotiate_default_protocol()
+            .write(&request)
+            .read(&[&b"ORGB"[..], &0_u32.to_le_bytes(), &1_u32.to_le_bytes(), &0_u32.to_le_bytes()].concat())
+            .to_client().await?);
+        let (status, body) = call(&gateway, "GET", "/api/controllers/1", None).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body["error"].is_string());
+
+        // server does not answer
+        let gateway = Gateway::new(Builder::new()
+            .negotiate_default_protocol()
+            .write(&request)
+            .wait(Duration::from_secs(60))
+            .to_client().await?);
+        let (status, _) = call(&gateway, "GET", "/api/controllers/1", None).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watch_devices() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let gateway = Gateway::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(0))
+            .expect(Request::ControllerCount)
+            .respond(Response::DeviceListUpdated)
+            .respond(Response::ControllerCount(1))
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(1))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(fixtures::controller(DEFAULT_PROTOCOL).1))
+            .to_client().await?);
+
+        let mut events = gateway.events.subscribe();
+        let watch = tokio::spawn({
+            let gateway = gateway.clone();
+            async move { gateway.watch_devices(Duration::from_secs(10)).await }
+        });
+
+        // nothing is sent until server notifies a change
+        let event: Value = serde_json::from_str(&events.recv().await?)?;
+        assert_eq!(event["type"], "devices");
+        assert_eq!(event["controllers"][0]["name"], "Keyboard");
+        watch.abort();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_mode_and_colors() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let mut mode = controller.modes[0].clone();
+        mode.speed = Some(5);
+
+        let gateway = Gateway::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode })
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![Color::new(255, 0, 0); 2] })
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .to_client().await?);
+
+        let (status, _) = call(&gateway, "PUT", "/api/controllers/0/mode", Some(json!({ "mode": "Breath", "speed": 5 }))).await?;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, _) = call(&gateway, "PUT", "/api/controllers/0/colors", Some(json!({ "color": "#ff0000" }))).await?;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, _) = call(&gateway, "PUT", "/api/controllers/0/zones/3/colors", Some(json!({ "color": "#ff0000" }))).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_mode_invalid() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, mut controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        controller.modes[0].flags -= HasDirectionUD;
+        let invalid = [
+            json!({ "mode": "Breath", "speed": 6 }), // out of range
+            json!({ "mode": "Breath", "brightness": 101 }), // out of range
+            json!({ "mode": "Breath", "direction": 2 }), // no up/down directions
+            json!({ "mode": "Breath", "direction": 42 }), // unknown direction
+            json!({ "mode": "Breath", "colors": ["#ff0000", "#00ff00", "#0000ff"] }), // 1 to 2 colors
+            json!({ "mode": "Breath", "colors": [] }),
+        ];
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol();
+        for _ in &invalid {
+            mock.expect(Request::ControllerData { controller: 0 }).respond(Response::ControllerData(controller.clone()));
+        }
+        let gateway = Gateway::new(mock.to_client().await?);
+
+        for update in invalid {
+            let (status, body) = call(&gateway, "PUT", "/api/controllers/0/mode", Some(update.clone())).await?;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", update);
+            assert!(body["error"].is_string());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_profiles() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let gateway = Gateway::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ProfileList)
+            .respond(Response::ProfileList(vec!["night".to_string()]))
+            .expect(Request::LoadProfile("night".to_string()))
+            .to_client().await?);
+
+        let (status, body) = call(&gateway, "GET", "/api/profiles", None).await?;
+        assert_eq!((status, body), (StatusCode::OK, json!(["night"])));
+
+        let (status, _) = call(&gateway, "POST", "/api/profiles/night/load", None).await?;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let gateway = Gateway::new(MockBuilder::new().negotiate_protocol(1).to_client().await?);
+        let (status, _) = call(&gateway, "GET", "/api/profiles", None).await?;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_apply_frame() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let gateway = Gateway::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::UpdateZoneLeds { controller: 1, zone: 0, colors: vec![Color::new(0, 0, 255)] })
+            .expect(Request::UpdateLeds { controller: 2, colors: vec![Color::new(1, 2, 3), Color::new(4, 5, 6)] })
+            .to_client().await?);
+
+        gateway.apply_frame(Message::Text(json!({ "controller": 1, "zone": 0, "colors": ["#0000ff"] }).to_string().into())).await.unwrap();
+        gateway.apply_frame(Message::Binary(vec![2, 0, 0, 0, 1, 2, 3, 4, 5, 6].into())).await.unwrap();
+        assert!(gateway.apply_frame(Message::Binary(vec![2, 0, 0, 0, 1].into())).await.is_err());
+        assert!(gateway.apply_frame(Message::Text("{}".into())).await.is_err());
+
+        Ok(())
+    }
+}
@@ -19,24 +19,57 @@
 //! ```
 //!
 //! See [examples](https://github.com/nicoulaj/openrgb-rs/tree/master/examples), and [OpenRGB] for client API.
+//!
+//! # Features
+//!
+//! * `gateway`: HTTP REST and WebSocket gateway in `gateway` module, and `openrgb-gateway` daemon.
+//! * `metrics`: report [client metrics](metrics) through the [metrics](https://docs.rs/metrics) crate facade.
+//! * `mqtt`: MQTT bridge publishing controllers as Home Assistant lights in `mqtt` module.
+//! * `scheduler`: time-of-day lighting scheduler in `scheduler` module.
+//! * `test-util`: scriptable mock server in `mock` module, to unit test code using the client.
+//! * `tracing`: [tracing](https://docs.rs/tracing) span for each request (device ID, packet ID, payload sizes, protocol
+//!   version and latency), and events for unsolicited packets and decode failures.
 
 #![warn(missing_docs)]
 #![deny(rustdoc::broken_intra_doc_links)]
 
-#[macro_use]
-extern crate enum_primitive_derive;
-extern crate num_traits;
-
 #[doc(inline)]
 pub use {
-    client::{DEFAULT_ADDR, DEFAULT_PROTOCOL, OpenRGB},
-    error::OpenRGBError,
+    builder::OpenRGBBuilder,
+    client::{DEFAULT_ADDR, DEFAULT_PROTOCOL, DeviceListWatcher, OpenRGB},
+    error::{OpenRGBError, RequestContext},
+    limits::DecodeLimits,
+    protocol::{OpenRGBReadableStream, OpenRGBStream, OpenRGBWritableStream},
+    timeouts::Timeouts,
 };
 
+mod builder;
 mod client;
 mod error;
+mod limits;
 mod protocol;
+mod timeouts;
+pub mod alert;
+pub mod ambilight;
+pub mod compositor;
 pub mod data;
+pub mod dissect;
+#[cfg(feature = "gateway")]
+pub mod gateway;
+pub mod message;
+pub mod metrics;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod output;
+pub mod profile_store;
+pub mod proxy;
+#[cfg(feature = "scheduler")]
+pub mod scheduler;
+pub mod session;
+pub mod state;
+pub mod transition;
 
 #[cfg(test)]
 mod tests;
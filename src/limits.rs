@@ -0,0 +1,143 @@
+use std::future::Future;
+
+use crate::OpenRGBError;
+use crate::OpenRGBError::DecodeLimitExceeded;
+
+/// Limits enforced when decoding data received from OpenRGB server.
+///
+/// Sizes declared by the server are checked against these limits before anything gets allocated, so a hostile or
+/// buggy server cannot make the client allocate unbounded memory.
+///
+/// # Example
+///
+/// ```no_run
+/// # use openrgb::{DecodeLimits, OpenRGB};
+/// # use std::error::Error;
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let mut client = OpenRGB::connect().await?;
+/// client.set_decode_limits(DecodeLimits { max_leds: 1024, ..Default::default() });
+/// #
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DecodeLimits {
+    /// Maximum packet payload size, in bytes.
+    pub max_packet_size: usize,
+
+    /// Maximum string length, in bytes.
+    pub max_string_len: usize,
+
+    /// Maximum number of modes per controller.
+    pub max_modes: usize,
+
+    /// Maximum number of zones per controller.
+    pub max_zones: usize,
+
+    /// Maximum number of LEDs and colors per controller or mode.
+    pub max_leds: usize,
+
+    /// Maximum number of cells in a zone matrix.
+    pub max_matrix_size: usize,
+
+    /// Maximum number of elements in other lists (eg: profiles).
+    pub max_list_len: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_packet_size: 4 * 1024 * 1024,
+            max_string_len: 4096,
+            max_modes: 1024,
+            max_zones: 1024,
+            max_leds: 16384,
+            max_matrix_size: 16384,
+            max_list_len: 4096,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct DecodeScope {
+    limits: DecodeLimits,
+    packet_len: Option<usize>,
+}
+
+// Readers are generic over any stream, so limits and current packet length are made available to them through the
+// task decoding data rather than by threading them through every reader.
+tokio::task_local! {
+    static SCOPE: DecodeScope;
+}
+
+impl DecodeLimits {
+    /// Limits applying to current decoding task, or default limits outside of [DecodeLimits::apply].
+    pub(crate) fn current() -> Self {
+        current_scope().limits
+    }
+
+    /// Run `f` with these limits applied.
+    pub(crate) async fn apply<F: Future>(self, f: F) -> F::Output {
+        SCOPE.scope(DecodeScope { limits: self, packet_len: None }, f).await
+    }
+}
+
+fn current_scope() -> DecodeScope {
+    SCOPE.try_with(|scope| *scope).unwrap_or_default()
+}
+
+/// Run `f` decoding a packet payload of `len` bytes, with current limits.
+pub(crate) async fn with_packet_len<F: Future>(len: usize, f: F) -> Result<F::Output, OpenRGBError> {
+    let scope = current_scope();
+    check("packet size", len, scope.limits.max_packet_size)?;
+    Ok(SCOPE.scope(DecodeScope { packet_len: Some(len), ..scope }, f).await)
+}
+
+/// Check `value` declared by server for `what` is within `limit`.
+pub(crate) fn check(what: &str, value: usize, limit: usize) -> Result<usize, OpenRGBError> {
+    if value > limit {
+        return Err(DecodeLimitExceeded { what: what.to_owned(), value, limit });
+    }
+    Ok(value)
+}
+
+/// Check `size` declared by server for `what` fits in the packet being decoded.
+pub(crate) fn check_declared_size(what: &str, size: usize) -> Result<usize, OpenRGBError> {
+    match current_scope().packet_len {
+        Some(packet_len) if size > packet_len => Err(DecodeLimitExceeded { what: what.to_owned(), value: size, limit: packet_len }),
+        _ => Ok(size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::DecodeLimits;
+    use crate::limits::{check_declared_size, with_packet_len};
+    use crate::OpenRGBError::DecodeLimitExceeded;
+
+    #[tokio::test]
+    async fn test_scope_001() -> Result<(), Box<dyn Error>> {
+        assert_eq!(DecodeLimits::current(), DecodeLimits::default());
+
+        let limits = DecodeLimits { max_packet_size: 10, ..Default::default() };
+        limits.apply(async {
+            assert_eq!(DecodeLimits::current(), limits);
+        }).await;
+
+        assert!(matches!(
+            limits.apply(with_packet_len(11, async {})).await,
+            Err(DecodeLimitExceeded { value: 11, limit: 10, .. })
+        ));
+
+        limits.apply(with_packet_len(8, async {
+            assert!(check_declared_size("test", 8).is_ok());
+            assert!(check_declared_size("test", 9).is_err());
+        })).await?;
+
+        Ok(())
+    }
+}
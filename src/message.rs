@@ -0,0 +1,401 @@
+//! Typed OpenRGB protocol messages.
+//!
+//! [Request] and [Response] represent decoded packet payloads, respectively sent by clients and servers.
+//!
+//! See [Open SDK documentation](https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation#packet-ids) for more information.
+
+use std::mem::size_of;
+
+use async_trait::async_trait;
+
+use crate::data::{Color, Controller, Mode, OpenRGBWritable, PacketId, RawString};
+use crate::{limits, OpenRGBError};
+use crate::OpenRGBError::UnknownPacket;
+use crate::protocol::{self, OpenRGBReadableStream, OpenRGBWritableStream};
+
+use PacketId::*;
+
+/// Packet sent by a client to a server.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Request {
+    /// Request controller count.
+    ControllerCount,
+
+    /// Request controller data.
+    ControllerData {
+        /// Controller ID.
+        controller: u32,
+    },
+
+    /// Request protocol version, sending client protocol version.
+    ProtocolVersion(u32),
+
+    /// Set client name.
+    SetClientName(String),
+
+    /// Request profile list.
+    ProfileList,
+
+    /// Save current configuration to a profile.
+    SaveProfile(String),
+
+    /// Load a profile.
+    LoadProfile(String),
+
+    /// Delete a profile.
+    DeleteProfile(String),
+
+    /// Resize a controller zone.
+    ResizeZone {
+        /// Controller ID.
+        controller: u32,
+
+        /// Zone ID.
+        zone: i32,
+
+        /// New zone size.
+        size: i32,
+    },
+
+    /// Update controller LEDs.
+    UpdateLeds {
+        /// Controller ID.
+        controller: u32,
+
+        /// LED colors.
+        colors: Vec<Color>,
+    },
+
+    /// Update controller zone LEDs.
+    UpdateZoneLeds {
+        /// Controller ID.
+        controller: u32,
+
+        /// Zone ID.
+        zone: u32,
+
+        /// LED colors.
+        colors: Vec<Color>,
+    },
+
+    /// Update a single LED.
+    UpdateSingleLed {
+        /// Controller ID.
+        controller: u32,
+
+        /// LED ID.
+        led: i32,
+
+        /// LED color.
+        color: Color,
+    },
+
+    /// Switch controller to custom mode.
+    SetCustomMode {
+        /// Controller ID.
+        controller: u32,
+    },
+
+    /// Update a controller mode.
+    UpdateMode {
+        /// Controller ID.
+        controller: u32,
+
+        /// Mode ID.
+        mode_id: i32,
+
+        /// Mode data.
+        mode: Mode,
+    },
+
+    /// Save a controller mode.
+    SaveMode {
+        /// Controller ID.
+        controller: u32,
+
+        /// Mode ID.
+        mode_id: i32,
+
+        /// Mode data.
+        mode: Mode,
+    },
+}
+
+impl Request {
+    /// Get packet ID used to send this request.
+    pub fn packet_id(&self) -> PacketId {
+        match self {
+            Request::ControllerCount => RequestControllerCount,
+            Request::ControllerData { .. } => RequestControllerData,
+            Request::ProtocolVersion(_) => RequestProtocolVersion,
+            Request::SetClientName(_) => SetClientName,
+            Request::ProfileList => RequestProfileList,
+            Request::SaveProfile(_) => RequestSaveProfile,
+            Request::LoadProfile(_) => RequestLoadProfile,
+            Request::DeleteProfile(_) => RequestDeleteProfile,
+            Request::ResizeZone { .. } => RGBControllerResizeZone,
+            Request::UpdateLeds { .. } => RGBControllerUpdateLeds,
+            Request::UpdateZoneLeds { .. } => RGBControllerUpdateZoneLeds,
+            Request::UpdateSingleLed { .. } => RGBControllerUpdateSingleLed,
+            Request::SetCustomMode { .. } => RGBControllerSetCustomMode,
+            Request::UpdateMode { .. } => RGBControllerUpdateMode,
+            Request::SaveMode { .. } => RGBControllerSaveMode,
+        }
+    }
+
+    /// Get device ID used to send this request.
+    pub fn device_id(&self) -> u32 {
+        match self {
+            Request::ControllerData { controller }
+            | Request::ResizeZone { controller, .. }
+            | Request::UpdateLeds { controller, .. }
+            | Request::UpdateZoneLeds { controller, .. }
+            | Request::UpdateSingleLed { controller, .. }
+            | Request::SetCustomMode { controller }
+            | Request::UpdateMode { controller, .. }
+            | Request::SaveMode { controller, .. } => *controller,
+            _ => 0,
+        }
+    }
+
+    /// Decode a request from a packet payload.
+    pub async fn decode(device_id: u32, packet_id: PacketId, payload: &[u8], protocol: u32) -> Result<Self, OpenRGBError> {
+        limits::with_packet_len(payload.len(), Self::read(device_id, packet_id, payload, protocol))
+            .await?
+            .map_err(|e| protocol::truncated(e, packet_id, payload.len(), protocol))
+    }
+
+    async fn read(device_id: u32, packet_id: PacketId, mut stream: &[u8], protocol: u32) -> Result<Self, OpenRGBError> {
+        let controller = device_id;
+        Ok(match packet_id {
+            RequestControllerCount => Request::ControllerCount,
+            RequestControllerData => Request::ControllerData { controller },
+            RequestProtocolVersion => Request::ProtocolVersion(stream.read_value(protocol).await?),
+            SetClientName => Request::SetClientName(stream.read_value::<RawString>(protocol).await?.0),
+            RequestProfileList => Request::ProfileList,
+            RequestSaveProfile => Request::SaveProfile(stream.read_value::<RawString>(protocol).await?.0),
+            RequestLoadProfile => Request::LoadProfile(stream.read_value::<RawString>(protocol).await?.0),
+            RequestDeleteProfile => Request::DeleteProfile(stream.read_value::<RawString>(protocol).await?.0),
+            RGBControllerResizeZone => {
+                let (zone, size) = stream.read_value(protocol).await?;
+                Request::ResizeZone { controller, zone, size }
+            }
+            RGBControllerUpdateLeds => {
+                let (_size, colors) = stream.read_value::<(u32, _)>(protocol).await?;
+                Request::UpdateLeds { controller, colors }
+            }
+            RGBControllerUpdateZoneLeds => {
+                let (_size, zone, colors) = stream.read_value::<(u32, _, _)>(protocol).await?;
+                Request::UpdateZoneLeds { controller, zone, colors }
+            }
+            RGBControllerUpdateSingleLed => {
+                let (led, color) = stream.read_value(protocol).await?;
+                Request::UpdateSingleLed { controller, led, color }
+            }
+            RGBControllerSetCustomMode => Request::SetCustomMode { controller },
+            RGBControllerUpdateMode => {
+                let (_size, mode_id, mode) = stream.read_value::<(u32, _, _)>(protocol).await?;
+                Request::UpdateMode { controller, mode_id, mode }
+            }
+            RGBControllerSaveMode => {
+                let (_size, mode_id, mode) = stream.read_value::<(u32, _, _)>(protocol).await?;
+                Request::SaveMode { controller, mode_id, mode }
+            }
+            DeviceListUpdated | Other(_) => return Err(UnknownPacket { packet_id, kind: "request".to_owned() }),
+        })
+    }
+}
+
+#[async_trait]
+impl OpenRGBWritable for Request {
+    fn size(&self, protocol: u32) -> usize {
+        match self {
+            Request::ControllerCount | Request::ProfileList | Request::SetCustomMode { .. } => 0,
+            Request::ControllerData { .. } => if protocol >= 1 { size_of::<u32>() } else { 0 },
+            Request::ProtocolVersion(version) => version.size(protocol),
+            Request::SetClientName(name)
+            | Request::SaveProfile(name)
+            | Request::LoadProfile(name)
+            | Request::DeleteProfile(name) => name.len() + 1,
+            Request::ResizeZone { zone, size, .. } => zone.size(protocol) + size.size(protocol),
+            Request::UpdateLeds { colors, .. } => size_of::<u32>() + colors.size(protocol),
+            Request::UpdateZoneLeds { zone, colors, .. } => size_of::<u32>() + zone.size(protocol) + colors.size(protocol),
+            Request::UpdateSingleLed { led, color, .. } => led.size(protocol) + color.size(protocol),
+            Request::UpdateMode { mode_id, mode, .. }
+            | Request::SaveMode { mode_id, mode, .. } => size_of::<u32>() + mode_id.size(protocol) + mode.size(protocol),
+        }
+    }
+
+    async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
+        match self {
+            Request::ControllerCount | Request::ProfileList | Request::SetCustomMode { .. } => Ok(()),
+            // protocol version is only sent since protocol 1
+            Request::ControllerData { .. } => if protocol >= 1 { stream.write_value(protocol, protocol).await } else { Ok(()) },
+            Request::ProtocolVersion(version) => stream.write_value(version, protocol).await,
+            Request::SetClientName(name)
+            | Request::SaveProfile(name)
+            | Request::LoadProfile(name)
+            | Request::DeleteProfile(name) => stream.write_value(RawString(name), protocol).await,
+            Request::ResizeZone { zone, size, .. } => stream.write_value((zone, size), protocol).await,
+            Request::UpdateLeds { colors, .. } => stream.write_value((colors.size(protocol), colors), protocol).await,
+            Request::UpdateZoneLeds { zone, colors, .. } => {
+                stream.write_value((zone.size(protocol) + colors.size(protocol), zone, colors), protocol).await
+            }
+            Request::UpdateSingleLed { led, color, .. } => stream.write_value((led, color), protocol).await,
+            Request::UpdateMode { mode_id, mode, .. }
+            | Request::SaveMode { mode_id, mode, .. } => {
+                stream.write_value((mode_id.size(protocol) + mode.size(protocol), mode_id, mode), protocol).await
+            }
+        }
+    }
+}
+
+/// Packet sent by a server to a client.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum Response {
+    /// Controller count.
+    ControllerCount(u32),
+
+    /// Controller data.
+    ControllerData(Controller),
+
+    /// Server protocol version.
+    ProtocolVersion(u32),
+
+    /// Profile names.
+    ProfileList(Vec<String>),
+
+    /// Notification that device list has updated.
+    DeviceListUpdated,
+}
+
+impl Response {
+    /// Get packet ID used to send this response.
+    pub fn packet_id(&self) -> PacketId {
+        match self {
+            Response::ControllerCount(_) => RequestControllerCount,
+            Response::ControllerData(_) => RequestControllerData,
+            Response::ProtocolVersion(_) => RequestProtocolVersion,
+            Response::ProfileList(_) => RequestProfileList,
+            Response::DeviceListUpdated => DeviceListUpdated,
+        }
+    }
+
+    /// Decode a response from a packet payload.
+    pub async fn decode(packet_id: PacketId, payload: &[u8], protocol: u32) -> Result<Self, OpenRGBError> {
+        limits::with_packet_len(payload.len(), Self::read(packet_id, payload, protocol))
+            .await?
+            .map_err(|e| protocol::truncated(e, packet_id, payload.len(), protocol))
+    }
+
+    async fn read(packet_id: PacketId, mut stream: &[u8], protocol: u32) -> Result<Self, OpenRGBError> {
+        Ok(match packet_id {
+            RequestControllerCount => Response::ControllerCount(stream.read_value(protocol).await?),
+            RequestControllerData => Response::ControllerData(stream.read_value(protocol).await?),
+            RequestProtocolVersion => Response::ProtocolVersion(stream.read_value(protocol).await?),
+            RequestProfileList => Response::ProfileList(stream.read_value::<(u32, _)>(protocol).await?.1),
+            DeviceListUpdated => Response::DeviceListUpdated,
+            _ => return Err(UnknownPacket { packet_id, kind: "response".to_owned() }),
+        })
+    }
+}
+
+#[async_trait]
+impl OpenRGBWritable for Response {
+    fn size(&self, protocol: u32) -> usize {
+        match self {
+            Response::ControllerCount(count) => count.size(protocol),
+            Response::ControllerData(controller) => controller.size(protocol),
+            Response::ProtocolVersion(version) => version.size(protocol),
+            Response::ProfileList(profiles) => size_of::<u32>() + profiles.size(protocol),
+            Response::DeviceListUpdated => 0,
+        }
+    }
+
+    async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
+        match self {
+            Response::ControllerCount(count) => stream.write_value(count, protocol).await,
+            Response::ControllerData(controller) => stream.write_value(controller, protocol).await,
+            Response::ProtocolVersion(version) => stream.write_value(version, protocol).await,
+            Response::ProfileList(profiles) => stream.write_value((size_of::<u32>() + profiles.size(protocol), profiles), protocol).await,
+            Response::DeviceListUpdated => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::data::{Color, PacketId};
+    use crate::{DEFAULT_PROTOCOL, OpenRGBError};
+    use crate::message::{Request, Response};
+    use crate::tests::setup;
+
+    #[tokio::test]
+    async fn test_decode_request_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let payload = [
+            &14_u32.to_le_bytes()[..], // data size
+            &7_u32.to_le_bytes(), // zone id
+            &1_u16.to_le_bytes(), // colors len
+            &[37_u8, 54_u8, 126_u8, 0_u8], // colors[0]
+        ].concat();
+
+        let request = Request::decode(2, PacketId::RGBControllerUpdateZoneLeds, &payload, DEFAULT_PROTOCOL).await?;
+
+        assert_eq!(request, Request::UpdateZoneLeds { controller: 2, zone: 7, colors: vec![Color { r: 37, g: 54, b: 126 }] });
+        assert_eq!(request.device_id(), 2);
+        assert_eq!(request.packet_id(), PacketId::RGBControllerUpdateZoneLeds);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_decode_request_002() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        assert_eq!(Request::decode(0, PacketId::RequestLoadProfile, b"test\0", DEFAULT_PROTOCOL).await?, Request::LoadProfile("test".to_string()));
+        assert!(matches!(
+            Request::decode(0, PacketId::RequestLoadProfile, b"test", DEFAULT_PROTOCOL).await,
+            Err(OpenRGBError::TruncatedPayload { packet_id: PacketId::RequestLoadProfile, len: 4, .. })
+        ));
+        assert!(matches!(
+            Request::decode(0, PacketId::DeviceListUpdated, &[], DEFAULT_PROTOCOL).await,
+            Err(OpenRGBError::UnknownPacket { packet_id: PacketId::DeviceListUpdated, .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_decode_response_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let payload = [
+            &18_u32.to_le_bytes()[..], // data size
+            &2_u16.to_le_bytes(), // profiles len
+            &3_u16.to_le_bytes(), // profiles[0] len
+            b"p1\0", // profiles[0]
+            &3_u16.to_le_bytes(), // profiles[1] len
+            b"p2\0", // profiles[1]
+        ].concat();
+
+        assert_eq!(
+            Response::decode(PacketId::RequestProfileList, &payload, DEFAULT_PROTOCOL).await?,
+            Response::ProfileList(vec!["p1".to_string(), "p2".to_string()])
+        );
+        assert!(matches!(
+            Response::decode(PacketId::RequestProfileList, &payload[..10], DEFAULT_PROTOCOL).await,
+            Err(OpenRGBError::TruncatedPayload { packet_id: PacketId::RequestProfileList, len: 10, .. })
+        ));
+        assert!(matches!(
+            Response::decode(PacketId::RequestLoadProfile, &[], DEFAULT_PROTOCOL).await,
+            Err(OpenRGBError::UnknownPacket { packet_id: PacketId::RequestLoadProfile, .. })
+        ));
+
+        Ok(())
+    }
+}
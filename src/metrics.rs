@@ -0,0 +1,243 @@
+//! Client metrics.
+//!
+//! Each [client](crate::OpenRGB) counts packets and bytes exchanged with the server (server notifications included),
+//! request round-trip latencies and errors, see [OpenRGB::metrics](crate::OpenRGB::metrics).
+//!
+//! With the `metrics` feature, these are also reported through the [metrics](https://docs.rs/metrics) crate facade:
+//! * `openrgb_packets_sent_total` and `openrgb_packets_received_total` counters,
+//! * `openrgb_bytes_sent_total` and `openrgb_bytes_received_total` counters,
+//! * `openrgb_request_latency_seconds` histogram,
+//! * `openrgb_errors_total` counter,
+//!
+//! labelled by `packet` ID and error `kind`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use std::error::Error;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let client = OpenRGB::connect().await?;
+//! // ... stream some LED updates ...
+//! for (packet_id, packets) in client.metrics().packets {
+//!     println!("{:?}: {} packets sent, {} bytes", packet_id, packets.sent, packets.sent_bytes);
+//! }
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::data::PacketId;
+use crate::OpenRGBError;
+
+/// Upper bounds of [Histogram] buckets.
+pub const LATENCY_BUCKETS: [Duration; 12] = [
+    Duration::from_micros(100),
+    Duration::from_micros(250),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_micros(2500),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_secs(1),
+];
+
+/// Snapshot of client metrics.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Packet counters, by packet ID.
+    pub packets: HashMap<PacketId, PacketMetrics>,
+
+    /// Round-trip latency of requests expecting a response, by packet ID.
+    pub latencies: HashMap<PacketId, Histogram>,
+
+    /// Error counts, by [error kind](OpenRGBError::kind).
+    pub errors: HashMap<&'static str, u64>,
+}
+
+/// Packet counters.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct PacketMetrics {
+    /// Number of packets sent.
+    pub sent: u64,
+
+    /// Number of bytes sent, including headers.
+    pub sent_bytes: u64,
+
+    /// Number of packets received.
+    pub received: u64,
+
+    /// Number of bytes received, including headers.
+    pub received_bytes: u64,
+}
+
+/// Latency histogram.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Histogram {
+    /// Number of samples in each bucket, whose upper bounds are [LATENCY_BUCKETS].
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+
+    /// Number of samples above last bucket upper bound.
+    pub overflow: u64,
+
+    /// Total number of samples.
+    pub count: u64,
+
+    /// Sum of samples.
+    pub sum: Duration,
+
+    /// Maximum sample.
+    pub max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; LATENCY_BUCKETS.len()],
+            overflow: 0,
+            count: 0,
+            sum: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl Histogram {
+    /// Record a sample.
+    pub fn record(&mut self, value: Duration) {
+        match LATENCY_BUCKETS.iter().position(|bound| value <= *bound) {
+            Some(i) => self.buckets[i] += 1,
+            None => self.overflow += 1,
+        }
+        self.count += 1;
+        self.sum += value;
+        self.max = self.max.max(value);
+    }
+
+    /// Mean of samples, if any.
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count {
+            0 => None,
+            count => {
+                let nanos = self.sum.as_nanos() / u128::from(count);
+                Some(Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32))
+            }
+        }
+    }
+
+    /// Upper bound of the bucket containing quantile `q` (between 0 and 1), or [Histogram::max] if above last bucket.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.buckets) {
+            seen += count;
+            if seen >= rank {
+                return Some(*bound);
+            }
+        }
+        Some(self.max)
+    }
+}
+
+/// Metrics recorder shared by clients of a connection.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    snapshot: Mutex<MetricsSnapshot>,
+}
+
+impl Metrics {
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        self.snapshot.lock().unwrap().clone()
+    }
+
+    pub(crate) fn reset(&self) {
+        *self.snapshot.lock().unwrap() = MetricsSnapshot::default();
+    }
+
+    pub(crate) fn sent(&self, packet_id: PacketId, bytes: usize) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let packets = snapshot.packets.entry(packet_id).or_default();
+        packets.sent += 1;
+        packets.sent_bytes += bytes as u64;
+
+        #[cfg(feature = "metrics")] {
+            ::metrics::counter!("openrgb_packets_sent_total", "packet" => packet_id.name()).increment(1);
+            ::metrics::counter!("openrgb_bytes_sent_total", "packet" => packet_id.name()).increment(bytes as u64);
+        }
+    }
+
+    /// Record a received packet, with `latency` of the request it answers (`None` for server notifications).
+    pub(crate) fn received(&self, packet_id: PacketId, bytes: usize, latency: Option<Duration>) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let packets = snapshot.packets.entry(packet_id).or_default();
+        packets.received += 1;
+        packets.received_bytes += bytes as u64;
+        if let Some(latency) = latency {
+            snapshot.latencies.entry(packet_id).or_default().record(latency);
+        }
+
+        #[cfg(feature = "metrics")] {
+            ::metrics::counter!("openrgb_packets_received_total", "packet" => packet_id.name()).increment(1);
+            ::metrics::counter!("openrgb_bytes_received_total", "packet" => packet_id.name()).increment(bytes as u64);
+            if let Some(latency) = latency {
+                ::metrics::histogram!("openrgb_request_latency_seconds", "packet" => packet_id.name()).record(latency.as_secs_f64());
+            }
+        }
+    }
+
+    pub(crate) fn error(&self, error: &OpenRGBError) {
+        *self.snapshot.lock().unwrap().errors.entry(error.kind()).or_default() += 1;
+
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!("openrgb_errors_total", "kind" => error.kind()).increment(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::metrics::Histogram;
+
+    #[test]
+    fn test_histogram_001() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.mean(), None);
+        assert_eq!(histogram.quantile(0.5), None);
+
+        histogram.record(Duration::from_micros(50));
+        histogram.record(Duration::from_micros(700));
+        histogram.record(Duration::from_millis(3));
+        histogram.record(Duration::from_secs(2));
+
+        assert_eq!(histogram.count, 4);
+        assert_eq!(histogram.buckets[0], 1);
+        assert_eq!(histogram.buckets[3], 1);
+        assert_eq!(histogram.buckets[5], 1);
+        assert_eq!(histogram.overflow, 1);
+        assert_eq!(histogram.max, Duration::from_secs(2));
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_millis(1)));
+        assert_eq!(histogram.quantile(0.75), Some(Duration::from_millis(5)));
+        assert_eq!(histogram.quantile(1.0), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_histogram_002() {
+        let count = u64::from(u32::MAX) + 2;
+        let histogram = Histogram { count, sum: Duration::from_nanos(3000 * count), ..Histogram::default() };
+        assert_eq!(histogram.mean(), Some(Duration::from_micros(3)));
+    }
+}
@@ -0,0 +1,301 @@
+//! Scriptable mock OpenRGB server, to unit test code using the [client](crate::OpenRGB).
+//!
+//! Requires the `test-util` feature.
+//!
+//! The mock is scripted at the level of [typed messages](crate::message): it checks requests sent by the client
+//! against expected ones in order, and sends canned responses back. It panics on unexpected requests, and on drop if
+//! part of the script was not played.
+//!
+//! # Example
+//!
+//! ```
+//! use openrgb::data::Color;
+//! use openrgb::message::{Request, Response};
+//! use openrgb::mock::MockBuilder;
+//! use std::error::Error;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn Error>> {
+//!     let colors = vec![Color::new(255, 0, 0); 2];
+//!
+//!     let client = MockBuilder::new()
+//!         .negotiate_default_protocol()
+//!         .expect(Request::ControllerCount)
+//!         .respond(Response::ControllerCount(3))
+//!         .expect(Request::UpdateLeds { controller: 2, colors: colors.clone() })
+//!         .to_client().await?;
+//!
+//!     assert_eq!(client.get_controller_count().await?, 3);
+//!     client.update_leds(2, colors).await?;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{DEFAULT_PROTOCOL, OpenRGB, OpenRGBError};
+use crate::data::PacketId;
+use crate::message::{Request, Response};
+use crate::protocol::{HEADER_LEN, OpenRGBWritableStream};
+
+#[derive(Debug)]
+enum Step {
+    Expect {
+        protocol: u32,
+        request: Request,
+    },
+    Respond {
+        protocol: u32,
+        device_id: u32,
+        response: Response,
+    },
+}
+
+/// Builder for [MockStream].
+#[derive(Debug)]
+pub struct MockBuilder {
+    protocol: u32,
+    device_id: u32,
+    steps: VecDeque<Step>,
+}
+
+impl Default for MockBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockBuilder {
+    /// Build a new mock with an empty script.
+    ///
+    /// Protocol version used to decode requests and encode responses is [DEFAULT_PROTOCOL] until
+    /// [MockBuilder::negotiate_protocol] is called.
+    pub fn new() -> Self {
+        Self {
+            protocol: DEFAULT_PROTOCOL,
+            device_id: 0,
+            steps: VecDeque::new(),
+        }
+    }
+
+    /// Expect client protocol negotiation, answering with [DEFAULT_PROTOCOL].
+    pub fn negotiate_default_protocol(&mut self) -> &mut Self {
+        self.negotiate_protocol(DEFAULT_PROTOCOL)
+    }
+
+    /// Expect client protocol negotiation, answering with `protocol` and using it for the rest of the script.
+    pub fn negotiate_protocol(&mut self, protocol: u32) -> &mut Self {
+        self.negotiate(DEFAULT_PROTOCOL, protocol)
+    }
+
+    /// Expect client protocol negotiation with a client supporting up to `client_protocol` (eg: when using
+    /// [OpenRGBBuilder::max_protocol](crate::OpenRGBBuilder::max_protocol)), answering with `server_protocol`.
+    pub fn negotiate(&mut self, client_protocol: u32, server_protocol: u32) -> &mut Self {
+        self.protocol = client_protocol;
+        self.expect(Request::ProtocolVersion(client_protocol));
+        self.respond(Response::ProtocolVersion(server_protocol));
+        self.protocol = client_protocol.min(server_protocol);
+        self
+    }
+
+    /// Expect client to send `request`.
+    pub fn expect(&mut self, request: Request) -> &mut Self {
+        self.device_id = request.device_id();
+        self.steps.push_back(Step::Expect { protocol: self.protocol, request });
+        self
+    }
+
+    /// Send `response` to client.
+    ///
+    /// Response device ID is the one of the last expected request, or 0 for [Response::DeviceListUpdated].
+    pub fn respond(&mut self, response: Response) -> &mut Self {
+        let device_id = match response {
+            Response::DeviceListUpdated => 0,
+            _ => self.device_id,
+        };
+        self.steps.push_back(Step::Respond { protocol: self.protocol, device_id, response });
+        self
+    }
+
+    /// Build mock stream, playing script recorded so far.
+    pub fn build(&mut self) -> MockStream {
+        MockStream {
+            protocol: self.protocol,
+            steps: std::mem::take(&mut self.steps),
+            written: Vec::new(),
+            readable: VecDeque::new(),
+        }
+    }
+
+    /// Build a client connected to mock stream.
+    pub async fn to_client(&mut self) -> Result<OpenRGB<MockStream>, OpenRGBError> {
+        OpenRGB::new(self.build()).await
+    }
+}
+
+/// Mock OpenRGB server stream, built with [MockBuilder].
+#[derive(Debug)]
+pub struct MockStream {
+    protocol: u32,
+    steps: VecDeque<Step>,
+    written: Vec<u8>,
+    readable: VecDeque<u8>,
+}
+
+impl MockStream {
+    /// Play script until it needs more data from client.
+    fn play(&mut self, cx: &mut Context<'_>) {
+        loop {
+            match self.steps.front() {
+                Some(Step::Expect { protocol, .. }) => {
+                    self.protocol = *protocol;
+                    let request = match self.next_request(cx) {
+                        Some(request) => request,
+                        None => return,
+                    };
+                    if let Some(Step::Expect { request: expected, .. }) = self.steps.pop_front() {
+                        assert_eq!(request, expected, "mock received unexpected request");
+                    }
+                }
+                Some(Step::Respond { .. }) => {
+                    if let Some(Step::Respond { protocol, device_id, response }) = self.steps.pop_front() {
+                        self.protocol = protocol;
+                        let mut buf = Vec::new();
+                        poll_now(buf.write_packet(protocol, device_id, response.packet_id(), response), cx)
+                            .expect("mock failed encoding response");
+                        self.readable.extend(buf);
+                    }
+                }
+                None => {
+                    if let Some(request) = self.next_request(cx) {
+                        panic!("mock received request after end of script: {:?}", request);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Decode next request written by client, if complete.
+    fn next_request(&mut self, cx: &mut Context<'_>) -> Option<Request> {
+        if self.written.len() < HEADER_LEN {
+            return None;
+        }
+        assert_eq!(&self.written[0..4], b"ORGB", "mock received packet without OpenRGB magic value");
+        let field = |i: usize| u32::from_le_bytes([self.written[i], self.written[i + 1], self.written[i + 2], self.written[i + 3]]);
+        let (device_id, packet_id, len) = (field(4), PacketId::from(field(8)), field(12) as usize);
+        if self.written.len() < HEADER_LEN + len {
+            return None;
+        }
+        let request = poll_now(Request::decode(device_id, packet_id, &self.written[HEADER_LEN..HEADER_LEN + len], self.protocol), cx)
+            .unwrap_or_else(|e| panic!("mock failed decoding {:?} request: {}", packet_id, e));
+        self.written.drain(..HEADER_LEN + len);
+        Some(request)
+    }
+}
+
+impl AsyncRead for MockStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        self.play(cx);
+        if self.readable.is_empty() {
+            if let Some(Step::Expect { request, .. }) = self.steps.front() {
+                panic!("client is waiting for a response, but mock expects request {:?}", request);
+            }
+            // end of script, close stream
+            return Poll::Ready(Ok(()));
+        }
+        let len = buf.remaining().min(self.readable.len());
+        let data: Vec<u8> = self.readable.drain(..len).collect();
+        buf.put_slice(&data);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for MockStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.written.extend_from_slice(buf);
+        self.play(cx);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MockStream {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            return;
+        }
+        assert!(self.steps.is_empty(), "mock script was not fully played, remaining steps: {:?}", self.steps);
+        assert!(self.readable.is_empty(), "client did not read {} bytes of mock responses", self.readable.len());
+        assert!(self.written.is_empty(), "mock received {} bytes of incomplete request", self.written.len());
+    }
+}
+
+/// Poll a future that cannot block, as it only encodes to or decodes from memory.
+fn poll_now<F: Future>(f: F, cx: &mut Context<'_>) -> F::Output {
+    match Box::pin(f).as_mut().poll(cx) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("in-memory encoding should not block"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::message::{Request, Response};
+    use crate::mock::MockBuilder;
+    use crate::tests::setup;
+
+    #[tokio::test]
+    async fn test_script_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = MockBuilder::new()
+            .negotiate_protocol(2)
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(3))
+            .expect(Request::LoadProfile("test".to_string()))
+            .to_client().await?;
+
+        assert_eq!(client.get_protocol_version(), 2);
+        assert_eq!(client.get_controller_count().await?, 3);
+        client.load_profile("test").await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "mock received unexpected request")]
+    async fn test_unexpected_request() {
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::LoadProfile("test".to_string()))
+            .to_client().await.unwrap();
+
+        client.load_profile("other").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "mock script was not fully played")]
+    async fn test_unplayed_script() {
+        MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .to_client().await.unwrap();
+    }
+}
@@ -0,0 +1,866 @@
+//! MQTT bridge with Home Assistant discovery.
+//!
+//! Requires the `mqtt` feature.
+//!
+//! [MqttBridge] publishes each controller as a Home Assistant [MQTT light](https://www.home-assistant.io/integrations/light.mqtt/)
+//! entity using the JSON schema, through [MQTT discovery](https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery).
+//! Each light supports on/off, RGB color, brightness and effects, effects being controller [mode](crate::data::Mode)
+//! names.
+//!
+//! Topics, with default prefixes:
+//! * `homeassistant/light/openrgb/{object_id}/config`: discovery configuration (retained),
+//! * `openrgb/{object_id}/state`: light state, published on changes (retained),
+//! * `openrgb/{object_id}/set`: commands from Home Assistant,
+//! * `openrgb/status`: bridge availability, `online` or `offline` (see [MqttBridge::last_will]).
+//!
+//! Object IDs are derived from controller name and serial (or location), so that entities survive controller list
+//! changes.
+//!
+//! The bridge talks to the broker through the [MqttPublisher] trait, implemented for [rumqttc::AsyncClient], so that it
+//! can be tested without broker.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::mqtt::MqttBridge;
+//! # use rumqttc::{AsyncClient, MqttOptions};
+//! # use std::error::Error;
+//! # use std::time::Duration;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let bridge = MqttBridge::new(OpenRGB::connect().await?);
+//!
+//! let mut options = MqttOptions::new("openrgb", "localhost", 1883);
+//! options.set_last_will(bridge.last_will());
+//! let (mqtt, eventloop) = AsyncClient::new(options, 16);
+//!
+//! bridge.run(mqtt, eventloop, Duration::from_secs(5)).await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use flagset::FlagSet;
+use log::{debug, info, warn};
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+use crate::{OpenRGB, OpenRGBError};
+use crate::data::{Color, ColorMode, Controller, Mode, ModeFlag};
+use crate::protocol::OpenRGBStream;
+use crate::state::ControllerState;
+
+/// Errors returned by [MqttBridge].
+#[derive(Error, Debug)]
+pub enum MqttBridgeError {
+    /// OpenRGB request failed.
+    #[error(transparent)]
+    OpenRGB(#[from] OpenRGBError),
+
+    /// MQTT client request failed.
+    #[error("MQTT client request failed")]
+    Client(#[from] rumqttc::ClientError),
+
+    /// Received an invalid command.
+    #[error("Invalid command on {topic:?}: {reason}")]
+    InvalidCommand {
+
+        /// Command topic.
+        topic: String,
+
+        /// Reason.
+        reason: String,
+    },
+}
+
+/// MQTT client used by [MqttBridge].
+#[async_trait]
+pub trait MqttPublisher: Send + Sync {
+    /// Publish `payload` on `topic`.
+    async fn publish(&self, topic: &str, payload: String, retain: bool) -> Result<(), MqttBridgeError>;
+
+    /// Subscribe to `topic` filter.
+    async fn subscribe(&self, topic: &str) -> Result<(), MqttBridgeError>;
+}
+
+#[async_trait]
+impl MqttPublisher for AsyncClient {
+    async fn publish(&self, topic: &str, payload: String, retain: bool) -> Result<(), MqttBridgeError> {
+        AsyncClient::publish(self, topic, QoS::AtLeastOnce, retain, payload).await.map_err(Into::into)
+    }
+
+    async fn subscribe(&self, topic: &str) -> Result<(), MqttBridgeError> {
+        AsyncClient::subscribe(self, topic, QoS::AtLeastOnce).await.map_err(Into::into)
+    }
+}
+
+/// Home Assistant JSON schema light state.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LightState {
+    /// `ON` or `OFF`.
+    pub state: String,
+
+    /// Brightness, between 0 and 255.
+    pub brightness: u8,
+
+    /// Color.
+    pub color: Rgb,
+
+    /// Color mode, always `rgb`.
+    pub color_mode: String,
+
+    /// Effect (active mode name).
+    pub effect: Option<String>,
+}
+
+/// Home Assistant RGB color.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Rgb {
+    /// Red.
+    pub r: u8,
+
+    /// Green.
+    pub g: u8,
+
+    /// Blue.
+    pub b: u8,
+}
+
+impl From<Color> for Rgb {
+    fn from(c: Color) -> Self {
+        Rgb { r: c.r, g: c.g, b: c.b }
+    }
+}
+
+impl From<Rgb> for Color {
+    fn from(c: Rgb) -> Self {
+        Color::new(c.r, c.g, c.b)
+    }
+}
+
+/// Home Assistant JSON schema light command.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Command {
+    state: Option<String>,
+    brightness: Option<u8>,
+    color: Option<Rgb>,
+    effect: Option<String>,
+}
+
+/// Bridged controller.
+#[derive(Debug, Clone)]
+struct Light {
+    controller_id: u32,
+    object_id: String,
+    color: Color,
+    brightness: u8,
+    saved: Option<ControllerState>,
+    published: Option<LightState>,
+}
+
+/// MQTT bridge publishing controllers as Home Assistant lights, see [module documentation](self).
+pub struct MqttBridge<S: OpenRGBStream> {
+    client: OpenRGB<S>,
+    discovery_prefix: String,
+    base_topic: String,
+    lights: Mutex<Vec<Light>>,
+}
+
+impl<S: OpenRGBStream> MqttBridge<S> {
+    /// Build a bridge for `client`, with default `homeassistant` discovery prefix and `openrgb` base topic.
+    pub fn new(client: OpenRGB<S>) -> Self {
+        Self {
+            client,
+            discovery_prefix: "homeassistant".to_owned(),
+            base_topic: "openrgb".to_owned(),
+            lights: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Set Home Assistant discovery prefix (default: `homeassistant`).
+    pub fn discovery_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.discovery_prefix = prefix.into();
+        self
+    }
+
+    /// Set base topic of state and command topics (default: `openrgb`).
+    pub fn base_topic(mut self, topic: impl Into<String>) -> Self {
+        self.base_topic = topic.into();
+        self
+    }
+
+    /// Availability topic.
+    pub fn availability_topic(&self) -> String {
+        format!("{}/status", self.base_topic)
+    }
+
+    /// Last will marking the bridge offline, to set on MQTT connection options.
+    pub fn last_will(&self) -> LastWill {
+        LastWill::new(self.availability_topic(), "offline", QoS::AtLeastOnce, true)
+    }
+
+    /// Run bridge forever: announce lights on each (re)connection to broker and Home Assistant restart, handle commands,
+    /// and poll controllers every `poll` to publish state changes.
+    ///
+    /// Returns on OpenRGB request failure, eg: if connection to OpenRGB server is lost. Broker disconnections are
+    /// retried.
+    pub async fn run(&self, mqtt: AsyncClient, eventloop: EventLoop, poll: Duration) -> Result<(), MqttBridgeError> {
+        // requests are only sent while event loop is polled, which must go on while publishing waits for room in the
+        // client channel
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let _events_task = AbortOnDrop(tokio::spawn(poll_events(eventloop, events_tx)));
+
+        // lights are announced once connected
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + poll, poll);
+        loop {
+            tokio::select! {
+                Some(event) = events.recv() => match event {
+                    Event::Incoming(Packet::ConnAck(_)) => {
+                        info!("Connected to MQTT broker");
+                        self.announce(&mqtt).await?;
+                    }
+                    Event::Incoming(Packet::Publish(publish)) => {
+                        let payload = String::from_utf8_lossy(&publish.payload);
+                        match self.handle_message(&mqtt, &publish.topic, &payload).await {
+                            Err(MqttBridgeError::OpenRGB(e)) => return Err(e.into()),
+                            Err(e) => warn!("Failed handling MQTT message: {}", e),
+                            Ok(()) => {}
+                        }
+                    }
+                    _ => {}
+                },
+                _ = interval.tick() => self.refresh(&mqtt).await?,
+            }
+        }
+    }
+
+    /// Publish discovery configuration and state of all lights, and subscribe to command topics.
+    pub async fn announce(&self, mqtt: &impl MqttPublisher) -> Result<(), MqttBridgeError> {
+        let controllers = self.client.get_controllers().await?;
+        let mut lights = self.lights.lock().await;
+        self.unannounce_removed(mqtt, &lights, &controllers).await?;
+        *lights = self.merge_lights(&lights, &controllers);
+
+        mqtt.subscribe(&format!("{}/+/set", self.base_topic)).await?;
+        mqtt.subscribe(&format!("{}/status", self.discovery_prefix)).await?;
+        for (light, controller) in lights.iter_mut().zip(&controllers) {
+            debug!("Announcing {} as {}", controller.name, light.object_id);
+            mqtt.publish(&self.config_topic(&light.object_id), self.config(light, controller).to_string(), true).await?;
+            light.published = None;
+        }
+        mqtt.publish(&self.availability_topic(), "online".to_owned(), true).await?;
+        for (light, controller) in lights.iter_mut().zip(&controllers) {
+            self.publish_state(mqtt, light, controller).await?;
+        }
+        Ok(())
+    }
+
+    /// Re-read controllers, announcing lights again if controller list changed, or publishing state changes.
+    pub async fn refresh(&self, mqtt: &impl MqttPublisher) -> Result<(), MqttBridgeError> {
+        let controllers = self.client.get_controllers().await?;
+        let mut lights = self.lights.lock().await;
+        let object_ids: Vec<_> = controllers.iter().map(object_id).collect();
+        if lights.iter().map(|l| &l.object_id).ne(object_ids.iter()) {
+            drop(lights);
+            info!("Controller list changed, announcing lights again");
+            return self.announce(mqtt).await;
+        }
+        for (light, controller) in lights.iter_mut().zip(&controllers) {
+            self.publish_state(mqtt, light, controller).await?;
+        }
+        Ok(())
+    }
+
+    /// Handle a message received on subscribed topics.
+    pub async fn handle_message(&self, mqtt: &impl MqttPublisher, topic: &str, payload: &str) -> Result<(), MqttBridgeError> {
+        if topic == format!("{}/status", self.discovery_prefix) {
+            if payload == "online" {
+                info!("Home Assistant restarted, announcing lights again");
+                self.announce(mqtt).await?;
+            }
+            return Ok(());
+        }
+
+        let object_id = topic.strip_prefix(&format!("{}/", self.base_topic))
+            .and_then(|t| t.strip_suffix("/set"))
+            .ok_or_else(|| invalid(topic, "unexpected topic"))?;
+        let command: Command = serde_json::from_str(payload).map_err(|e| invalid(topic, &e.to_string()))?;
+
+        let mut lights = self.lights.lock().await;
+        let light = lights.iter_mut().find(|l| l.object_id == object_id).ok_or_else(|| invalid(topic, "unknown light"))?;
+        debug!("Applying {:?} to controller {}", command, light.controller_id);
+        self.apply(light, &command).await.map_err(|e| match e {
+            MqttBridgeError::InvalidCommand { reason, .. } => invalid(topic, &reason),
+            e => e,
+        })?;
+        let controller = self.client.get_controller(light.controller_id).await?;
+        self.publish_state(mqtt, light, &controller).await
+    }
+
+    /// Apply `command` to `light`.
+    async fn apply(&self, light: &mut Light, command: &Command) -> Result<(), MqttBridgeError> {
+        let id = light.controller_id;
+        let controller = self.client.get_controller(id).await?;
+        let active = active_mode(&controller);
+
+        if command.state.as_deref() == Some("OFF") {
+            if is_on(&controller) {
+                light.saved = Some(ControllerState {
+                    name: controller.name.clone(),
+                    active_mode: controller.active_mode,
+                    mode: active.cloned(),
+                    colors: controller.colors.clone(),
+                });
+            }
+            return match controller.modes.iter().position(|m| m.name.eq_ignore_ascii_case("off")) {
+                Some(off) => Ok(self.client.update_mode(id, off as i32, controller.modes[off].clone()).await?),
+                None => {
+                    self.client.set_custom_mode(id).await?;
+                    Ok(self.client.update_leds(id, vec![Color::new(0, 0, 0); controller.leds.len()]).await?)
+                }
+            };
+        }
+
+        if let Some(brightness) = command.brightness {
+            light.brightness = brightness;
+        }
+        if let Some(color) = command.color {
+            light.color = color.into();
+        }
+
+        let controller = match (&light.saved, is_on(&controller), &command.effect) {
+            (Some(saved), false, None) => {
+                self.client.restore_controller(id, saved).await?;
+                self.client.get_controller(id).await?
+            }
+            _ => controller,
+        };
+
+        let (mode_id, mut mode) = match &command.effect {
+            Some(effect) => controller.modes.iter().position(|m| &m.name == effect)
+                .map(|i| (i, controller.modes[i].clone()))
+                .ok_or_else(|| invalid("", &format!("unknown effect {:?}", effect)))?,
+            None => match active_mode(&controller) {
+                Some(mode) => (controller.active_mode as usize, mode.clone()),
+                None => return Err(invalid("", "controller has no active mode")),
+            },
+        };
+
+        let mut mode_changed = command.effect.is_some();
+        let scaled_brightness = match mode_brightness_range(&mode) {
+            Some(range) if command.brightness.is_some() => {
+                mode.brightness = Some(to_mode_brightness(light.brightness, range));
+                mode_changed = true;
+                false
+            }
+            Some(_) => false,
+            None => true,
+        };
+
+        let leds = if command.color.is_some() || (command.brightness.is_some() && scaled_brightness) {
+            let color = if scaled_brightness { scale(light.color, light.brightness) } else { light.color };
+            match mode.color_mode {
+                Some(ColorMode::ModeSpecific) if !mode.colors.is_empty() => {
+                    mode.colors.iter_mut().for_each(|c| *c = color);
+                    mode_changed = true;
+                    None
+                }
+                Some(ColorMode::PerLED) => Some(color),
+                _ if command.effect.is_none() => {
+                    self.client.set_custom_mode(id).await?;
+                    mode_changed = false;
+                    Some(color)
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        if mode_changed {
+            self.client.update_mode(id, mode_id as i32, mode).await?;
+        }
+        if let Some(color) = leds {
+            self.client.update_leds(id, vec![color; controller.leds.len()]).await?;
+        }
+        light.saved = None;
+        Ok(())
+    }
+
+    /// Publish `light` state if it changed.
+    async fn publish_state(&self, mqtt: &impl MqttPublisher, light: &mut Light, controller: &Controller) -> Result<(), MqttBridgeError> {
+        let mode = active_mode(controller);
+        if let Some(color) = current_color(controller) {
+            if mode.is_some_and(|m| m.color_mode != Some(ColorMode::PerLED) || mode_brightness_range(m).is_some()) {
+                light.color = color;
+            }
+        }
+        let brightness = mode.and_then(|m| Some((mode_brightness_range(m)?, m.brightness?)))
+            .map(|(range, b)| from_mode_brightness(b, range))
+            .unwrap_or(light.brightness);
+        let state = LightState {
+            state: if is_on(controller) { "ON" } else { "OFF" }.to_owned(),
+            brightness,
+            color: light.color.into(),
+            color_mode: "rgb".to_owned(),
+            effect: mode.map(|m| m.name.clone()),
+        };
+        if light.published.as_ref() != Some(&state) {
+            let payload = serde_json::to_string(&state).map_err(|e| invalid(&light.object_id, &e.to_string()))?;
+            mqtt.publish(&format!("{}/{}/state", self.base_topic, light.object_id), payload, true).await?;
+            light.published = Some(state);
+        }
+        Ok(())
+    }
+
+    /// Remove discovery configuration of lights no longer present.
+    async fn unannounce_removed(&self, mqtt: &impl MqttPublisher, lights: &[Light], controllers: &[Controller]) -> Result<(), MqttBridgeError> {
+        for light in lights {
+            if !controllers.iter().any(|c| object_id(c) == light.object_id) {
+                debug!("Removing {}", light.object_id);
+                mqtt.publish(&self.config_topic(&light.object_id), String::new(), true).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Build lights for `controllers`, keeping state of existing lights.
+    fn merge_lights(&self, lights: &[Light], controllers: &[Controller]) -> Vec<Light> {
+        (0..).zip(controllers).map(|(controller_id, controller)| {
+            let object_id = object_id(controller);
+            match lights.iter().find(|l| l.object_id == object_id) {
+                Some(light) => Light { controller_id, ..light.clone() },
+                None => Light {
+                    controller_id,
+                    color: current_color(controller).unwrap_or_else(|| Color::new(255, 255, 255)),
+                    brightness: 255,
+                    saved: None,
+                    published: None,
+                    object_id,
+                },
+            }
+        }).collect()
+    }
+
+    fn config_topic(&self, object_id: &str) -> String {
+        format!("{}/light/openrgb/{}/config", self.discovery_prefix, object_id)
+    }
+
+    fn config(&self, light: &Light, controller: &Controller) -> serde_json::Value {
+        json!({
+            "name": null,
+            "unique_id": format!("openrgb_{}", light.object_id),
+            "object_id": format!("openrgb_{}", light.object_id),
+            "schema": "json",
+            "command_topic": format!("{}/{}/set", self.base_topic, light.object_id),
+            "state_topic": format!("{}/{}/state", self.base_topic, light.object_id),
+            "availability_topic": self.availability_topic(),
+            "brightness": true,
+            "supported_color_modes": ["rgb"],
+            "effect": true,
+            "effect_list": controller.modes.iter().map(|m| &m.name).collect::<Vec<_>>(),
+            "device": {
+                "identifiers": [format!("openrgb_{}", light.object_id)],
+                "name": controller.name,
+                "manufacturer": controller.vendor,
+                "model": controller.description,
+                "sw_version": controller.version,
+                "serial_number": controller.serial,
+            },
+        })
+    }
+}
+
+/// Poll MQTT `eventloop` forever, forwarding events to `events` until it is closed.
+async fn poll_events(mut eventloop: EventLoop, events: mpsc::UnboundedSender<Event>) {
+    loop {
+        match eventloop.poll().await {
+            Ok(event) => {
+                if events.send(event).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                warn!("MQTT connection error: {}, reconnecting", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+/// Task aborted when dropped.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+fn invalid(topic: &str, reason: &str) -> MqttBridgeError {
+    MqttBridgeError::InvalidCommand { topic: topic.to_owned(), reason: reason.to_owned() }
+}
+
+/// Stable object ID of `controller`.
+fn object_id(controller: &Controller) -> String {
+    let discriminator = if controller.serial.is_empty() { &controller.location } else { &controller.serial };
+    let mut id = String::new();
+    for c in format!("{} {}", controller.name, discriminator).chars() {
+        match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9') => id.push(c),
+            _ if !id.ends_with('_') => id.push('_'),
+            _ => {}
+        }
+    }
+    id.trim_matches('_').to_owned()
+}
+
+fn active_mode(controller: &Controller) -> Option<&Mode> {
+    usize::try_from(controller.active_mode).ok().and_then(|i| controller.modes.get(i))
+}
+
+fn is_black(color: &Color) -> bool {
+    color.r == 0 && color.g == 0 && color.b == 0
+}
+
+fn is_on(controller: &Controller) -> bool {
+    match active_mode(controller) {
+        Some(mode) if mode.name.eq_ignore_ascii_case("off") => false,
+        Some(mode) if mode.color_mode == Some(ColorMode::PerLED) => !controller.colors.iter().all(is_black),
+        _ => true,
+    }
+}
+
+/// Color currently displayed by `controller`, if any.
+fn current_color(controller: &Controller) -> Option<Color> {
+    match active_mode(controller) {
+        Some(mode) if mode.color_mode == Some(ColorMode::ModeSpecific) => mode.colors.first().copied(),
+        Some(mode) if mode.color_mode == Some(ColorMode::PerLED) => controller.colors.iter().find(|c| !is_black(c)).copied(),
+        _ => None,
+    }
+}
+
+/// Brightness range of `mode`, if it supports brightness.
+fn mode_brightness_range(mode: &Mode) -> Option<(u32, u32)> {
+    let flags: FlagSet<ModeFlag> = mode.flags;
+    match (flags.contains(ModeFlag::HasBrightness), mode.brightness_min, mode.brightness_max) {
+        (true, Some(min), Some(max)) if max > min => Some((min, max)),
+        _ => None,
+    }
+}
+
+/// Scale MQTT `brightness` (0 to 255) to mode brightness `range`.
+fn to_mode_brightness(brightness: u8, (min, max): (u32, u32)) -> u32 {
+    // computed in u64 so that large ranges do not overflow
+    let range = u64::from(max - min);
+    min + ((u64::from(brightness) * range + 127) / 255) as u32
+}
+
+/// Scale mode `brightness` within its `range` to MQTT brightness (0 to 255).
+fn from_mode_brightness(brightness: u32, (min, max): (u32, u32)) -> u8 {
+    let range = u64::from(max - min).max(1);
+    ((u64::from(brightness.clamp(min, max) - min) * 255 + range / 2) / range) as u8
+}
+
+fn scale(color: Color, brightness: u8) -> Color {
+    let scale = |c: u8| ((u16::from(c) * u16::from(brightness) + 127) / 255) as u8;
+    Color::new(scale(color.r), scale(color.g), scale(color.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+    use crate::DEFAULT_PROTOCOL;
+    use crate::data::{Color, ColorMode, Controller, fixtures, ModeFlag};
+    use crate::message::{Request, Response};
+    use crate::mock::MockBuilder;
+    use crate::mqtt::{from_mode_brightness, MqttBridge, MqttBridgeError, MqttPublisher, object_id, to_mode_brightness};
+    use crate::tests::setup;
+
+    #[derive(Default)]
+    struct Recorder {
+        published: Mutex<Vec<(String, String, bool)>>,
+        subscribed: Mutex<Vec<String>>,
+    }
+
+    impl Recorder {
+        fn take(&self) -> Vec<(String, String, bool)> {
+            std::mem::take(&mut *self.published.lock().unwrap())
+        }
+    }
+
+    #[async_trait]
+    impl MqttPublisher for Recorder {
+        async fn publish(&self, topic: &str, payload: String, retain: bool) -> Result<(), MqttBridgeError> {
+            self.published.lock().unwrap().push((topic.to_owned(), payload, retain));
+            Ok(())
+        }
+
+        async fn subscribe(&self, topic: &str) -> Result<(), MqttBridgeError> {
+            self.subscribed.lock().unwrap().push(topic.to_owned());
+            Ok(())
+        }
+    }
+
+    /// Controller with a per LED "Direct" mode and a mode specific "Static" mode with brightness.
+    fn controller(active_mode: i32) -> Controller {
+        let (_, mut controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let mut direct = controller.modes[0].clone();
+        direct.name = "Direct".to_string();
+        direct.flags = ModeFlag::HasPerLEDColor.into();
+        direct.color_mode = Some(ColorMode::PerLED);
+        direct.colors = vec![];
+        direct.brightness = None;
+        direct.brightness_min = None;
+        direct.brightness_max = None;
+        let mut fixed = controller.modes[0].clone();
+        fixed.name = "Static".to_string();
+        controller.modes = vec![direct, fixed];
+        controller.active_mode = active_mode;
+        controller
+    }
+
+    fn payload(published: &[(String, String, bool)], topic: &str) -> Value {
+        let (_, payload, _) = published.iter().find(|(t, _, _)| t == topic).unwrap_or_else(|| panic!("nothing published on {}", topic));
+        serde_json::from_str(payload).unwrap()
+    }
+
+    #[test]
+    fn test_object_id() {
+        assert_eq!(object_id(&controller(0)), "keyboard_hid");
+    }
+
+    #[test]
+    fn test_brightness_scaling() {
+        assert_eq!(to_mode_brightness(128, (0, 100)), 50);
+        assert_eq!(from_mode_brightness(50, (0, 100)), 128);
+        assert_eq!(to_mode_brightness(255, (0, u32::MAX)), u32::MAX);
+        assert_eq!(to_mode_brightness(0, (1, u32::MAX)), 1);
+        assert_eq!(from_mode_brightness(u32::MAX, (0, u32::MAX)), 255);
+        assert_eq!(from_mode_brightness(u32::MAX / 2 + 1, (0, u32::MAX)), 128);
+    }
+
+    #[tokio::test]
+    async fn test_announce() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let bridge = MqttBridge::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(1))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller(1)))
+            .to_client().await?);
+        let mqtt = Recorder::default();
+
+        bridge.announce(&mqtt).await?;
+
+        assert_eq!(*mqtt.subscribed.lock().unwrap(), vec!["openrgb/+/set".to_string(), "homeassistant/status".to_string()]);
+        let published = mqtt.take();
+        let config = payload(&published, "homeassistant/light/openrgb/keyboard_hid/config");
+        assert_eq!(config["command_topic"], "openrgb/keyboard_hid/set");
+        assert_eq!(config["effect_list"], json!(["Direct", "Static"]));
+        assert_eq!(config["device"]["manufacturer"], "ACME");
+        assert!(published.contains(&("openrgb/status".to_string(), "online".to_string(), true)));
+        assert_eq!(payload(&published, "openrgb/keyboard_hid/state"), json!({
+            "state": "ON",
+            "brightness": 204,
+            "color": { "r": 255, "g": 0, "b": 0 },
+            "color_mode": "rgb",
+            "effect": "Static",
+        }));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_commands() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let mut fixed = controller(1).modes[1].clone();
+        fixed.colors = vec![Color::new(0, 0, 255)];
+        fixed.brightness = Some(50);
+        let mut off = controller(0);
+        off.colors = vec![Color::new(0, 0, 0); 2];
+
+        let bridge = MqttBridge::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(1))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller(1)))
+            // color and brightness on mode specific mode
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller(1)))
+            .expect(Request::UpdateMode { controller: 0, mode_id: 1, mode: fixed.clone() })
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller(1)))
+            // off without "Off" mode
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller(1)))
+            .expect(Request::SetCustomMode { controller: 0 })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![Color::new(0, 0, 0); 2] })
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(off.clone()))
+            // on restores saved state
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(off))
+            .expect(Request::UpdateMode { controller: 0, mode_id: 1, mode: controller(1).modes[1].clone() })
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller(1)))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller(1)))
+            // unknown effect
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller(1)))
+            .to_client().await?);
+        let mqtt = Recorder::default();
+        bridge.announce(&mqtt).await?;
+        mqtt.take();
+
+        bridge.handle_message(&mqtt, "openrgb/keyboard_hid/set", r#"{"state": "ON", "color": {"r": 0, "g": 0, "b": 255}, "brightness": 128}"#).await?;
+        bridge.handle_message(&mqtt, "openrgb/keyboard_hid/set", r#"{"state": "OFF"}"#).await?;
+        assert_eq!(payload(&mqtt.take(), "openrgb/keyboard_hid/state")["state"], "OFF");
+        bridge.handle_message(&mqtt, "openrgb/keyboard_hid/set", r#"{"state": "ON"}"#).await?;
+        assert_eq!(payload(&mqtt.take(), "openrgb/keyboard_hid/state")["state"], "ON");
+
+        assert!(matches!(
+            bridge.handle_message(&mqtt, "openrgb/unknown/set", r#"{"state": "ON"}"#).await,
+            Err(MqttBridgeError::InvalidCommand { .. })
+        ));
+        assert!(matches!(
+            bridge.handle_message(&mqtt, "openrgb/keyboard_hid/set", r#"{"effect": "Rainbow"}"#).await,
+            Err(MqttBridgeError::InvalidCommand { .. })
+        ));
+
+        Ok(())
+    }
+
+    /// Read an MQTT packet, returning its first byte and payload.
+    async fn read_mqtt_packet(stream: &mut (impl AsyncRead + Unpin)) -> std::io::Result<(u8, Vec<u8>)> {
+        let kind = stream.read_u8().await?;
+        let mut len = 0;
+        for shift in (0..28).step_by(7) {
+            let byte = stream.read_u8().await?;
+            len |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload).await?;
+        Ok((kind, payload))
+    }
+
+    #[tokio::test]
+    async fn test_run_more_lights_than_client_capacity() -> Result<(), Box<dyn Error>> {
+        use std::time::Duration;
+
+        use rumqttc::{AsyncClient, MqttOptions};
+        use tokio::net::TcpListener;
+
+        setup()?;
+
+        let lights = 4;
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol();
+        mock.expect(Request::ControllerCount).respond(Response::ControllerCount(lights));
+        for id in 0..lights {
+            let mut controller = controller(1);
+            controller.serial = format!("{}", id);
+            mock.expect(Request::ControllerData { controller: id }).respond(Response::ControllerData(controller));
+        }
+        let bridge = MqttBridge::new(mock.to_client().await?);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let broker = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            let (connect, _) = read_mqtt_packet(&mut stream).await?;
+            assert_eq!(connect, 0x10);
+            stream.write_all(&[0x20, 0x02, 0x00, 0x00]).await?;
+            let mut published = 0;
+            while published < 2 * lights + 1 {
+                let (kind, payload) = read_mqtt_packet(&mut stream).await?;
+                match kind >> 4 {
+                    // acknowledge QoS 1 publish with its packet ID, following topic
+                    3 => {
+                        let topic_len = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+                        stream.write_all(&[0x40, 0x02, payload[2 + topic_len], payload[3 + topic_len]]).await?;
+                        published += 1;
+                    }
+                    8 => stream.write_all(&[0x90, 0x03, payload[0], payload[1], 0x01]).await?,
+                    _ => {}
+                }
+            }
+            Ok::<_, std::io::Error>(published)
+        });
+
+        // much less room than the 2N+3 requests of an announce
+        let (mqtt, eventloop) = AsyncClient::new(MqttOptions::new("openrgb-bridge-test", "127.0.0.1", port), 2);
+        let run = tokio::spawn(async move { bridge.run(mqtt, eventloop, Duration::from_secs(3600)).await });
+        let published = tokio::time::timeout(Duration::from_secs(10), broker).await???;
+        assert_eq!(published, 2 * lights + 1);
+        run.abort();
+
+        Ok(())
+    }
+
+    /// Run against a local broker with `MQTT_BROKER=localhost:1883 cargo test --features mqtt -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn test_broker() -> Result<(), Box<dyn Error>> {
+        use std::time::Duration;
+
+        use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+
+        setup()?;
+
+        let broker = std::env::var("MQTT_BROKER").unwrap_or_else(|_| "localhost:1883".to_string());
+        let (host, port) = broker.rsplit_once(':').ok_or("invalid MQTT_BROKER")?;
+
+        let bridge = MqttBridge::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(1))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller(1)))
+            .to_client().await?)
+            .base_topic(format!("openrgb-test-{}", std::process::id()));
+        let (mqtt, eventloop) = AsyncClient::new(MqttOptions::new("openrgb-bridge-test", host, port.parse()?), 16);
+
+        let (observer, mut events) = AsyncClient::new(MqttOptions::new("openrgb-observer-test", host, port.parse()?), 16);
+        observer.subscribe(format!("openrgb-test-{}/+/state", std::process::id()), QoS::AtLeastOnce).await?;
+        loop {
+            if let Event::Incoming(Packet::SubAck(_)) = events.poll().await? {
+                break;
+            }
+        }
+
+        let run = tokio::spawn(async move { bridge.run(mqtt, eventloop, Duration::from_secs(3600)).await });
+        let state = loop {
+            if let Event::Incoming(Packet::Publish(publish)) = events.poll().await? {
+                break serde_json::from_slice::<Value>(&publish.payload)?;
+            }
+        };
+        assert_eq!(state["effect"], "Static");
+        run.abort();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,834 @@
+//! Output filters applied by the client to outgoing colors and modes.
+//!
+//! An [OutputFilter] added with [OpenRGB::add_output_filter] sees, and may change, every color sent with
+//! [OpenRGB::update_leds], [OpenRGB::update_zone_leds] and [OpenRGB::update_led], and every mode sent with
+//! [OpenRGB::update_mode]. Filters are shared by clients sharing a connection, and applied in the order they were
+//! added.
+//!
+//! The client remembers colors and modes it sent before filtering: [captured state](crate::state),
+//! [transitions](crate::transition) and [alerts](crate::alert) start from them rather than from filtered values read
+//! back from the server, so that restoring or fading applies filters once (eg: does not halve brightness on each capture
+//! and restore).
+//!
+//! Provided filters:
+//! * [Brightness]: global and per-controller brightness master,
+//! * [PowerLimiter]: current estimator scaling down frames that would exceed a power supply budget,
+//! * [Photosensitivity]: flash frequency limiter for photosensitive users.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::output::{Brightness, PowerLimiter};
+//! # use std::error::Error;
+//! # use std::sync::Arc;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let client = OpenRGB::connect().await?;
+//!
+//! let brightness = Arc::new(Brightness::new());
+//! client.add_output_filter(brightness.clone());
+//! // 5V 4A supply, 60 mA per LED at full white
+//! client.add_output_filter(Arc::new(PowerLimiter::new(4000.0).strip(0, 20.0)));
+//!
+//! brightness.set_global(0.5);
+//! #
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [OpenRGB::add_output_filter]: crate::OpenRGB::add_output_filter
+//! [OpenRGB::update_leds]: crate::OpenRGB::update_leds
+//! [OpenRGB::update_zone_leds]: crate::OpenRGB::update_zone_leds
+//! [OpenRGB::update_led]: crate::OpenRGB::update_led
+//! [OpenRGB::update_mode]: crate::OpenRGB::update_mode
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use flagset::FlagSet;
+use log::{debug, warn};
+use tokio::time::Instant;
+
+use crate::data::{Color, ColorMode, Controller, Mode, ModeFlag};
+use crate::transition::{from_linear, to_linear};
+
+/// Part of a controller outgoing colors are sent to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum OutputTarget {
+    /// All controller LEDs ([update_leds](crate::OpenRGB::update_leds)).
+    Controller,
+
+    /// LEDs of a zone ([update_zone_leds](crate::OpenRGB::update_zone_leds)).
+    Zone(u32),
+
+    /// Single LED ([update_led](crate::OpenRGB::update_led)).
+    Led(i32),
+}
+
+/// Filter applied to outgoing colors and modes, see [module documentation](self).
+pub trait OutputFilter: Send + Sync {
+    /// Filter `colors` about to be sent to `target` of controller `controller_id`.
+    fn filter_colors(&self, _controller_id: u32, _target: OutputTarget, _colors: &mut [Color]) {}
+
+    /// Filter `mode` about to be sent as mode `mode_id` of controller `controller_id`.
+    fn filter_mode(&self, _controller_id: u32, _mode_id: i32, _mode: &mut Mode) {}
+}
+
+/// Global and per-controller brightness master.
+///
+/// Colors are scaled by the global factor times the controller factor. Modes supporting brightness
+/// ([ModeFlag::HasBrightness]) get their brightness scaled within `brightness_min` and `brightness_max`, other modes get
+/// their colors scaled.
+#[derive(Debug)]
+pub struct Brightness {
+    factors: Mutex<(f32, HashMap<u32, f32>)>,
+}
+
+impl Default for Brightness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Brightness {
+    /// Build a brightness master at full brightness.
+    pub fn new() -> Self {
+        Self {
+            factors: Mutex::new((1.0, HashMap::new())),
+        }
+    }
+
+    /// Set global brightness, between 0 and 1.
+    pub fn set_global(&self, brightness: f32) {
+        self.factors.lock().unwrap().0 = brightness.clamp(0.0, 1.0);
+    }
+
+    /// Global brightness.
+    pub fn global(&self) -> f32 {
+        self.factors.lock().unwrap().0
+    }
+
+    /// Set brightness of controller `controller_id`, between 0 and 1.
+    pub fn set_controller(&self, controller_id: u32, brightness: f32) {
+        self.factors.lock().unwrap().1.insert(controller_id, brightness.clamp(0.0, 1.0));
+    }
+
+    /// Brightness of controller `controller_id`, without global brightness.
+    pub fn controller(&self, controller_id: u32) -> f32 {
+        self.factors.lock().unwrap().1.get(&controller_id).copied().unwrap_or(1.0)
+    }
+
+    /// Effective brightness of controller `controller_id`.
+    pub fn factor(&self, controller_id: u32) -> f32 {
+        let factors = self.factors.lock().unwrap();
+        factors.0 * factors.1.get(&controller_id).copied().unwrap_or(1.0)
+    }
+}
+
+impl OutputFilter for Brightness {
+    fn filter_colors(&self, controller_id: u32, _target: OutputTarget, colors: &mut [Color]) {
+        scale(colors, self.factor(controller_id));
+    }
+
+    fn filter_mode(&self, controller_id: u32, _mode_id: i32, mode: &mut Mode) {
+        let factor = self.factor(controller_id);
+        let flags: FlagSet<ModeFlag> = mode.flags;
+        match (mode.brightness, mode.brightness_min, mode.brightness_max) {
+            (Some(brightness), Some(min), Some(max)) if flags.contains(ModeFlag::HasBrightness) && max > min => {
+                let brightness = brightness.clamp(min, max) - min;
+                mode.brightness = Some(min + (brightness as f32 * factor).round() as u32);
+            }
+            _ => scale(&mut mode.colors, factor),
+        }
+    }
+}
+
+/// Power budget limiter.
+///
+/// Current drawn by each configured LED strip (controller) is estimated from its colors, each channel drawing a
+/// configured current at full intensity, proportionally to its value. When a frame would make total estimated current
+/// of all strips exceed the budget, it is scaled down to fit in what is left by the other strips.
+///
+/// Zone and single LED updates are accounted separately from whole controller updates, until the next whole controller
+/// update of the same controller. Controllers without configuration are not limited.
+///
+/// Hardware modes using [mode specific colors](ColorMode::ModeSpecific) are estimated as all LEDs showing the brightest
+/// mode color, and their colors scaled down the same way, for controllers with a known LED count (see
+/// [PowerLimiter::zones]). Current drawn by other hardware modes cannot be estimated and is not accounted.
+#[derive(Debug)]
+pub struct PowerLimiter {
+    budget_ma: f32,
+    strips: HashMap<u32, f32>,
+    leds: HashMap<u32, usize>,
+    drawn: Mutex<HashMap<(u32, OutputTarget), f32>>,
+}
+
+impl PowerLimiter {
+    /// Build a limiter for a power supply budget of `budget_ma` milliamps.
+    pub fn new(budget_ma: f32) -> Self {
+        Self {
+            budget_ma,
+            strips: HashMap::new(),
+            leds: HashMap::new(),
+            drawn: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Configure controller `controller_id` LEDs as drawing `ma_per_channel` milliamps per channel at full intensity.
+    pub fn strip(mut self, controller_id: u32, ma_per_channel: f32) -> Self {
+        self.strips.insert(controller_id, ma_per_channel);
+        self
+    }
+
+    /// Set LED counts of zones of controller `controller_id` (eg: from [Zone::leds_count](crate::data::Zone::leds_count)),
+    /// to limit its hardware modes.
+    pub fn zones(mut self, controller_id: u32, zone_sizes: impl IntoIterator<Item=u32>) -> Self {
+        self.leds.insert(controller_id, zone_sizes.into_iter().map(|size| size as usize).sum());
+        self
+    }
+
+    /// Power supply budget, in milliamps.
+    pub fn budget_ma(&self) -> f32 {
+        self.budget_ma
+    }
+
+    /// Estimated current drawn by last sent colors, in milliamps.
+    pub fn drawn_ma(&self) -> f32 {
+        self.drawn.lock().unwrap().values().sum()
+    }
+}
+
+impl OutputFilter for PowerLimiter {
+    fn filter_colors(&self, controller_id: u32, target: OutputTarget, colors: &mut [Color]) {
+        let ma_per_channel = match self.strips.get(&controller_id) {
+            Some(ma) => *ma,
+            None => return,
+        };
+        let estimate = |colors: &[Color]| colors.iter()
+            .map(|c| (u32::from(c.r) + u32::from(c.g) + u32::from(c.b)) as f32 / 255.0 * ma_per_channel)
+            .sum::<f32>();
+
+        let mut drawn = self.drawn.lock().unwrap();
+        if target == OutputTarget::Controller {
+            drawn.retain(|(id, _), _| *id != controller_id);
+        }
+        let others: f32 = drawn.iter().filter(|(key, _)| **key != (controller_id, target)).map(|(_, ma)| ma).sum();
+        let available = (self.budget_ma - others).max(0.0);
+        let mut current = estimate(colors);
+        if current > available {
+            debug!("Scaling down controller {} {:?} frame drawing {:.0} mA to {:.0} mA", controller_id, target, current, available);
+            scale_down(colors, available / current);
+            current = estimate(colors);
+        }
+        drawn.insert((controller_id, target), current);
+    }
+
+    fn filter_mode(&self, controller_id: u32, mode_id: i32, mode: &mut Mode) {
+        let (ma_per_channel, leds) = match (self.strips.get(&controller_id), self.leds.get(&controller_id)) {
+            (Some(ma), Some(leds)) => (*ma, *leds),
+            _ => return,
+        };
+        // per LED modes draw what next LED updates will send
+        if mode.color_mode == Some(ColorMode::PerLED) {
+            return;
+        }
+        let mut drawn = self.drawn.lock().unwrap();
+        drawn.retain(|(id, _), _| *id != controller_id);
+        if mode.color_mode != Some(ColorMode::ModeSpecific) || mode.colors.is_empty() {
+            debug!("Cannot estimate current drawn by mode {} ({:?}) of controller {}", mode_id, mode.name, controller_id);
+            return;
+        }
+        let estimate = |colors: &[Color]| colors.iter()
+            .map(|c| (u32::from(c.r) + u32::from(c.g) + u32::from(c.b)) as f32 / 255.0 * ma_per_channel * leds as f32)
+            .fold(0.0, f32::max);
+
+        let available = (self.budget_ma - drawn.values().sum::<f32>()).max(0.0);
+        let mut current = estimate(&mode.colors);
+        if current > available {
+            debug!("Scaling down mode {} ({:?}) of controller {} drawing {:.0} mA to {:.0} mA", mode_id, mode.name, controller_id, current, available);
+            scale_down(&mut mode.colors, available / current);
+            current = estimate(&mode.colors);
+        }
+        drawn.insert((controller_id, OutputTarget::Controller), current);
+    }
+}
+
+/// Scale `colors` by `factor`, truncating so that scaled colors stay within a budget.
+fn scale_down(colors: &mut [Color], factor: f32) {
+    for color in colors {
+        *color = Color::new((color.r as f32 * factor) as u8, (color.g as f32 * factor) as u8, (color.b as f32 * factor) as u8);
+    }
+}
+
+/// Photosensitivity-safe filter, following [WCAG 2.3.1 Three Flashes or Below Threshold](https://www.w3.org/WAI/WCAG22/Understanding/three-flashes-or-below-threshold).
+///
+/// Outgoing colors are analyzed over time for each LED. A flash is a pair of opposing transitions of:
+/// * relative luminance, changing by at least 10% while the darker state is below 80%,
+/// * or saturated red (red being at least 80% of the color), `(R - G - B) * 320` changing by more than 20 (linear
+///   values).
+///
+/// Each LED is kept under 3 flashes of each kind in any second (at most 5 transitions): once its budget is spent,
+/// further changes are smoothed, only moving the LED partway to its target so that no transition happens, until older
+/// transitions leave the one second window.
+///
+/// LEDs are tracked by index in their controller, whether they are updated as a whole controller, zone or single LED,
+/// as long as zone sizes of the controller are known (see [Photosensitivity::zones]). Zone updates of other controllers
+/// are tracked separately.
+///
+/// Hardware modes are not rendered by the client and cannot be filtered, [update_mode](crate::OpenRGB::update_mode)
+/// logs a warning for modes with a speed in the upper part of their range instead.
+#[derive(Debug)]
+pub struct Photosensitivity {
+    max_speed: f32,
+    zones: HashMap<u32, Vec<u32>>,
+    leds: Mutex<HashMap<LedKey, LedHistory>>,
+}
+
+/// Controller ID, zone ID if LED index is relative to an unknown zone, and LED index.
+type LedKey = (u32, Option<u32>, usize);
+
+/// Maximum number of transitions per LED in any [FLASH_WINDOW].
+const MAX_TRANSITIONS: usize = 5;
+
+const FLASH_WINDOW: Duration = Duration::from_secs(1);
+
+const LUMINANCE_THRESHOLD: f32 = 0.1;
+
+const RED_THRESHOLD: f32 = 20.0;
+
+impl Default for Photosensitivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Photosensitivity {
+    /// Build a filter warning about mode speeds above half of their range.
+    pub fn new() -> Self {
+        Self {
+            max_speed: 0.5,
+            zones: HashMap::new(),
+            leds: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set fraction of a mode speed range, between 0 and 1, above which [update_mode](crate::OpenRGB::update_mode)
+    /// logs a warning.
+    pub fn max_speed(mut self, fraction: f32) -> Self {
+        self.max_speed = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set LED counts of zones of controller `controller_id`, in zone order (eg: from
+    /// [Zone::leds_count](crate::data::Zone::leds_count)).
+    pub fn zones(mut self, controller_id: u32, zone_sizes: impl IntoIterator<Item=u32>) -> Self {
+        self.zones.insert(controller_id, zone_sizes.into_iter().collect());
+        self
+    }
+
+    /// History key of LED `index` of `target` of controller `controller_id`.
+    fn led_key(&self, controller_id: u32, target: OutputTarget, index: usize) -> LedKey {
+        match target {
+            OutputTarget::Controller => (controller_id, None, index),
+            OutputTarget::Led(led) => (controller_id, None, usize::try_from(led).unwrap_or_default() + index),
+            OutputTarget::Zone(zone) => {
+                let offset = self.zones.get(&controller_id)
+                    .and_then(|sizes| sizes.get(..zone as usize))
+                    .map(|sizes| sizes.iter().map(|&size| size as usize).sum::<usize>());
+                match offset {
+                    Some(offset) => (controller_id, None, offset + index),
+                    None => (controller_id, Some(zone), index),
+                }
+            }
+        }
+    }
+
+    /// Whether `mode` speed is above configured fraction of its range.
+    pub fn is_too_fast(&self, mode: &Mode) -> bool {
+        let flags: FlagSet<ModeFlag> = mode.flags;
+        match (mode.speed, mode.speed_min, mode.speed_max) {
+            (Some(speed), Some(min), Some(max)) if flags.contains(ModeFlag::HasSpeed) && min != max => {
+                // some devices have inverted speed ranges
+                let fraction = (speed as f32 - min as f32) / (max as f32 - min as f32);
+                fraction > self.max_speed
+            }
+            _ => false,
+        }
+    }
+}
+
+impl OutputFilter for Photosensitivity {
+    fn filter_colors(&self, controller_id: u32, target: OutputTarget, colors: &mut [Color]) {
+        let now = Instant::now();
+        let mut leds = self.leds.lock().unwrap();
+        for (i, color) in colors.iter_mut().enumerate() {
+            let history = leds.entry(self.led_key(controller_id, target, i)).or_insert_with(|| LedHistory::new(*color));
+            let filtered = history.filter(*color, now);
+            if filtered != *color {
+                debug!("Smoothing LED {} of controller {} {:?} from {:?} to {:?}", i, controller_id, target, color, filtered);
+            }
+            *color = filtered;
+        }
+    }
+
+    fn filter_mode(&self, controller_id: u32, _mode_id: i32, mode: &mut Mode) {
+        if self.is_too_fast(mode) {
+            warn!(
+                "Mode {:?} of controller {} uses speed {:?} in {:?}..{:?}, hardware effects cannot be filtered for photosensitivity",
+                mode.name, controller_id, mode.speed.unwrap_or_default(), mode.speed_min.unwrap_or_default(), mode.speed_max.unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// Output history of a LED, in linear light.
+#[derive(Debug)]
+struct LedHistory {
+    last: [f32; 3],
+    luminance: Transitions,
+    red: Transitions,
+}
+
+impl LedHistory {
+    fn new(color: Color) -> Self {
+        let last = [to_linear(color.r), to_linear(color.g), to_linear(color.b)];
+        Self {
+            last,
+            luminance: Transitions::new(luminance(last)),
+            red: Transitions::new(red(last)),
+        }
+    }
+
+    /// Color to output instead of `color`.
+    fn filter(&mut self, color: Color, now: Instant) -> Color {
+        let target = [to_linear(color.r), to_linear(color.g), to_linear(color.b)];
+        self.luminance.expire(now);
+        self.red.expire(now);
+
+        // mixing in linear light, luminance and red values change linearly
+        let mut t = self.luminance.allowed(luminance(self.last), luminance(target), LUMINANCE_THRESHOLD);
+        if is_saturated_red(self.last) || is_saturated_red(target) {
+            t = t.min(self.red.allowed(red(self.last), red(target), RED_THRESHOLD));
+        }
+        let output = if t >= 1.0 { target } else { [0, 1, 2].map(|c| self.last[c] + (target[c] - self.last[c]) * t) };
+
+        let l = luminance(output);
+        self.luminance.record(l, LUMINANCE_THRESHOLD, l.min(self.luminance.reference) < 0.8, now);
+        let saturated = is_saturated_red(self.last) || is_saturated_red(output);
+        self.red.record(red(output), RED_THRESHOLD, saturated, now);
+        self.last = output;
+
+        if t >= 1.0 { color } else { Color::new(from_linear(output[0]), from_linear(output[1]), from_linear(output[2])) }
+    }
+}
+
+/// Transitions of a value, see [Photosensitivity].
+#[derive(Debug)]
+struct Transitions {
+    /// Value at last extreme.
+    reference: f32,
+    /// Direction of last transition, 0 before first transition.
+    direction: f32,
+    times: VecDeque<Instant>,
+}
+
+impl Transitions {
+    fn new(value: f32) -> Self {
+        Self {
+            reference: value,
+            direction: 0.0,
+            times: VecDeque::new(),
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self.times.front().is_some_and(|t| now.duration_since(*t) >= FLASH_WINDOW) {
+            self.times.pop_front();
+        }
+    }
+
+    /// Fraction of change from `from` to `to` allowed without exceeding transitions budget.
+    fn allowed(&self, from: f32, to: f32, threshold: f32) -> f32 {
+        if self.times.len() < MAX_TRANSITIONS || to == from {
+            return 1.0;
+        }
+        // continuing in direction of last transition only moves the extreme
+        let margin = threshold * 0.99;
+        let (low, high) = match self.direction {
+            d if d > 0.0 => (self.reference - margin, f32::INFINITY),
+            d if d < 0.0 => (f32::NEG_INFINITY, self.reference + margin),
+            _ => (self.reference - margin, self.reference + margin),
+        };
+        let bound = if to > from { high } else { low };
+        ((bound - from) / (to - from)).clamp(0.0, 1.0)
+    }
+
+    /// Record output `value`, counting a transition if it changed by `threshold` from last extreme in the opposite
+    /// direction and the change is `significant`.
+    fn record(&mut self, value: f32, threshold: f32, significant: bool, now: Instant) {
+        let delta = value - self.reference;
+        if self.direction != 0.0 && delta * self.direction > 0.0 {
+            self.reference = value;
+        } else if delta.abs() >= threshold && significant {
+            self.times.push_back(now);
+            self.direction = delta.signum();
+            self.reference = value;
+        }
+    }
+}
+
+fn luminance([r, g, b]: [f32; 3]) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+fn red([r, g, b]: [f32; 3]) -> f32 {
+    (r - g - b) * 320.0
+}
+
+fn is_saturated_red([r, g, b]: [f32; 3]) -> bool {
+    r > 0.0 && r / (r + g + b) >= 0.8
+}
+
+/// Colors and modes last sent to controllers, before and after output filters, see [module documentation](self).
+#[derive(Debug, Default)]
+pub(crate) struct SentFrames {
+    controllers: Mutex<HashMap<u32, SentFrame>>,
+}
+
+#[derive(Debug, Default)]
+struct SentFrame {
+    /// Color updates since last whole controller update included, in order, with unfiltered and filtered colors.
+    colors: Vec<(OutputTarget, Vec<Color>, Vec<Color>)>,
+
+    /// Last mode update, with unfiltered and filtered mode.
+    mode: Option<(i32, Mode, Mode)>,
+}
+
+impl SentFrames {
+    /// Record `colors` sent to `target` of controller `controller_id` as `filtered`.
+    pub(crate) fn colors_sent(&self, controller_id: u32, target: OutputTarget, colors: Vec<Color>, filtered: Vec<Color>) {
+        let mut controllers = self.controllers.lock().unwrap();
+        let frame = controllers.entry(controller_id).or_default();
+        match target {
+            OutputTarget::Controller => frame.colors.clear(),
+            _ => frame.colors.retain(|(t, _, _)| *t != target),
+        }
+        frame.colors.push((target, colors, filtered));
+    }
+
+    /// Record `mode` sent as mode `mode_id` of controller `controller_id` as `filtered`.
+    pub(crate) fn mode_sent(&self, controller_id: u32, mode_id: i32, mode: Mode, filtered: Mode) {
+        self.controllers.lock().unwrap().entry(controller_id).or_default().mode = Some((mode_id, mode, filtered));
+    }
+
+    /// Replace colors and active mode of `controller` read from server with the ones sent before filters, where they
+    /// still are what was sent.
+    pub(crate) fn unfilter(&self, controller_id: u32, controller: &mut Controller) {
+        let controllers = self.controllers.lock().unwrap();
+        let frame = match controllers.get(&controller_id) {
+            Some(frame) => frame,
+            None => return,
+        };
+        for (target, colors, filtered) in &frame.colors {
+            let (offset, len) = match *target {
+                OutputTarget::Controller => (0, colors.len()),
+                OutputTarget::Led(led) => match usize::try_from(led) {
+                    Ok(led) => (led, 1),
+                    Err(_) => continue,
+                },
+                OutputTarget::Zone(zone) => match controller.zones.get(zone as usize) {
+                    Some(z) => (controller.zones[..zone as usize].iter().map(|z| z.leds_count as usize).sum(), z.leds_count as usize),
+                    None => continue,
+                },
+            };
+            for ((current, color), filtered) in controller.colors.iter_mut().skip(offset).take(len).zip(colors).zip(filtered) {
+                if current == filtered {
+                    *current = *color;
+                }
+            }
+        }
+        if let Some((mode_id, mode, filtered)) = &frame.mode {
+            if *mode_id == controller.active_mode {
+                if let Some(current) = usize::try_from(*mode_id).ok().and_then(|i| controller.modes.get_mut(i)) {
+                    if current == filtered {
+                        *current = mode.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn scale(colors: &mut [Color], factor: f32) {
+    if factor >= 1.0 {
+        return;
+    }
+    let scale = |c: u8| (f32::from(c) * factor).round() as u8;
+    for color in colors {
+        *color = Color::new(scale(color.r), scale(color.g), scale(color.b));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::data::{Color, ColorMode, fixtures};
+    use crate::DEFAULT_PROTOCOL;
+    use crate::message::Request;
+    use crate::mock::MockBuilder;
+    use crate::output::{Brightness, OutputFilter, OutputTarget, Photosensitivity, PowerLimiter, SentFrames};
+    use crate::tests::setup;
+
+    #[test]
+    fn test_brightness() {
+        let (_, mode) = fixtures::mode(DEFAULT_PROTOCOL);
+        let brightness = Brightness::new();
+        brightness.set_global(0.5);
+        brightness.set_controller(1, 0.5);
+        assert_eq!(brightness.factor(0), 0.5);
+        assert_eq!(brightness.factor(1), 0.25);
+
+        let mut colors = [Color::new(255, 100, 0)];
+        brightness.filter_colors(0, OutputTarget::Controller, &mut colors);
+        assert_eq!(colors, [Color::new(128, 50, 0)]);
+
+        // brightness 80 within 0..100
+        let mut scaled = mode.clone();
+        brightness.filter_mode(1, 0, &mut scaled);
+        assert_eq!(scaled.brightness, Some(20));
+        assert_eq!(scaled.colors, mode.colors);
+
+        // no brightness support, colors are scaled
+        let (_, mut mode) = fixtures::mode(2);
+        brightness.filter_mode(0, 0, &mut mode);
+        assert_eq!(mode.brightness, None);
+        assert_eq!(mode.colors, vec![Color::new(128, 0, 0)]);
+    }
+
+    #[test]
+    fn test_power_limiter() {
+        let limiter = PowerLimiter::new(300.0).strip(0, 20.0).strip(1, 10.0);
+
+        // 4 white LEDs draw 240 mA
+        let mut colors = [Color::new(255, 255, 255); 4];
+        limiter.filter_colors(0, OutputTarget::Controller, &mut colors);
+        assert_eq!(colors, [Color::new(255, 255, 255); 4]);
+
+        // 120 mA more would exceed budget, scaled to remaining 60 mA
+        let mut colors = [Color::new(255, 255, 255); 4];
+        limiter.filter_colors(1, OutputTarget::Controller, &mut colors);
+        assert_eq!(colors, [Color::new(127, 127, 127); 4]);
+        assert!(limiter.drawn_ma() <= 300.0);
+
+        // dimming first strip frees budget
+        let mut colors = [Color::new(0, 0, 0); 4];
+        limiter.filter_colors(0, OutputTarget::Controller, &mut colors);
+        let mut colors = [Color::new(255, 255, 255); 4];
+        limiter.filter_colors(1, OutputTarget::Controller, &mut colors);
+        assert_eq!(colors, [Color::new(255, 255, 255); 4]);
+
+        // unconfigured controllers are not limited
+        let mut colors = [Color::new(255, 255, 255); 100];
+        limiter.filter_colors(2, OutputTarget::Controller, &mut colors);
+        assert_eq!(colors, [Color::new(255, 255, 255); 100]);
+    }
+
+    #[test]
+    fn test_power_limiter_modes() {
+        let limiter = PowerLimiter::new(300.0).strip(0, 20.0).zones(0, [2, 2]).strip(1, 10.0);
+        let (_, mut mode) = fixtures::mode(DEFAULT_PROTOCOL);
+        mode.colors = vec![Color::new(255, 0, 0), Color::new(255, 255, 255)];
+
+        // 4 LEDs showing white at most draw 240 mA, scaled to remaining 180 mA
+        let mut colors = [Color::new(255, 255, 255); 4];
+        limiter.filter_colors(1, OutputTarget::Controller, &mut colors);
+        let mut limited = mode.clone();
+        limiter.filter_mode(0, 0, &mut limited);
+        assert_eq!(limited.colors, [Color::new(191, 0, 0), Color::new(191, 191, 191)]);
+        assert!(limiter.drawn_ma() <= 300.0);
+
+        // mode replaces colors sent before
+        let mut colors = [Color::new(255, 255, 255); 4];
+        limiter.filter_colors(0, OutputTarget::Controller, &mut colors);
+        let mut limited = mode.clone();
+        limiter.filter_mode(0, 0, &mut limited);
+        assert_eq!(limited.colors, [Color::new(191, 0, 0), Color::new(191, 191, 191)]);
+
+        // per LED modes are limited by LED updates
+        let mut limited = mode.clone();
+        limited.color_mode = Some(ColorMode::PerLED);
+        limiter.filter_mode(0, 0, &mut limited);
+        assert_eq!(limited.colors, mode.colors);
+
+        // controllers with unknown LED count are not limited
+        let mut limited = mode.clone();
+        limiter.filter_mode(1, 0, &mut limited);
+        assert_eq!(limited.colors, mode.colors);
+    }
+
+    #[test]
+    fn test_sent_frames() {
+        let (_, mut controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let (black, white, gray) = (Color::new(0, 0, 0), Color::new(255, 255, 255), Color::new(128, 128, 128));
+        let sent = SentFrames::default();
+        sent.colors_sent(0, OutputTarget::Controller, vec![white, white], vec![gray, gray]);
+        sent.colors_sent(0, OutputTarget::Led(1), vec![black], vec![black]);
+        let mut dimmed = controller.modes[0].clone();
+        dimmed.brightness = Some(40);
+        sent.mode_sent(0, 0, controller.modes[0].clone(), dimmed.clone());
+
+        let mut read = controller.clone();
+        read.colors = vec![gray, black];
+        read.modes[0] = dimmed.clone();
+        sent.unfilter(0, &mut read);
+        assert_eq!(read.colors, [white, black]);
+        assert_eq!(read.modes, controller.modes);
+
+        // colors and modes changed by other clients are kept
+        controller.colors = vec![black, white];
+        let mut read = controller.clone();
+        sent.unfilter(0, &mut read);
+        assert_eq!(read, controller);
+
+        // zone updates replace previous LED update
+        sent.colors_sent(0, OutputTarget::Zone(0), vec![white, white], vec![gray, gray]);
+        let mut read = controller.clone();
+        read.colors = vec![gray, gray];
+        sent.unfilter(0, &mut read);
+        assert_eq!(read.colors, [white, white]);
+    }
+
+    /// Send `frames` 100 ms apart to `filter`, returning filtered frames.
+    async fn play(filter: &Photosensitivity, frames: &[Color]) -> Vec<Color> {
+        let mut output = Vec::new();
+        for frame in frames {
+            let mut colors = [*frame];
+            filter.filter_colors(0, OutputTarget::Controller, &mut colors);
+            output.push(colors[0]);
+            tokio::time::advance(Duration::from_millis(100)).await;
+        }
+        output
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_photosensitivity_flashes() {
+        let (black, white) = (Color::new(0, 0, 0), Color::new(255, 255, 255));
+        let filter = Photosensitivity::new();
+
+        // 5 transitions pass in a second, further ones are smoothed
+        let frames: Vec<_> = (0..10).map(|i| if i % 2 == 0 { black } else { white }).collect();
+        let output = play(&filter, &frames).await;
+        assert_eq!(output[..6], frames[..6]);
+        // LED stays close to white
+        assert!(output[6..].iter().all(|c| c.r > 200 && c.r == c.g && c.g == c.b), "{:?}", output);
+
+        // transitions are allowed again once older ones leave the window
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(play(&filter, &[white, black]).await, vec![white, black]);
+
+        // slow fades are not flashes
+        let filter = Photosensitivity::new();
+        let frames: Vec<_> = (0..=25).map(|i| Color::new(i * 10, i * 10, i * 10)).collect();
+        assert_eq!(play(&filter, &frames).await, frames);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_photosensitivity_red() {
+        // dark red flashes are below luminance threshold, but are saturated red transitions
+        let (black, red) = (Color::new(0, 0, 0), Color::new(128, 0, 0));
+        let filter = Photosensitivity::new();
+
+        let frames: Vec<_> = (0..10).map(|i| if i % 2 == 0 { black } else { red }).collect();
+        let output = play(&filter, &frames).await;
+        assert_eq!(output[..6], frames[..6]);
+        assert!(output[6..].iter().all(|c| c.r > 100), "{:?}", output);
+
+        // same flashes in gray are not limited
+        let gray = Color::new(40, 40, 40);
+        let filter = Photosensitivity::new();
+        let frames: Vec<_> = (0..10).map(|i| if i % 2 == 0 { black } else { gray }).collect();
+        assert_eq!(play(&filter, &frames).await, frames);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_photosensitivity_zones() {
+        let (black, white) = (Color::new(0, 0, 0), Color::new(255, 255, 255));
+        // LED 2 is first LED of zone 1
+        let filter = Photosensitivity::new().zones(0, [2, 1]);
+
+        // alternating whole controller and zone updates of the same LED share its transitions budget
+        let mut output = Vec::new();
+        for i in 0..10 {
+            let color = if i % 2 == 0 { black } else { white };
+            if i % 4 < 2 {
+                let mut colors = [black, black, color];
+                filter.filter_colors(0, OutputTarget::Controller, &mut colors);
+                output.push(colors[2]);
+            } else {
+                let mut colors = [color];
+                filter.filter_colors(0, OutputTarget::Zone(1), &mut colors);
+                output.push(colors[0]);
+            }
+            tokio::time::advance(Duration::from_millis(100)).await;
+        }
+        assert_eq!(output[..6], [black, white, black, white, black, white]);
+        assert!(output[6..].iter().all(|c| c.r > 200), "{:?}", output);
+
+        // and so do single LED updates
+        let mut colors = [black];
+        filter.filter_colors(0, OutputTarget::Led(2), &mut colors);
+        assert_ne!(colors[0], black);
+    }
+
+    #[test]
+    fn test_photosensitivity_speed() {
+        // speed 3 in 1..5
+        let (_, mut mode) = fixtures::mode(DEFAULT_PROTOCOL);
+        assert!(!Photosensitivity::new().is_too_fast(&mode));
+        assert!(Photosensitivity::new().max_speed(0.25).is_too_fast(&mode));
+        mode.speed = Some(5);
+        assert!(Photosensitivity::new().is_too_fast(&mode));
+
+        let unchanged = mode.clone();
+        Photosensitivity::new().filter_mode(0, 0, &mut mode);
+        assert_eq!(mode, unchanged);
+    }
+
+    #[tokio::test]
+    async fn test_client_filters() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, mode) = fixtures::mode(DEFAULT_PROTOCOL);
+        let mut dimmed = mode.clone();
+        dimmed.brightness = Some(40);
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![Color::new(128, 0, 0)] })
+            .expect(Request::UpdateZoneLeds { controller: 0, zone: 1, colors: vec![Color::new(0, 128, 0)] })
+            .expect(Request::UpdateSingleLed { controller: 0, led: 2, color: Color::new(0, 0, 128) })
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: dimmed })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![Color::new(255, 0, 0)] })
+            .to_client().await?;
+
+        let brightness = Arc::new(Brightness::new());
+        client.add_output_filter(brightness.clone());
+        brightness.set_global(0.5);
+
+        client.update_leds(0, vec![Color::new(255, 0, 0)]).await?;
+        client.update_zone_leds(0, 1, vec![Color::new(0, 255, 0)]).await?;
+        client.update_led(0, 2, Color::new(0, 0, 255)).await?;
+        client.update_mode(0, 0, mode).await?;
+
+        client.clear_output_filters();
+        client.update_leds(0, vec![Color::new(255, 0, 0)]).await?;
+
+        Ok(())
+    }
+}
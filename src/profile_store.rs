@@ -0,0 +1,497 @@
+//! Client-side profile store.
+//!
+//! [ProfileStore] saves controllers lighting state as files in a directory, unlike
+//! [server profiles](crate::OpenRGB::save_profile) it does not require protocol version 2 and profiles can be moved
+//! between machines.
+//!
+//! When loading a profile, saved devices are matched to server controllers by identity (type, name, vendor, then serial
+//! and location) rather than by controller index, so profiles survive controller list changes.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::profile_store::ProfileStore;
+//! # use std::error::Error;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let client = OpenRGB::connect().await?;
+//! let store = ProfileStore::new("profiles");
+//!
+//! store.save_profile(&client, "gaming").await?;
+//! println!("{:?}", store.get_profiles().await?);
+//! store.load_profile(&client, "gaming").await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use log::{debug, warn};
+use tokio::io::AsyncReadExt;
+
+use crate::{DEFAULT_PROTOCOL, OpenRGB, OpenRGBError};
+use crate::data::{Controller, DeviceType, OpenRGBReadable, OpenRGBWritable};
+use crate::OpenRGBError::{NotAProfile, ProfileStoreError, UnsupportedProfileVersion};
+use crate::protocol::{OpenRGBReadableStream, OpenRGBStream, OpenRGBWritableStream};
+use crate::state::ControllerState;
+
+static MAGIC: [u8; 4] = *b"ORGP";
+
+/// Profile files extension.
+pub const PROFILE_EXTENSION: &str = "orp";
+
+/// Store of profiles saved as files in a directory.
+#[derive(Debug, Clone)]
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+/// Identity of a controller, used to match saved devices to server controllers.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeviceIdentity {
+    /// Controller type.
+    pub r#type: DeviceType,
+
+    /// Controller name.
+    pub name: String,
+
+    /// Controller vendor.
+    pub vendor: String,
+
+    /// Controller serial.
+    pub serial: String,
+
+    /// Controller location.
+    pub location: String,
+}
+
+impl DeviceIdentity {
+    /// Identity of given controller.
+    pub fn of(controller: &Controller) -> Self {
+        Self {
+            r#type: controller.r#type,
+            name: controller.name.clone(),
+            vendor: controller.vendor.clone(),
+            serial: controller.serial.clone(),
+            location: controller.location.clone(),
+        }
+    }
+
+    /// How well `other` matches this identity, `None` if it is another device.
+    fn score(&self, other: &DeviceIdentity) -> Option<u8> {
+        if self.r#type != other.r#type || self.name != other.name || (!self.vendor.is_empty() && !other.vendor.is_empty() && self.vendor != other.vendor) {
+            return None;
+        }
+        let serial = !self.serial.is_empty() && self.serial == other.serial;
+        let location = self.location == other.location;
+        Some(2 * serial as u8 + location as u8)
+    }
+}
+
+/// Device saved in a profile.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ProfileDevice {
+    identity: DeviceIdentity,
+    state: ControllerState,
+}
+
+impl ProfileStore {
+    /// Build a store saving profiles in directory `dir`, created on first save.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Store directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get profile names, sorted.
+    pub async fn get_profiles(&self) -> Result<Vec<String>, OpenRGBError> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(source) => return Err(ProfileStoreError { path: self.dir.clone(), source }),
+        };
+        let mut profiles = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(|source| ProfileStoreError { path: self.dir.clone(), source })? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == PROFILE_EXTENSION) {
+                if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                    profiles.push(name.to_owned());
+                }
+            }
+        }
+        profiles.sort();
+        Ok(profiles)
+    }
+
+    /// Save lighting state of all `client` controllers to profile `name`, replacing it if it exists.
+    pub async fn save_profile<S: OpenRGBStream>(&self, client: &OpenRGB<S>, name: impl AsRef<str>) -> Result<(), OpenRGBError> {
+        let path = self.path(name.as_ref())?;
+        let devices = client.get_controllers_unfiltered().await?.into_iter().map(|controller| ProfileDevice {
+            identity: DeviceIdentity::of(&controller),
+            state: ControllerState::of(controller),
+        }).collect();
+        let data = encode(client.get_protocol_version(), devices).await?;
+        tokio::fs::create_dir_all(&self.dir).await.map_err(|source| ProfileStoreError { path: self.dir.clone(), source })?;
+        tokio::fs::write(&path, data).await.map_err(|source| ProfileStoreError { path, source })
+    }
+
+    /// Load profile `name`, applying saved state to matching `client` controllers.
+    ///
+    /// Saved modes are looked up by name in current controller modes, and saved colors are resized to current controller
+    /// LED count, keeping current colors of added LEDs. Saved devices without matching controller or mode are skipped.
+    pub async fn load_profile<S: OpenRGBStream>(&self, client: &OpenRGB<S>, name: impl AsRef<str>) -> Result<(), OpenRGBError> {
+        let devices = self.read(&self.path(name.as_ref())?).await?;
+        let controllers = client.get_controllers().await?;
+        let identities: Vec<_> = controllers.iter().map(DeviceIdentity::of).collect();
+        for (device, controller_id) in devices.iter().zip(match_devices(&devices, &identities)) {
+            match controller_id {
+                Some(controller_id) => if let Some(state) = adapt_state(&device.state, controller_id, &controllers[controller_id as usize]) {
+                    client.restore_controller(controller_id, &state).await?;
+                },
+                None => warn!("No controller matching {:?} in profile {:?}, skipping", device.identity, name.as_ref()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete profile `name`.
+    pub async fn delete_profile(&self, name: impl AsRef<str>) -> Result<(), OpenRGBError> {
+        let path = self.path(name.as_ref())?;
+        tokio::fs::remove_file(&path).await.map_err(|source| ProfileStoreError { path, source })
+    }
+
+    /// Rename profile `from` to `to`, failing if `to` exists.
+    pub async fn rename_profile(&self, from: impl AsRef<str>, to: impl AsRef<str>) -> Result<(), OpenRGBError> {
+        let (from, to) = (self.path(from.as_ref())?, self.path(to.as_ref())?);
+        if tokio::fs::metadata(&to).await.is_ok() {
+            return Err(ProfileStoreError { path: to, source: io::Error::new(ErrorKind::AlreadyExists, "profile already exists") });
+        }
+        tokio::fs::rename(&from, &to).await.map_err(|source| ProfileStoreError { path: from, source })
+    }
+
+    /// Import profile file at `path` as profile `name`, replacing it if it exists.
+    pub async fn import_profile(&self, path: impl AsRef<Path>, name: impl AsRef<str>) -> Result<(), OpenRGBError> {
+        let to = self.path(name.as_ref())?;
+        self.read(path.as_ref()).await?;
+        tokio::fs::create_dir_all(&self.dir).await.map_err(|source| ProfileStoreError { path: self.dir.clone(), source })?;
+        tokio::fs::copy(path.as_ref(), &to).await.map_err(|source| ProfileStoreError { path: to, source })?;
+        Ok(())
+    }
+
+    /// Export profile `name` to file at `path`.
+    pub async fn export_profile(&self, name: impl AsRef<str>, path: impl AsRef<Path>) -> Result<(), OpenRGBError> {
+        let from = self.path(name.as_ref())?;
+        tokio::fs::copy(&from, path.as_ref()).await.map_err(|source| ProfileStoreError { path: from, source })?;
+        Ok(())
+    }
+
+    /// Path of profile `name`.
+    fn path(&self, name: &str) -> Result<PathBuf, OpenRGBError> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', '\0']) {
+            return Err(ProfileStoreError {
+                path: self.dir.join(name),
+                source: io::Error::new(ErrorKind::InvalidInput, format!("invalid profile name {:?}", name)),
+            });
+        }
+        Ok(self.dir.join(format!("{}.{}", name, PROFILE_EXTENSION)))
+    }
+
+    /// Read and decode profile file at `path`.
+    async fn read(&self, path: &Path) -> Result<Vec<ProfileDevice>, OpenRGBError> {
+        let data = tokio::fs::read(path).await.map_err(|source| ProfileStoreError { path: path.to_owned(), source })?;
+        debug!("Decoding profile {:?}", path);
+        decode(path, &data).await
+    }
+}
+
+/// Match saved `devices` to controller `identities`, returning matched controller ID for each device.
+///
+/// Best matches are assigned first, and each controller is matched at most once.
+fn match_devices(devices: &[ProfileDevice], identities: &[DeviceIdentity]) -> Vec<Option<u32>> {
+    let mut candidates = Vec::new();
+    for (device_index, device) in devices.iter().enumerate() {
+        for (controller_id, identity) in (0_u32..).zip(identities) {
+            if let Some(score) = device.identity.score(identity) {
+                candidates.push((score, device_index, controller_id));
+            }
+        }
+    }
+    candidates.sort_by_key(|&(score, _, _)| std::cmp::Reverse(score));
+
+    let mut matches = vec![None; devices.len()];
+    let mut claimed = vec![false; identities.len()];
+    for (_, device_index, controller_id) in candidates {
+        if matches[device_index].is_none() && !claimed[controller_id as usize] {
+            matches[device_index] = Some(controller_id);
+            claimed[controller_id as usize] = true;
+        }
+    }
+    matches
+}
+
+/// Saved `state` adapted to current modes and LEDs of `controller` with ID `controller_id`, `None` if there is nothing
+/// to restore.
+fn adapt_state(state: &ControllerState, controller_id: u32, controller: &Controller) -> Option<ControllerState> {
+    let mode = state.mode.as_ref()?;
+    let active_mode = match controller.modes.iter().position(|m| m.name == mode.name) {
+        Some(mode_id) => mode_id as i32,
+        None => {
+            warn!("Controller {} ({}) has no {:?} mode saved in profile, skipping", controller_id, controller.name, mode.name);
+            return None;
+        }
+    };
+    let mut colors = state.colors.clone();
+    let leds = controller.colors.len();
+    if colors.len() != leds {
+        warn!("Resizing {} colors saved in profile to {} LEDs of controller {} ({})", colors.len(), leds, controller_id, controller.name);
+        colors.truncate(leds);
+        colors.extend_from_slice(&controller.colors[colors.len()..]);
+    }
+    Some(ControllerState { name: state.name.clone(), active_mode, mode: Some(mode.clone()), colors })
+}
+
+/// Encode profile file: magic value, protocol version used to encode modes, then devices.
+async fn encode(protocol: u32, devices: Vec<ProfileDevice>) -> Result<Vec<u8>, OpenRGBError> {
+    let mut data = MAGIC.to_vec();
+    data.write_value(protocol, protocol).await?;
+    data.write_value(devices, protocol).await?;
+    Ok(data)
+}
+
+/// Decode profile file.
+async fn decode(path: &Path, mut data: &[u8]) -> Result<Vec<ProfileDevice>, OpenRGBError> {
+    let mut magic = [0; 4];
+    if data.read_exact(&mut magic).await.is_err() || magic != MAGIC {
+        return Err(NotAProfile { path: path.to_owned() });
+    }
+    let protocol = data.read_value::<u32>(0).await?;
+    if protocol > DEFAULT_PROTOCOL {
+        return Err(UnsupportedProfileVersion { path: path.to_owned(), protocol });
+    }
+    data.read_value(protocol).await
+}
+
+#[async_trait]
+impl OpenRGBWritable for ProfileDevice {
+    fn size(&self, protocol: u32) -> usize {
+        self.identity.r#type.size(protocol)
+            + self.identity.name.size(protocol)
+            + self.identity.vendor.size(protocol)
+            + self.identity.serial.size(protocol)
+            + self.identity.location.size(protocol)
+            + self.state.active_mode.size(protocol)
+            + self.state.mode.iter().cloned().collect::<Vec<_>>().size(protocol)
+            + self.state.colors.size(protocol)
+    }
+
+    async fn write(self, stream: &mut impl OpenRGBWritableStream, protocol: u32) -> Result<(), OpenRGBError> {
+        stream.write_value(self.identity.r#type, protocol).await?;
+        stream.write_value(self.identity.name, protocol).await?;
+        stream.write_value(self.identity.vendor, protocol).await?;
+        stream.write_value(self.identity.serial, protocol).await?;
+        stream.write_value(self.identity.location, protocol).await?;
+        stream.write_value(self.state.active_mode, protocol).await?;
+        stream.write_value(self.state.mode.into_iter().collect::<Vec<_>>(), protocol).await?;
+        stream.write_value(self.state.colors, protocol).await
+    }
+}
+
+#[async_trait]
+impl OpenRGBReadable for ProfileDevice {
+    async fn read(stream: &mut impl OpenRGBReadableStream, protocol: u32) -> Result<Self, OpenRGBError> {
+        let identity = DeviceIdentity {
+            r#type: stream.read_value(protocol).await?,
+            name: stream.read_value(protocol).await?,
+            vendor: stream.read_value(protocol).await?,
+            serial: stream.read_value(protocol).await?,
+            location: stream.read_value(protocol).await?,
+        };
+        let active_mode = stream.read_value(protocol).await?;
+        let mode = stream.read_value::<Vec<_>>(protocol).await?.pop();
+        let colors = stream.read_value(protocol).await?;
+        Ok(ProfileDevice {
+            state: ControllerState { name: identity.name.clone(), active_mode, mode, colors },
+            identity,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::path::PathBuf;
+
+    use crate::DEFAULT_PROTOCOL;
+    use crate::data::{Color, ColorMode, fixtures};
+    use crate::message::{Request, Response};
+    use crate::mock::MockBuilder;
+    use crate::OpenRGBError;
+    use crate::profile_store::{DeviceIdentity, match_devices, ProfileDevice, ProfileStore};
+    use crate::state::ControllerState;
+    use crate::tests::setup;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("openrgb-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn device(name: &str, serial: &str, location: &str) -> ProfileDevice {
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let identity = DeviceIdentity {
+            name: name.to_string(),
+            serial: serial.to_string(),
+            location: location.to_string(),
+            ..DeviceIdentity::of(&controller)
+        };
+        ProfileDevice {
+            state: ControllerState { name: name.to_string(), active_mode: 0, mode: None, colors: vec![] },
+            identity,
+        }
+    }
+
+    #[test]
+    fn test_match_devices() {
+        let devices = vec![device("Keyboard", "A", "HID:1"), device("Keyboard", "B", "HID:2"), device("Mouse", "", "HID:3")];
+        let identities = vec![
+            device("Keyboard", "B", "HID:1").identity,
+            device("Keyboard", "A", "HID:4").identity,
+            device("Headset", "", "HID:3").identity,
+        ];
+        assert_eq!(match_devices(&devices, &identities), vec![Some(1), Some(0), None]);
+    }
+
+    #[tokio::test]
+    async fn test_save_load() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let store = ProfileStore::new(temp_dir("save-load"));
+        assert!(store.get_profiles().await?.is_empty());
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let mut per_led = controller.clone();
+        per_led.name = "Strip".to_string();
+        per_led.modes[0].color_mode = Some(ColorMode::PerLED);
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(2))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::ControllerData { controller: 1 })
+            .respond(Response::ControllerData(per_led.clone()))
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(2))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(per_led.clone()))
+            .expect(Request::ControllerData { controller: 1 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateMode { controller: 1, mode_id: 0, mode: controller.modes[0].clone() })
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: per_led.modes[0].clone() })
+            .expect(Request::UpdateLeds { controller: 0, colors: per_led.colors.clone() })
+            .to_client().await?;
+
+        store.save_profile(&client, "test").await?;
+        assert_eq!(store.get_profiles().await?, vec!["test".to_string()]);
+        store.load_profile(&client, "test").await?;
+
+        std::fs::remove_dir_all(store.dir())?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_changed_controllers() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let store = ProfileStore::new(temp_dir("load-changed"));
+
+        let (_, mut strip) = fixtures::controller(DEFAULT_PROTOCOL);
+        strip.name = "Strip".to_string();
+        strip.modes[0].color_mode = Some(ColorMode::PerLED);
+        let (_, keyboard) = fixtures::controller(DEFAULT_PROTOCOL);
+
+        // strip got a new mode before saved one and a third LED, keyboard lost saved mode
+        let mut new_strip = strip.clone();
+        let mut other_mode = strip.modes[0].clone();
+        other_mode.name = "Static".to_string();
+        new_strip.modes.insert(0, other_mode.clone());
+        new_strip.colors.push(Color::new(1, 2, 3));
+        let mut new_keyboard = keyboard.clone();
+        new_keyboard.modes = vec![other_mode];
+
+        let mut resized = strip.colors.clone();
+        resized.push(Color::new(1, 2, 3));
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(2))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(strip.clone()))
+            .expect(Request::ControllerData { controller: 1 })
+            .respond(Response::ControllerData(keyboard.clone()))
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(2))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(new_strip.clone()))
+            .expect(Request::ControllerData { controller: 1 })
+            .respond(Response::ControllerData(new_keyboard.clone()))
+            .expect(Request::UpdateMode { controller: 0, mode_id: 1, mode: strip.modes[0].clone() })
+            .expect(Request::UpdateLeds { controller: 0, colors: resized })
+            .to_client().await?;
+
+        store.save_profile(&client, "test").await?;
+        store.load_profile(&client, "test").await?;
+
+        std::fs::remove_dir_all(store.dir())?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_manage_profiles() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let dir = temp_dir("manage");
+        let store = ProfileStore::new(&dir);
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(0))
+            .to_client().await?;
+
+        store.save_profile(&client, "a").await?;
+        store.rename_profile("a", "b").await?;
+        assert_eq!(store.get_profiles().await?, vec!["b".to_string()]);
+
+        let exported = dir.join("exported.bin");
+        store.export_profile("b", &exported).await?;
+        store.import_profile(&exported, "c").await?;
+        assert_eq!(store.get_profiles().await?, vec!["b".to_string(), "c".to_string()]);
+
+        assert!(matches!(store.rename_profile("b", "c").await, Err(OpenRGBError::ProfileStoreError { .. })));
+        assert!(matches!(store.delete_profile("../b").await, Err(OpenRGBError::ProfileStoreError { .. })));
+
+        store.delete_profile("b").await?;
+        assert_eq!(store.get_profiles().await?, vec!["c".to_string()]);
+
+        std::fs::write(&exported, b"garbage")?;
+        assert!(matches!(store.import_profile(&exported, "d").await, Err(OpenRGBError::NotAProfile { .. })));
+        std::fs::write(&exported, [&b"ORGP"[..], &(DEFAULT_PROTOCOL + 1).to_le_bytes()].concat())?;
+        assert!(matches!(store.import_profile(&exported, "d").await, Err(OpenRGBError::UnsupportedProfileVersion { .. })));
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+}
@@ -1,59 +1,142 @@
+use std::io::ErrorKind;
+
 use async_trait::async_trait;
-use log::debug;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use log::{debug, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use OpenRGBError::*;
 
 use crate::data::{OpenRGBReadable, OpenRGBWritable, PacketId};
-use crate::OpenRGBError;
+use crate::{DecodeLimits, limits, OpenRGBError};
 
 static MAGIC: [u8; 4] = *b"ORGB";
 
+/// Packet header length.
+pub(crate) const HEADER_LEN: usize = 16;
+
+/// Stream OpenRGB data can be read from.
+///
+/// Implemented for any [AsyncRead] type, this is only needed as a bound when writing code generic over the
+/// [client](crate::OpenRGB) stream type.
 #[async_trait]
 pub trait OpenRGBReadableStream: AsyncReadExt + Sized + Send + Sync + Unpin {
+    /// Read a value.
     async fn read_value<T: OpenRGBReadable>(&mut self, protocol: u32) -> Result<T, OpenRGBError> {
         T::read(self, protocol).await
     }
 
+    /// Read a packet header, returning its payload length.
     async fn read_header(&mut self, protocol: u32, expected_device_id: u32, expected_packet_id: PacketId) -> Result<usize, OpenRGBError> {
         debug!("Reading {:?} packet...", expected_packet_id);
+        let (device_id, packet_id, len) = read_any_header(self, protocol).await?;
+        check_header(expected_device_id, expected_packet_id, device_id, packet_id, len)
+    }
 
-        for c in MAGIC {
-            if self.read_u8().await? != c {
-                return Err(ProtocolError(format!("expected OpenRGB magic value, got \"{}\"", c)));
-            }
-        }
+    /// Read a whole packet.
+    async fn read_packet<O: OpenRGBReadable>(&mut self, protocol: u32, expected_device_id: u32, expected_packet_id: PacketId) -> Result<O, OpenRGBError> {
+        let len = self.read_header(protocol, expected_device_id, expected_packet_id).await?;
+        let mut payload = vec![0; limits::check("packet size", len, DecodeLimits::current().max_packet_size)?];
+        self.read_exact(&mut payload).await?;
+        decode_payload(expected_packet_id, &payload, protocol).await
+    }
+}
 
-        let device_id = self.read_value::<u32>(protocol).await?;
-        if device_id != expected_device_id {
-            return Err(ProtocolError(format!("expected device ID {}, got {}", expected_device_id, device_id)));
-        }
+/// Read a packet header, returning its device ID, packet ID and payload length.
+async fn read_any_header<S: OpenRGBReadableStream>(stream: &mut S, protocol: u32) -> Result<(u32, PacketId, usize), OpenRGBError> {
+    let mut magic = [0; 4];
+    stream.read_exact(&mut magic).await?;
+    if magic != MAGIC {
+        return Err(BadMagic { got: magic });
+    }
+
+    let device_id = stream.read_value::<u32>(protocol).await?;
+    let packet_id = stream.read_value::<PacketId>(protocol).await?;
+    // saturated on 16 bits platforms, then rejected by packet size limit
+    let len = usize::try_from(stream.read_value::<u32>(protocol).await?).unwrap_or(usize::MAX);
+    Ok((device_id, packet_id, len))
+}
 
-        let packet_id = self.read_value::<PacketId>(protocol).await?;
-        if packet_id != expected_packet_id {
-            return Err(ProtocolError(format!("expected packet ID {:?}, got {:?}", expected_packet_id, packet_id)));
+/// Check a received packet header is the expected one, returning its payload length.
+fn check_header(expected_device_id: u32, expected_packet_id: PacketId, device_id: u32, packet_id: PacketId, len: usize) -> Result<usize, OpenRGBError> {
+    if device_id != expected_device_id {
+        return Err(UnexpectedDevice { expected: expected_device_id, got: device_id });
+    }
+
+    if packet_id != expected_packet_id {
+        return Err(UnexpectedPacket { expected: expected_packet_id, got: packet_id });
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("response_size", len);
+
+    Ok(len)
+}
+
+/// Read a whole packet payload, to be decoded with [decode_payload].
+///
+/// Unsolicited [DeviceListUpdated](PacketId::DeviceListUpdated) notifications received before it are consumed, calling
+/// `notified` with the payload length of each.
+///
+/// On error, the stream may be left in the middle of a packet.
+pub(crate) async fn read_packet_payload<S: OpenRGBReadableStream>(stream: &mut S, protocol: u32, expected_device_id: u32, expected_packet_id: PacketId, notified: impl Fn(usize)) -> Result<Vec<u8>, OpenRGBError> {
+    debug!("Reading {:?} packet...", expected_packet_id);
+    let len = loop {
+        let (device_id, packet_id, len) = read_any_header(stream, protocol).await?;
+        if packet_id == PacketId::DeviceListUpdated && expected_packet_id != PacketId::DeviceListUpdated {
+            debug!("Received unsolicited {:?} packet", packet_id);
+            #[cfg(feature = "tracing")]
+            tracing::info!(device_id, ?packet_id, len, "received unsolicited packet");
+            let mut payload = vec![0; limits::check("packet size", len, DecodeLimits::current().max_packet_size)?];
+            stream.read_exact(&mut payload).await?;
+            notified(len);
+            continue;
         }
+        break check_header(expected_device_id, expected_packet_id, device_id, packet_id, len)?;
+    };
+    let mut payload = vec![0; limits::check("packet size", len, DecodeLimits::current().max_packet_size)?];
+    stream.read_exact(&mut payload).await?;
+    Ok(payload)
+}
 
-        self.read_value::<u32>(protocol)
-            .await?
-            .try_into()
-            .map_err(|e| ProtocolError(format!("received invalid data length: {}", e)))
+/// Decode a whole packet payload.
+///
+/// Trailing bytes left after decoding are skipped, so that newer servers appending fields to packets stay supported.
+pub(crate) async fn decode_payload<O: OpenRGBReadable>(packet_id: PacketId, payload: &[u8], protocol: u32) -> Result<O, OpenRGBError> {
+    let mut stream = payload;
+    let value = limits::with_packet_len(payload.len(), stream.read_value::<O>(protocol))
+        .await?
+        .map_err(|e| {
+            let e = truncated(e, packet_id, payload.len(), protocol);
+            #[cfg(feature = "tracing")]
+            tracing::warn!(?packet_id, len = payload.len(), protocol, error = %e, "failed decoding packet");
+            e
+        })?;
+    if !stream.is_empty() {
+        warn!("Skipping {} unknown trailing bytes in {:?} packet payload of {} bytes", stream.len(), packet_id, payload.len());
     }
+    Ok(value)
+}
 
-    async fn read_packet<O: OpenRGBReadable>(&mut self, protocol: u32, expected_device_id: u32, expected_packet_id: PacketId) -> Result<O, OpenRGBError> {
-        self.read_header(protocol, expected_device_id, expected_packet_id).await?;
-        // TODO check header length vs actual read length
-        self.read_value(protocol).await
+/// Map end of payload reached while decoding a packet payload of `len` bytes to [TruncatedPayload].
+pub(crate) fn truncated(e: OpenRGBError, packet_id: PacketId, len: usize, protocol: u32) -> OpenRGBError {
+    match e {
+        CommunicationError { source } if source.kind() == ErrorKind::UnexpectedEof => TruncatedPayload { packet_id, len, protocol },
+        e => e,
     }
 }
 
+/// Stream OpenRGB data can be written to.
+///
+/// Implemented for any [AsyncWrite] type, this is only needed as a bound when writing code generic over the
+/// [client](crate::OpenRGB) stream type.
 #[async_trait]
 pub trait OpenRGBWritableStream: AsyncWriteExt + Sized + Send + Sync + Unpin {
+    /// Write a value.
     async fn write_value<T: OpenRGBWritable>(&mut self, value: T, protocol: u32) -> Result<(), OpenRGBError> {
         T::write(value, self, protocol).await
     }
 
+    /// Write a packet header.
     async fn write_header(&mut self, protocol: u32, device_id: u32, packet_id: PacketId, data_len: usize) -> Result<(), OpenRGBError> {
         debug!("Sending {:?} packet of {} bytes...", packet_id, data_len);
         self.write_all(&MAGIC).await?;
@@ -63,6 +146,7 @@ pub trait OpenRGBWritableStream: AsyncWriteExt + Sized + Send + Sync + Unpin {
         Ok(())
     }
 
+    /// Write a whole packet.
     async fn write_packet<I: OpenRGBWritable>(&mut self, protocol: u32, device_id: u32, packet_id: PacketId, data: I) -> Result<(), OpenRGBError> {
         let size = data.size(protocol);
 
@@ -84,19 +168,21 @@ pub trait OpenRGBWritableStream: AsyncWriteExt + Sized + Send + Sync + Unpin {
     }
 }
 
+/// Bidirectional stream an [OpenRGB client](crate::OpenRGB) can talk to a server through.
+///
+/// Implemented for any [AsyncRead] + [AsyncWrite] type, such as [TcpStream](tokio::net::TcpStream), `UnixStream`
+/// or [DuplexStream](tokio::io::DuplexStream).
 #[async_trait]
 pub trait OpenRGBStream: OpenRGBReadableStream + OpenRGBWritableStream {
+    /// Write a request packet and read its response.
     async fn request<I: OpenRGBWritable, O: OpenRGBReadable>(&mut self, protocol: u32, device_id: u32, packet_id: PacketId, data: I) -> Result<O, OpenRGBError> {
         self.write_packet(protocol, device_id, packet_id, data).await?;
         self.read_packet(protocol, device_id, packet_id).await
     }
 }
 
-impl OpenRGBReadableStream for TcpStream {}
-
-impl OpenRGBWritableStream for TcpStream {}
+impl<T: AsyncRead + Send + Sync + Unpin> OpenRGBReadableStream for T {}
 
-impl OpenRGBStream for TcpStream {}
+impl<T: AsyncWrite + Send + Sync + Unpin> OpenRGBWritableStream for T {}
 
-#[cfg(debug_assertions)]
-impl OpenRGBWritableStream for Vec<u8> {}
+impl<T: OpenRGBReadableStream + OpenRGBWritableStream> OpenRGBStream for T {}
@@ -0,0 +1,473 @@
+//! Multiplexing SDK proxy.
+//!
+//! [Proxy] speaks the SDK protocol to any number of downstream clients while keeping a single upstream
+//! [client](OpenRGB) connection, so that several tools can share one server:
+//! * controller count and [RequestControllerData](crate::data::PacketId::RequestControllerData) are served from a
+//!   cache, filled from upstream on first use and kept up to date with downstream writes,
+//! * writes (LEDs, modes, zones, profiles) are forwarded upstream, and logged with the client that made them: mode,
+//!   zone and profile changes at info level, LED updates at debug level,
+//! * client names set by downstream clients are only used in logs, upstream keeps its own name.
+//!
+//! Changes made to the server by other means (eg: the OpenRGB UI) are only picked up by [Proxy::watch_devices], which
+//! drops the cache and forwards [DeviceListUpdated](crate::data::PacketId::DeviceListUpdated) notifications of the
+//! server to downstream clients.
+//!
+//! Requests and responses are decoded and encoded with [Request] and [Response], so downstream clients negotiating an
+//! older protocol version than upstream are served cached data encoded for their version.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::proxy::Proxy;
+//! # use std::error::Error;
+//! # use std::time::Duration;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let proxy = Proxy::new(OpenRGB::connect().await?);
+//! tokio::spawn({
+//!     let proxy = proxy.clone();
+//!     async move { proxy.watch_devices(Duration::from_secs(2)).await }
+//! });
+//! proxy.serve(tokio::net::TcpListener::bind("127.0.0.1:6743").await?).await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, WriteHalf};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::{DecodeLimits, OpenRGB, OpenRGBError};
+use crate::data::{Controller, Mode, PacketId};
+use crate::limits;
+use crate::message::{Request, Response};
+use crate::protocol::{HEADER_LEN, OpenRGBStream, OpenRGBWritableStream};
+
+/// Multiplexing SDK proxy to a client connection, see [module documentation](self).
+pub struct Proxy<S: OpenRGBStream + 'static> {
+    client: Arc<OpenRGB<S>>,
+    cache: Arc<Mutex<Option<Vec<Controller>>>>,
+    events: broadcast::Sender<()>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl<S: OpenRGBStream + 'static> Clone for Proxy<S> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            cache: self.cache.clone(),
+            events: self.events.clone(),
+            connections: self.connections.clone(),
+        }
+    }
+}
+
+/// Downstream client, as displayed in logs.
+#[derive(Debug)]
+struct Peer {
+    id: usize,
+    address: String,
+    name: Option<String>,
+}
+
+impl Display for Peer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "client {} ({:?}, {})", self.id, name, self.address),
+            None => write!(f, "client {} ({})", self.id, self.address),
+        }
+    }
+}
+
+impl<S: OpenRGBStream + 'static> Proxy<S> {
+    /// Build a proxy to upstream `client`.
+    pub fn new(client: OpenRGB<S>) -> Self {
+        Self {
+            client: Arc::new(client),
+            cache: Arc::new(Mutex::new(None)),
+            events: broadcast::channel(16).0,
+            connections: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Upstream client used by proxy.
+    pub fn client(&self) -> &OpenRGB<S> {
+        &self.client
+    }
+
+    /// Drop cached controllers, so that they are requested again from upstream on next use.
+    pub async fn invalidate(&self) {
+        *self.cache.lock().await = None;
+    }
+
+    /// Watch upstream device list updates, dropping cached controllers and notifying downstream clients.
+    ///
+    /// See [OpenRGB::watch_device_list] for `poll` interval. Returns on first failed request, eg: if connection is lost.
+    pub async fn watch_devices(&self, poll: Duration) -> Result<(), OpenRGBError> {
+        let mut watcher = self.client.watch_device_list(poll);
+        loop {
+            watcher.changed().await?;
+            self.invalidate().await;
+            debug!("Device list changed, notifying {} clients", self.events.receiver_count());
+            // no connected client is not an error
+            let _ = self.events.send(());
+        }
+    }
+
+    /// Accept downstream clients on `listener` forever, serving each one on its own task.
+    ///
+    /// Returns on failure to accept a connection.
+    pub async fn serve(&self, listener: TcpListener) -> Result<(), OpenRGBError> {
+        loop {
+            let (stream, address) = listener.accept().await?;
+            let proxy = self.clone();
+            tokio::spawn(async move { proxy.serve_connection(stream, address).await });
+        }
+    }
+
+    /// Serve a downstream client connected through `stream` until it disconnects.
+    ///
+    /// `address` is only used in logs. Returns early on failed upstream request or invalid downstream packet, closing
+    /// the connection.
+    pub async fn serve_connection<D>(&self, stream: D, address: impl Display) -> Result<(), OpenRGBError>
+        where D: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {
+        let mut peer = Peer {
+            id: self.connections.fetch_add(1, Ordering::Relaxed),
+            address: address.to_string(),
+            name: None,
+        };
+        let protocol = Arc::new(AtomicU32::new(0));
+        let (mut reader, writer) = tokio::io::split(stream);
+        let writer = Arc::new(Mutex::new(writer));
+        debug!("Accepted {}", peer);
+
+        let notifier = tokio::spawn({
+            let (mut events, writer, protocol) = (self.events.subscribe(), writer.clone(), protocol.clone());
+            async move {
+                while let Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) = events.recv().await {
+                    let response = Response::DeviceListUpdated;
+                    let protocol = protocol.load(Ordering::Relaxed);
+                    if writer.lock().await.write_packet(protocol, 0, response.packet_id(), response).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let result = async {
+            loop {
+                let request = match self.client.get_decode_limits().apply(read_request(&mut reader, protocol.load(Ordering::Relaxed))).await {
+                    Ok(Some(request)) => request,
+                    Ok(None) => return Ok(()),
+                    // whole packet was read, only its payload is invalid
+                    Err(e @ (OpenRGBError::TruncatedPayload { .. } | OpenRGBError::InvalidZoneMatrix { .. } | OpenRGBError::UnknownPacket { .. } | OpenRGBError::InvalidString { .. })) => {
+                        warn!("Ignoring invalid request from {}: {}", peer, e);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                if let Some((device_id, response)) = self.handle(&mut peer, &protocol, request).await? {
+                    write_response(&writer, protocol.load(Ordering::Relaxed), device_id, response).await?;
+                }
+            }
+        }.await;
+
+        notifier.abort();
+        match &result {
+            Ok(()) => debug!("{} disconnected", peer),
+            Err(e) => warn!("Closing connection of {}: {}", peer, e),
+        }
+        result
+    }
+
+    /// Handle `request` from `peer`, returning response to send back with its device ID, if any.
+    async fn handle(&self, peer: &mut Peer, protocol: &AtomicU32, request: Request) -> Result<Option<(u32, Response)>, OpenRGBError> {
+        Ok(match request {
+            Request::ProtocolVersion(version) => {
+                let upstream = self.client.get_protocol_version();
+                protocol.store(version.min(upstream), Ordering::Relaxed);
+                debug!("{} negotiated protocol version {}", peer, version.min(upstream));
+                Some((0, Response::ProtocolVersion(upstream)))
+            }
+            Request::SetClientName(name) => {
+                debug!("{} is named {:?}", peer, name);
+                peer.name = Some(name);
+                None
+            }
+            Request::ControllerCount => {
+                let mut cache = self.cache.lock().await;
+                Some((0, Response::ControllerCount(self.cached(&mut cache).await?.len() as u32)))
+            }
+            Request::ControllerData { controller } => {
+                let mut cache = self.cache.lock().await;
+                match self.cached(&mut cache).await?.get(controller as usize) {
+                    Some(data) => Some((controller, Response::ControllerData(data.clone()))),
+                    None => {
+                        // like OpenRGB server, do not respond
+                        warn!("{} requested unknown controller {}", peer, controller);
+                        None
+                    }
+                }
+            }
+            Request::ProfileList => Some((0, Response::ProfileList(self.client.get_profiles().await?))),
+            request => {
+                self.forward(peer, request).await?;
+                None
+            }
+        })
+    }
+
+    /// Forward write `request` from `peer` upstream, updating cache.
+    async fn forward(&self, peer: &Peer, request: Request) -> Result<(), OpenRGBError> {
+        let mut guard = self.cache.lock().await;
+        let cache = self.cached(&mut guard).await?;
+        let name = |controller: u32| cache.get(controller as usize).map_or("unknown", |c| c.name.as_str()).to_owned();
+        match request {
+            Request::UpdateLeds { controller, colors } => {
+                debug!("{} set {} LEDs of controller {} ({})", peer, colors.len(), controller, name(controller));
+                self.client.update_leds(controller, colors.clone()).await?;
+                if let Some(c) = cache.get_mut(controller as usize) {
+                    if c.colors.len() == colors.len() {
+                        c.colors = colors;
+                    }
+                }
+            }
+            Request::UpdateZoneLeds { controller, zone, colors } => {
+                debug!("{} set {} LEDs of zone {} of controller {} ({})", peer, colors.len(), zone, controller, name(controller));
+                self.client.update_zone_leds(controller, zone, colors.clone()).await?;
+                if let Some(c) = cache.get_mut(controller as usize) {
+                    let start: usize = c.zones.iter().take(zone as usize).map(|z| z.leds_count as usize).sum();
+                    if let Some(leds) = c.colors.get_mut(start..start + colors.len()) {
+                        leds.copy_from_slice(&colors);
+                    }
+                }
+            }
+            Request::UpdateSingleLed { controller, led, color } => {
+                debug!("{} set LED {} of controller {} ({}) to {:?}", peer, led, controller, name(controller), color);
+                self.client.update_led(controller, led, color).await?;
+                if let Some(c) = cache.get_mut(controller as usize).and_then(|c| c.colors.get_mut(led as usize)) {
+                    *c = color;
+                }
+            }
+            Request::UpdateMode { controller, mode_id, mode } => {
+                info!("{} set controller {} ({}) mode to {:?}", peer, controller, name(controller), mode.name);
+                self.client.update_mode(controller, mode_id, mode.clone()).await?;
+                update_cached_mode(cache, controller, mode_id, mode);
+            }
+            Request::SaveMode { controller, mode_id, mode } => {
+                info!("{} saved controller {} ({}) mode {:?}", peer, controller, name(controller), mode.name);
+                self.client.save_mode(controller, mode_id, mode.clone()).await?;
+                update_cached_mode(cache, controller, mode_id, mode);
+            }
+            Request::SetCustomMode { controller } => {
+                info!("{} set controller {} ({}) to custom mode", peer, controller, name(controller));
+                self.client.set_custom_mode(controller).await?;
+                *guard = None;
+            }
+            Request::ResizeZone { controller, zone, size } => {
+                info!("{} resized zone {} of controller {} ({}) to {} LEDs", peer, zone, controller, name(controller), size);
+                self.client.resize_zone(controller, zone, size).await?;
+                *guard = None;
+            }
+            Request::LoadProfile(profile) => {
+                info!("{} loaded profile {:?}", peer, profile);
+                self.client.load_profile(profile).await?;
+                *guard = None;
+            }
+            Request::SaveProfile(profile) => {
+                info!("{} saved profile {:?}", peer, profile);
+                self.client.save_profile(profile).await?;
+            }
+            Request::DeleteProfile(profile) => {
+                info!("{} deleted profile {:?}", peer, profile);
+                self.client.delete_profile(profile).await?;
+            }
+            request => warn!("{} sent unexpected {:?} request", peer, request.packet_id()),
+        }
+        Ok(())
+    }
+
+    /// Cached controllers, fetched from upstream if needed.
+    async fn cached<'a>(&self, cache: &'a mut Option<Vec<Controller>>) -> Result<&'a mut Vec<Controller>, OpenRGBError> {
+        if cache.is_none() {
+            debug!("Filling controllers cache");
+            *cache = Some(self.client.get_controllers().await?);
+        }
+        Ok(cache.get_or_insert_with(Vec::new))
+    }
+}
+
+fn update_cached_mode(cache: &mut [Controller], controller: u32, mode_id: i32, mode: Mode) {
+    if let Some(c) = cache.get_mut(controller as usize) {
+        if let Some(cached) = usize::try_from(mode_id).ok().and_then(|i| c.modes.get_mut(i)) {
+            *cached = mode;
+            c.active_mode = mode_id;
+        }
+    }
+}
+
+/// Read next request sent by a downstream client, or `None` if it disconnected.
+async fn read_request(stream: &mut (impl AsyncRead + Unpin), protocol: u32) -> Result<Option<Request>, OpenRGBError> {
+    let mut header = [0; HEADER_LEN];
+    match stream.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    if header[0..4] != *b"ORGB" {
+        return Err(OpenRGBError::BadMagic { got: [header[0], header[1], header[2], header[3]] });
+    }
+    let field = |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+    let (device_id, packet_id, len) = (field(4), PacketId::from(field(8)), field(12) as usize);
+    let mut payload = vec![0; limits::check("packet size", len, DecodeLimits::current().max_packet_size)?];
+    stream.read_exact(&mut payload).await?;
+    Request::decode(device_id, packet_id, &payload, protocol).await.map(Some)
+}
+
+async fn write_response<D: AsyncWrite + Send + Sync>(writer: &Mutex<WriteHalf<D>>, protocol: u32, device_id: u32, response: Response) -> Result<(), OpenRGBError> {
+    writer.lock().await.write_packet(protocol, device_id, response.packet_id(), response).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+
+    use crate::data::{Color, fixtures};
+    use crate::DEFAULT_PROTOCOL;
+    use crate::message::{Request, Response};
+    use crate::mock::MockBuilder;
+    use crate::OpenRGB;
+    use crate::proxy::Proxy;
+    use crate::tests::setup;
+
+    #[tokio::test]
+    async fn test_proxy() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let mut mode = controller.modes[0].clone();
+        mode.speed = Some(5);
+        let colors = vec![Color::new(1, 2, 3), Color::new(4, 5, 6)];
+
+        let proxy = Proxy::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(1))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: mode.clone() })
+            .expect(Request::UpdateLeds { controller: 0, colors: colors.clone() })
+            .expect(Request::LoadProfile("test".to_string()))
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(0))
+            .to_client().await?);
+
+        let mut clients = Vec::new();
+        for i in 0..2 {
+            let (downstream, upstream) = tokio::io::duplex(1024);
+            let proxy = proxy.clone();
+            tokio::spawn(async move { proxy.serve_connection(upstream, format!("test {}", i)).await });
+            let client = OpenRGB::new(downstream).await?;
+            client.set_name(format!("client {}", i)).await?;
+            clients.push(client);
+        }
+
+        // served from cache
+        for client in &clients {
+            assert_eq!(client.get_controller_count().await?, 1);
+            assert_eq!(client.get_controller(0).await?, controller);
+        }
+
+        // writes are forwarded, and visible to other clients
+        clients[0].update_mode(0, 0, mode.clone()).await?;
+        clients[0].update_leds(0, colors.clone()).await?;
+        clients[0].get_controller_count().await?;
+        let data = clients[1].get_controller(0).await?;
+        assert_eq!(data.modes[0], mode);
+        assert_eq!(data.colors, colors);
+
+        // loading a profile invalidates cache
+        clients[1].load_profile("test").await?;
+        assert_eq!(clients[1].get_controller_count().await?, 0);
+        assert_eq!(clients[0].get_controller_count().await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_request() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let proxy = Proxy::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(0))
+            .to_client().await?);
+
+        let (mut downstream, upstream) = tokio::io::duplex(1024);
+        tokio::spawn(async move { proxy.serve_connection(upstream, "test".to_string()).await });
+
+        // not a request
+        downstream.write_all(&[&b"ORGB"[..], &0_u32.to_le_bytes(), &100_u32.to_le_bytes(), &0_u32.to_le_bytes()].concat()).await?;
+        // truncated protocol version request
+        downstream.write_all(&[&b"ORGB"[..], &0_u32.to_le_bytes(), &40_u32.to_le_bytes(), &2_u32.to_le_bytes(), &[3, 0]].concat()).await?;
+
+        // connection is still usable
+        let client = OpenRGB::new(downstream).await?;
+        assert_eq!(client.get_controller_count().await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watch_devices() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let proxy = Proxy::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(0))
+            .expect(Request::ControllerCount)
+            .respond(Response::DeviceListUpdated)
+            .respond(Response::ControllerCount(1))
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(1))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(fixtures::controller(DEFAULT_PROTOCOL).1))
+            .to_client().await?);
+
+        let (downstream, upstream) = tokio::io::duplex(1024);
+        tokio::spawn({
+            let proxy = proxy.clone();
+            async move { proxy.serve_connection(upstream, "test".to_string()).await }
+        });
+        let client = OpenRGB::new(downstream).await?;
+        let mut updates = client.device_list_updates();
+        assert_eq!(client.get_controller_count().await?, 0);
+
+        // server notification is read by watcher poll, drops cache and is forwarded downstream
+        let watch = tokio::spawn({
+            let proxy = proxy.clone();
+            async move { proxy.watch_devices(Duration::from_secs(10)).await }
+        });
+        tokio::time::sleep(Duration::from_secs(15)).await;
+        assert_eq!(client.get_controller_count().await?, 1);
+        assert!(updates.try_recv().is_ok());
+        watch.abort();
+
+        Ok(())
+    }
+}
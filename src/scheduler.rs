@@ -0,0 +1,876 @@
+//! Time-of-day lighting scheduler.
+//!
+//! Requires the `scheduler` feature.
+//!
+//! A [Scheduler] runs lighting [actions](Action) when [triggers](Trigger) fire: at a time of day, on some weekdays or on
+//! cron expressions. Triggers missed while the machine was asleep or the client was disconnected are handled according
+//! to [MissedTriggers] policy.
+//!
+//! Time is read from a [Clock], that can be replaced to test schedules deterministically.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::scheduler::{Action, Scheduler, Trigger};
+//! # use std::error::Error;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let client = OpenRGB::connect().await?;
+//!
+//! let mut scheduler = Scheduler::new()
+//!     .add(Trigger::cron("0 22 * * 1-5")?, Action::LoadProfile("night".to_string()))
+//!     .add(Trigger::daily(8, 0), Action::LoadProfile("day".to_string()));
+//!
+//! scheduler.run(&client).await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday};
+use log::{debug, info, warn};
+use thiserror::Error;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::{OpenRGB, OpenRGBError};
+use crate::profile_store::ProfileStore;
+use crate::protocol::OpenRGBStream;
+use crate::state::LightingState;
+
+/// Maximum time the scheduler sleeps before checking the clock again, to notice clock changes and system sleep.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Maximum number of occurrences of a trigger considered at once.
+const MAX_OCCURRENCES: usize = 10_000;
+
+/// Source of local wall clock time.
+pub trait Clock: Send + Sync {
+    /// Current local time.
+    fn now(&self) -> NaiveDateTime;
+}
+
+/// System local time [Clock].
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+/// Condition firing a scheduled action.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Trigger {
+    /// Every day at given time.
+    Daily(NaiveTime),
+
+    /// On given weekdays at given time.
+    Weekdays(Vec<Weekday>, NaiveTime),
+
+    /// On cron expression.
+    Cron(Cron),
+}
+
+impl Trigger {
+    /// Every day at `hour`:`minute`.
+    ///
+    /// # Panics
+    ///
+    /// If `hour` or `minute` is out of range.
+    pub fn daily(hour: u32, minute: u32) -> Self {
+        Trigger::Daily(NaiveTime::from_hms_opt(hour, minute, 0).expect("invalid time of day"))
+    }
+
+    /// On `weekdays` at `hour`:`minute`.
+    ///
+    /// # Panics
+    ///
+    /// If `hour` or `minute` is out of range.
+    pub fn weekdays(weekdays: impl IntoIterator<Item=Weekday>, hour: u32, minute: u32) -> Self {
+        Trigger::Weekdays(weekdays.into_iter().collect(), NaiveTime::from_hms_opt(hour, minute, 0).expect("invalid time of day"))
+    }
+
+    /// On cron `expression`, see [Cron].
+    pub fn cron(expression: &str) -> Result<Self, ParseCronError> {
+        expression.parse().map(Trigger::Cron)
+    }
+
+    /// First time this trigger fires strictly after `time`.
+    pub fn next_after(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Trigger::Daily(at) => (0..=1)
+                .map(|days| (time.date() + chrono::Duration::days(days)).and_time(*at))
+                .find(|next| *next > time),
+            Trigger::Weekdays(weekdays, at) => (0..=7)
+                .map(|days| (time.date() + chrono::Duration::days(days)).and_time(*at))
+                .find(|next| *next > time && weekdays.contains(&next.weekday())),
+            Trigger::Cron(cron) => cron.next_after(time),
+        }
+    }
+}
+
+/// Cron expression with 5 fields: minute (0-59), hour (0-23), day of month (1-31), month (1-12 or `jan`-`dec`) and day
+/// of week (0-7 or `sun`-`sat`, 0 and 7 being Sunday).
+///
+/// Fields are `*`, values, ranges (`1-5`), steps (`*/15`, `0-30/10`) or comma separated lists of those. As in
+/// standard cron, when both day of month and day of week are restricted, either one matching is enough.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// Invalid [Cron] expression.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("Invalid cron expression {expression:?}: {reason}")]
+pub struct ParseCronError {
+    expression: String,
+    reason: String,
+}
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl FromStr for Cron {
+    type Err = ParseCronError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let error = |reason: String| ParseCronError { expression: expression.to_owned(), reason };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(error(format!("expected 5 fields, got {}", fields.len())));
+        }
+        let weekdays = parse_field(fields[4], 0, 7, &WEEKDAYS, 0).map_err(error)?;
+        Ok(Cron {
+            minutes: parse_field(fields[0], 0, 59, &[], 0).map_err(error)?,
+            hours: parse_field(fields[1], 0, 23, &[], 0).map_err(error)?,
+            days: parse_field(fields[2], 1, 31, &[], 0).map_err(error)?,
+            months: parse_field(fields[3], 1, 12, &MONTHS, 1).map_err(error)?,
+            weekdays: if weekdays & (1 << 7) != 0 { weekdays | 1 } else { weekdays },
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+}
+
+/// Parse a cron field into a bit set of values between `min` and `max`, `names` being aliases for values starting at
+/// `names_offset`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], names_offset: u32) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let value = match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            Some(i) => i as u32 + names_offset,
+            None => s.parse().map_err(|_| format!("invalid value {:?}", s))?,
+        };
+        if value < min || value > max {
+            return Err(format!("value {} out of range {}-{}", value, min, max));
+        }
+        Ok(value)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0).ok_or_else(|| format!("invalid step {:?}", step))?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("invalid range {:?}", range));
+        }
+        for v in (start..=end).step_by(step as usize) {
+            set |= 1 << v;
+        }
+    }
+    Ok(set)
+}
+
+impl Cron {
+    /// Whether expression matches `date`.
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        let day = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        day && self.months & (1 << date.month()) != 0
+    }
+
+    /// First time this expression matches strictly after `time`.
+    pub fn next_after(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = time.date().and_hms_opt(time.hour(), time.minute(), 0)? + chrono::Duration::minutes(1);
+        // a leap day only pattern may wait 8 years
+        for days in 0..=(366 * 8) {
+            let date = start.date() + chrono::Duration::days(days);
+            if !self.matches_date(date) {
+                continue;
+            }
+            let first_hour = if days == 0 { start.hour() } else { 0 };
+            for hour in (first_hour..24).filter(|h| self.hours & (1 << h) != 0) {
+                let first_minute = if days == 0 && hour == start.hour() { start.minute() } else { 0 };
+                if let Some(minute) = (first_minute..60).find(|m| self.minutes & (1 << m) != 0) {
+                    return date.and_hms_opt(hour, minute, 0);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Future run by an [effect](Action::StartEffect).
+pub type EffectFuture = Pin<Box<dyn Future<Output=()> + Send>>;
+
+/// Stop signal given to an [effect](Action::StartEffect).
+///
+/// Effects share the scheduler client connection, so they are never cancelled in the middle of a request: instead they
+/// must check this signal between requests and return once it is [stopped](EffectStop::is_stopped).
+#[derive(Debug, Clone)]
+pub struct EffectStop(watch::Receiver<bool>);
+
+impl EffectStop {
+    /// Whether the effect was asked to stop.
+    pub fn is_stopped(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Wait until the effect is asked to stop.
+    ///
+    /// This is cancellation safe, eg: to use in `tokio::select!` with a sleep between two requests.
+    pub async fn stopped(&mut self) {
+        // sender dropped means the scheduler is gone, which also means stop
+        while !*self.0.borrow_and_update() {
+            if self.0.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Running [effect](Action::StartEffect) task.
+struct RunningEffect {
+    stop: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+/// Action run by the [Scheduler].
+pub enum Action<S: OpenRGBStream + 'static> {
+    /// Load a server profile, see [OpenRGB::load_profile].
+    LoadProfile(String),
+
+    /// Load a client-side profile, see [ProfileStore::load_profile].
+    LoadStoredProfile(ProfileStore, String),
+
+    /// Apply a captured state, see [OpenRGB::restore].
+    ApplyState(LightingState),
+
+    /// Switch a controller to the mode with given name, see [OpenRGB::update_mode].
+    SetMode {
+        /// Controller ID.
+        controller: u32,
+
+        /// Mode name.
+        mode: String,
+    },
+
+    /// Start an effect in a background task, running until the next scheduled action.
+    ///
+    /// The function is given a client sharing the scheduler client connection, and an [EffectStop] it must check
+    /// between requests. The next scheduled action runs once the effect returned.
+    StartEffect(Arc<dyn Fn(OpenRGB<S>, EffectStop) -> EffectFuture + Send + Sync>),
+}
+
+impl<S: OpenRGBStream + 'static> Action<S> {
+    /// Build a [StartEffect](Action::StartEffect) action from an async function.
+    pub fn effect<F, Fut>(f: F) -> Self
+        where F: Fn(OpenRGB<S>, EffectStop) -> Fut + Send + Sync + 'static,
+              Fut: Future<Output=()> + Send + 'static {
+        Action::StartEffect(Arc::new(move |client, stop| Box::pin(f(client, stop))))
+    }
+}
+
+impl<S: OpenRGBStream + 'static> Debug for Action<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::LoadProfile(name) => f.debug_tuple("LoadProfile").field(name).finish(),
+            Action::LoadStoredProfile(store, name) => f.debug_tuple("LoadStoredProfile").field(store).field(name).finish(),
+            Action::ApplyState(state) => f.debug_tuple("ApplyState").field(state).finish(),
+            Action::SetMode { controller, mode } => f.debug_struct("SetMode").field("controller", controller).field("mode", mode).finish(),
+            Action::StartEffect(_) => f.write_str("StartEffect"),
+        }
+    }
+}
+
+/// Policy for triggers that fired while the scheduler was not running (eg: system sleep, client reconnection).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum MissedTriggers {
+    /// Ignore missed triggers.
+    Skip,
+
+    /// Run the latest missed trigger action only, restoring the lighting that should be active now.
+    #[default]
+    RunLatest,
+
+    /// Run all missed trigger actions in order.
+    RunAll,
+}
+
+/// Lighting scheduler, see [module documentation](self).
+pub struct Scheduler<S: OpenRGBStream + 'static> {
+    clock: Arc<dyn Clock>,
+    entries: Vec<(Trigger, Action<S>)>,
+    missed: MissedTriggers,
+    grace: Duration,
+    last_check: Option<NaiveDateTime>,
+    done: Vec<(NaiveDateTime, usize)>,
+    effect: Option<RunningEffect>,
+}
+
+impl<S: OpenRGBStream + 'static> Default for Scheduler<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: OpenRGBStream + 'static> Scheduler<S> {
+    /// Build a new scheduler without entries, using [SystemClock].
+    pub fn new() -> Self {
+        Self {
+            clock: Arc::new(SystemClock),
+            entries: Vec::new(),
+            missed: MissedTriggers::default(),
+            grace: Duration::from_secs(120),
+            last_check: None,
+            done: Vec::new(),
+            effect: None,
+        }
+    }
+
+    /// Set clock (default: [SystemClock]).
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Set policy for missed triggers (default: [MissedTriggers::RunLatest]).
+    pub fn missed_triggers(mut self, missed: MissedTriggers) -> Self {
+        self.missed = missed;
+        self
+    }
+
+    /// Set delay after which a trigger that did not run is considered missed (default: 2 minutes).
+    pub fn grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Add `action`, run when `trigger` fires.
+    pub fn add(mut self, trigger: Trigger, action: Action<S>) -> Self {
+        self.entries.push((trigger, action));
+        self
+    }
+
+    /// Run scheduled actions forever.
+    ///
+    /// Returns on first failed action, eg: if connection is lost. Calling it again (eg: with a reconnected client) runs
+    /// actions missed meanwhile according to [MissedTriggers] policy.
+    pub async fn run(&mut self, client: &OpenRGB<S>) -> Result<(), OpenRGBError> {
+        loop {
+            self.run_pending(client).await?;
+            let now = self.clock.now();
+            let wait = self.next_trigger(now)
+                .and_then(|next| (next - now).to_std().ok())
+                .map_or(MAX_SLEEP, |wait| wait.min(MAX_SLEEP));
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Next time a trigger fires, after `time`.
+    pub fn next_trigger(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.entries.iter().filter_map(|(trigger, _)| trigger.next_after(time)).min()
+    }
+
+    /// Run actions of triggers fired since last call, returning the number of actions run.
+    ///
+    /// First call only records current time. If an action fails, it is retried on next call, along with following ones
+    /// but not the ones already run. If the clock went backwards, nothing runs until it reaches last call time again.
+    pub async fn run_pending(&mut self, client: &OpenRGB<S>) -> Result<usize, OpenRGBError> {
+        let now = self.clock.now();
+        let last_check = match self.last_check {
+            Some(last_check) if last_check <= now => last_check,
+            Some(last_check) => {
+                debug!("Clock went back from {} to {}, waiting for it to catch up", last_check, now);
+                return Ok(0);
+            }
+            None => {
+                self.last_check = Some(now);
+                return Ok(0);
+            }
+        };
+
+        // occurrences are collected after a time and up to another one, included
+        let on_time_since = now - chrono::Duration::from_std(self.grace).unwrap_or_else(|_| chrono::Duration::zero());
+        let missed_until = on_time_since - chrono::Duration::nanoseconds(1);
+        let mut missed = Vec::new();
+        let mut on_time = Vec::new();
+        for (index, (trigger, _)) in self.entries.iter().enumerate() {
+            match self.missed {
+                MissedTriggers::RunAll => missed.extend(occurrences(trigger, last_check, missed_until).into_iter().map(|time| (time, index))),
+                _ => missed.extend(latest_occurrence(trigger, last_check, missed_until).map(|time| (time, index))),
+            }
+            on_time.extend(occurrences(trigger, last_check.max(missed_until), now).into_iter().map(|time| (time, index)));
+        }
+        missed.sort();
+        on_time.sort();
+
+        let run_missed = match self.missed {
+            MissedTriggers::Skip => &missed[missed.len()..],
+            MissedTriggers::RunLatest => &missed[missed.len().saturating_sub(1)..],
+            MissedTriggers::RunAll => &missed[..],
+        };
+        if !missed.is_empty() && self.missed != MissedTriggers::RunAll {
+            info!("Running {} of missed scheduled actions according to {:?} policy", run_missed.len(), self.missed);
+        }
+        let run: Vec<_> = run_missed.iter().chain(&on_time)
+            .filter(|occurrence| !self.done.contains(occurrence))
+            .copied()
+            .collect();
+
+        for (count, (time, index)) in run.iter().enumerate() {
+            if let Err(e) = self.execute(client, *index).await {
+                // retry from failed occurrence, skipping actions already run at the same time
+                self.last_check = Some(*time - chrono::Duration::nanoseconds(1));
+                self.done.retain(|(done, _)| done == time);
+                self.done.extend(run[..count].iter().filter(|(done, _)| done == time));
+                return Err(e);
+            }
+            debug!("Ran scheduled action {} of {}", count + 1, run.len());
+        }
+        self.last_check = Some(now);
+        self.done.clear();
+        Ok(run.len())
+    }
+
+    /// Run action of entry `index`, stopping running effect if any.
+    async fn execute(&mut self, client: &OpenRGB<S>, index: usize) -> Result<(), OpenRGBError> {
+        if let Some(effect) = self.effect.take() {
+            let _ = effect.stop.send(true);
+            if let Err(e) = effect.task.await {
+                warn!("Scheduled effect failed: {}", e);
+            }
+        }
+        let action = &self.entries[index].1;
+        debug!("Running scheduled action {:?}", action);
+        match action {
+            Action::LoadProfile(name) => client.load_profile(name.clone()).await,
+            Action::LoadStoredProfile(store, name) => store.load_profile(client, name).await,
+            Action::ApplyState(state) => client.restore(state).await,
+            Action::SetMode { controller, mode } => {
+                let data = client.get_controller(*controller).await?;
+                match data.modes.iter().position(|m| &m.name == mode) {
+                    Some(mode_id) => client.update_mode(*controller, mode_id as i32, data.modes[mode_id].clone()).await,
+                    None => Err(OpenRGBError::UnknownMode { controller: *controller, mode: mode.clone() }),
+                }
+            }
+            Action::StartEffect(f) => {
+                let (stop, stopped) = watch::channel(false);
+                let task = tokio::spawn(f(client.with_timeouts(client.get_timeouts()), EffectStop(stopped)));
+                self.effect = Some(RunningEffect { stop, task });
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Times `trigger` fires after `after` and up to `until`, at most [MAX_OCCURRENCES].
+fn occurrences(trigger: &Trigger, after: NaiveDateTime, until: NaiveDateTime) -> Vec<NaiveDateTime> {
+    let mut times = Vec::new();
+    let mut time = after;
+    while let Some(next) = trigger.next_after(time).filter(|next| *next <= until) {
+        times.push(next);
+        time = next;
+        if times.len() >= MAX_OCCURRENCES {
+            warn!("Ignoring scheduled trigger occurrences after {}", next);
+            break;
+        }
+    }
+    times
+}
+
+/// Latest time `trigger` fires after `after` and up to `until`.
+///
+/// Looks back from `until` in doubling windows, so that frequent triggers are not enumerated since `after`.
+fn latest_occurrence(trigger: &Trigger, after: NaiveDateTime, until: NaiveDateTime) -> Option<NaiveDateTime> {
+    let mut window = chrono::Duration::hours(1);
+    loop {
+        let from = until.checked_sub_signed(window).map_or(after, |from| from.max(after));
+        let mut latest = None;
+        let mut time = from;
+        while let Some(next) = trigger.next_after(time).filter(|next| *next <= until) {
+            latest = Some(next);
+            time = next;
+        }
+        if latest.is_some() || from == after {
+            return latest;
+        }
+        window = window * 2;
+    }
+}
+
+impl<S: OpenRGBStream + 'static> Drop for Scheduler<S> {
+    fn drop(&mut self) {
+        // the effect task is detached and returns after its current request
+        if let Some(effect) = self.effect.take() {
+            let _ = effect.stop.send(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use chrono::{NaiveDate, NaiveDateTime, Weekday};
+
+    use crate::{DEFAULT_PROTOCOL, OpenRGBError};
+    use crate::data::fixtures;
+    use crate::message::{Request, Response};
+    use crate::mock::MockBuilder;
+    use crate::scheduler::{Action, Clock, Cron, MissedTriggers, Scheduler, Trigger};
+    use crate::tests::setup;
+
+    #[derive(Clone)]
+    struct ManualClock(Arc<Mutex<NaiveDateTime>>);
+
+    impl ManualClock {
+        fn set(&self, time: NaiveDateTime) {
+            *self.0.lock().unwrap() = time;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> NaiveDateTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    /// 2024-01-01 is a Monday.
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_cron_001() -> Result<(), Box<dyn Error>> {
+        let cron: Cron = "*/15 22 * * mon-fri".parse()?;
+        assert_eq!(cron.next_after(at(1, 21, 50)), Some(at(1, 22, 0)));
+        assert_eq!(cron.next_after(at(1, 22, 0)), Some(at(1, 22, 15)));
+        assert_eq!(cron.next_after(at(5, 22, 45)), Some(at(8, 22, 0)));
+
+        let cron: Cron = "0 8 1,15 * 0".parse()?;
+        assert_eq!(cron.next_after(at(1, 9, 0)), Some(at(7, 8, 0)));
+        assert_eq!(cron.next_after(at(7, 9, 0)), Some(at(14, 8, 0)));
+        assert_eq!(cron.next_after(at(14, 9, 0)), Some(at(15, 8, 0)));
+
+        let cron: Cron = "30 12 29 feb *".parse()?;
+        assert_eq!(cron.next_after(at(1, 0, 0)), NaiveDate::from_ymd_opt(2024, 2, 29).unwrap().and_hms_opt(12, 30, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cron_invalid() {
+        for expression in ["* * * *", "60 * * * *", "* * 0 * *", "*/0 * * * *", "5-1 * * * *", "* * * foo *"] {
+            assert!(expression.parse::<Cron>().is_err(), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn test_triggers() {
+        assert_eq!(Trigger::daily(8, 0).next_after(at(1, 7, 0)), Some(at(1, 8, 0)));
+        assert_eq!(Trigger::daily(8, 0).next_after(at(1, 8, 0)), Some(at(2, 8, 0)));
+
+        let trigger = Trigger::weekdays([Weekday::Sat, Weekday::Sun], 10, 30);
+        assert_eq!(trigger.next_after(at(1, 0, 0)), Some(at(6, 10, 30)));
+        assert_eq!(trigger.next_after(at(6, 10, 30)), Some(at(7, 10, 30)));
+    }
+
+    #[tokio::test]
+    async fn test_run_pending() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::LoadProfile("night".to_string()))
+            .expect(Request::LoadProfile("day".to_string()))
+            .expect(Request::LoadProfile("night".to_string()))
+            .to_client().await?;
+
+        let clock = ManualClock(Arc::new(Mutex::new(at(1, 12, 0))));
+        let mut scheduler = Scheduler::new()
+            .clock(clock.clone())
+            .add(Trigger::daily(22, 0), Action::LoadProfile("night".to_string()))
+            .add(Trigger::daily(8, 0), Action::LoadProfile("day".to_string()));
+
+        assert_eq!(scheduler.next_trigger(at(1, 12, 0)), Some(at(1, 22, 0)));
+        assert_eq!(scheduler.run_pending(&client).await?, 0);
+
+        // on time
+        clock.set(at(1, 22, 1));
+        assert_eq!(scheduler.run_pending(&client).await?, 1);
+        assert_eq!(scheduler.run_pending(&client).await?, 0);
+
+        // asleep until next day: only latest missed trigger runs
+        clock.set(at(2, 9, 0));
+        assert_eq!(scheduler.run_pending(&client).await?, 1);
+
+        // asleep for two days: latest missed trigger is 22:00
+        clock.set(at(4, 23, 0));
+        assert_eq!(scheduler.run_pending(&client).await?, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missed_triggers() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::LoadProfile("night".to_string()))
+            .expect(Request::LoadProfile("day".to_string()))
+            .to_client().await?;
+
+        let clock = ManualClock(Arc::new(Mutex::new(at(1, 12, 0))));
+        let mut scheduler = Scheduler::new()
+            .clock(clock.clone())
+            .missed_triggers(MissedTriggers::RunAll)
+            .add(Trigger::daily(22, 0), Action::LoadProfile("night".to_string()))
+            .add(Trigger::daily(8, 0), Action::LoadProfile("day".to_string()));
+        scheduler.run_pending(&client).await?;
+        clock.set(at(2, 9, 0));
+        assert_eq!(scheduler.run_pending(&client).await?, 2);
+
+        let mut scheduler = scheduler.missed_triggers(MissedTriggers::Skip);
+        clock.set(at(3, 9, 0));
+        assert_eq!(scheduler.run_pending(&client).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missed_frequent_trigger() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let minutely = || Request::LoadProfile("minutely".to_string());
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(minutely())
+            .expect(minutely())
+            .expect(minutely())
+            .expect(minutely())
+            .to_client().await?;
+
+        let clock = ManualClock(Arc::new(Mutex::new(at(1, 12, 30))));
+        let mut scheduler = Scheduler::new()
+            .clock(clock.clone())
+            .add(Trigger::Cron("* * * * *".parse()?), Action::LoadProfile("minutely".to_string()))
+            .add(Trigger::daily(12, 0), Action::LoadProfile("noon".to_string()));
+        scheduler.run_pending(&client).await?;
+
+        // asleep for weeks: latest missed occurrence is 12:07, then 12:08 to 12:10 are within grace delay
+        clock.set(at(20, 12, 10));
+        assert_eq!(scheduler.run_pending(&client).await?, 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_failed_action() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = MockBuilder::new()
+            .negotiate_protocol(1)
+            .to_client().await?;
+
+        let clock = ManualClock(Arc::new(Mutex::new(at(1, 12, 0))));
+        let mut scheduler = Scheduler::new()
+            .clock(clock.clone())
+            .add(Trigger::daily(22, 0), Action::LoadProfile("night".to_string()));
+        scheduler.run_pending(&client).await?;
+        clock.set(at(1, 22, 0));
+        assert!(scheduler.run_pending(&client).await.is_err());
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::LoadProfile("night".to_string()))
+            .to_client().await?;
+        clock.set(at(1, 23, 0));
+        assert_eq!(scheduler.run_pending(&client).await?, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_effect() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::LoadProfile("day".to_string()))
+            .to_client().await?;
+
+        let started = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let clock = ManualClock(Arc::new(Mutex::new(at(1, 12, 0))));
+        let mut scheduler = Scheduler::new()
+            .clock(clock.clone())
+            .add(Trigger::daily(22, 0), Action::effect({
+                let started = started.clone();
+                let stopped = stopped.clone();
+                move |_client, mut stop| {
+                    let started = started.clone();
+                    let stopped = stopped.clone();
+                    async move {
+                        started.store(true, Ordering::SeqCst);
+                        stop.stopped().await;
+                        stopped.store(true, Ordering::SeqCst);
+                    }
+                }
+            }))
+            .add(Trigger::daily(8, 0), Action::LoadProfile("day".to_string()));
+        scheduler.run_pending(&client).await?;
+
+        clock.set(at(1, 22, 0));
+        scheduler.run_pending(&client).await?;
+        tokio::task::yield_now().await;
+        assert!(started.load(Ordering::SeqCst));
+        assert!(scheduler.effect.is_some());
+
+        clock.set(at(2, 8, 0));
+        scheduler.run_pending(&client).await?;
+        assert!(scheduler.effect.is_none());
+        assert!(stopped.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_effect_not_interrupted() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::LoadProfile("effect".to_string()))
+            .expect(Request::LoadProfile("day".to_string()))
+            .to_client().await?;
+
+        let clock = ManualClock(Arc::new(Mutex::new(at(1, 12, 0))));
+        let mut scheduler = Scheduler::new()
+            .clock(clock.clone())
+            .add(Trigger::daily(22, 0), Action::effect(|client, stop| async move {
+                while !stop.is_stopped() {
+                    tokio::task::yield_now().await;
+                }
+                // request in flight when stopped completes before next action
+                client.load_profile("effect").await.unwrap();
+            }))
+            .add(Trigger::daily(8, 0), Action::LoadProfile("day".to_string()));
+        scheduler.run_pending(&client).await?;
+
+        clock.set(at(1, 22, 0));
+        scheduler.run_pending(&client).await?;
+        clock.set(at(2, 8, 0));
+        scheduler.run_pending(&client).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_failed_action_at_same_time() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let clock = ManualClock(Arc::new(Mutex::new(at(1, 12, 0))));
+        let mut scheduler = Scheduler::new()
+            .clock(clock.clone())
+            .add(Trigger::daily(22, 0), Action::LoadProfile("first".to_string()))
+            .add(Trigger::daily(22, 0), Action::SetMode { controller: 0, mode: "Breath".to_string() })
+            .add(Trigger::daily(22, 0), Action::LoadProfile("third".to_string()));
+
+        // controller has no mode yet
+        let (_, mut controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let breath = controller.modes.remove(0);
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::LoadProfile("first".to_string()))
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .to_client().await?;
+        scheduler.run_pending(&client).await?;
+        clock.set(at(1, 22, 0));
+        assert!(matches!(scheduler.run_pending(&client).await, Err(OpenRGBError::UnknownMode { controller: 0, .. })));
+
+        // first action is not run again
+        controller.modes.push(breath.clone());
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller))
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: breath })
+            .expect(Request::LoadProfile("third".to_string()))
+            .to_client().await?;
+        clock.set(at(1, 22, 1));
+        assert_eq!(scheduler.run_pending(&client).await?, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clock_backwards() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let client = MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::LoadProfile("night".to_string()))
+            .to_client().await?;
+
+        let clock = ManualClock(Arc::new(Mutex::new(at(1, 12, 0))));
+        let mut scheduler = Scheduler::new()
+            .clock(clock.clone())
+            .add(Trigger::daily(22, 0), Action::LoadProfile("night".to_string()));
+        scheduler.run_pending(&client).await?;
+        clock.set(at(1, 22, 1));
+        assert_eq!(scheduler.run_pending(&client).await?, 1);
+
+        // clock adjusted back before trigger time: it does not fire twice
+        clock.set(at(1, 21, 59));
+        assert_eq!(scheduler.run_pending(&client).await?, 0);
+        clock.set(at(1, 22, 2));
+        assert_eq!(scheduler.run_pending(&client).await?, 0);
+
+        Ok(())
+    }
+}
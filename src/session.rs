@@ -0,0 +1,514 @@
+//! Session recording and replay.
+//!
+//! [Recorder] wraps any client stream and logs every packet exchanged with the server, [Replay] serves such a
+//! recording back to an [OpenRGB](crate::OpenRGB) client, checking the client sends the same packets as in the
+//! recorded session. This allows turning captures of real sessions into regression tests.
+//!
+//! Recordings are text files, with one packet per line: elapsed time in seconds since the start of the session,
+//! direction (`>` for client to server, `<` for server to client) and hex encoded packet bytes, eg:
+//!
+//! ```text
+//! 0.000012 > 4f52474200000000280000000400000003000000
+//! 0.000734 < 4f52474200000000280000000400000003000000
+//! ```
+//!
+//! # Example
+//!
+//! ```no_run
+//! use openrgb::OpenRGB;
+//! use openrgb::session::{Recorder, Replay};
+//! use std::error::Error;
+//! use tokio::net::TcpStream;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn Error>> {
+//!
+//!     // record a session with a real server
+//!     let stream = Recorder::to_file(TcpStream::connect("localhost:6742").await?, "session.txt")?;
+//!     let client = OpenRGB::new(stream).await?;
+//!     client.get_controller(0).await?;
+//!     drop(client);
+//!
+//!     // replay it without server
+//!     let client = OpenRGB::new(Replay::from_file("session.txt")?).await?;
+//!     client.get_controller(0).await?;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt::{self, Display, Formatter, Write as _};
+use std::fs::File;
+use std::io::{self, ErrorKind, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::warn;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::protocol::HEADER_LEN;
+
+/// Origin of a recorded packet.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum Origin {
+    /// Packet sent by client to server.
+    Client,
+
+    /// Packet sent by server to client.
+    Server,
+}
+
+/// A single recorded packet.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Record {
+    /// Time elapsed since the start of the session.
+    pub elapsed: Duration,
+
+    /// Packet origin.
+    pub origin: Origin,
+
+    /// Raw packet bytes, header included.
+    pub data: Vec<u8>,
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:06} {} {}",
+            self.elapsed.as_secs(),
+            self.elapsed.subsec_micros(),
+            match self.origin {
+                Origin::Client => '>',
+                Origin::Server => '<',
+            },
+            to_hex(&self.data),
+        )
+    }
+}
+
+/// A recorded session, as written by [Recorder].
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct Recording {
+    /// Recorded packets, in order.
+    pub records: Vec<Record>,
+}
+
+impl Recording {
+    /// Load a recording from a file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse a recording from its text representation.
+    ///
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut records = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| io::Error::new(ErrorKind::InvalidData, format!("invalid record at line {}: {}", n + 1, reason));
+            let mut fields = line.split_whitespace();
+            let elapsed = fields.next()
+                .and_then(|f| f.parse::<f64>().ok())
+                .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| invalid("bad timestamp"))?;
+            let origin = match fields.next() {
+                Some(">") => Origin::Client,
+                Some("<") => Origin::Server,
+                _ => return Err(invalid("bad direction")),
+            };
+            let data = fields.next()
+                .and_then(from_hex)
+                .ok_or_else(|| invalid("bad packet data"))?;
+            records.push(Record { elapsed, origin, data });
+        }
+        Ok(Recording { records })
+    }
+}
+
+impl Display for Recording {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for record in &self.records {
+            writeln!(f, "{}", record)?;
+        }
+        Ok(())
+    }
+}
+
+/// Stream wrapper recording all packets exchanged through it.
+///
+/// Each complete packet is written as a line to the output as soon as it went through, see [module documentation](self)
+/// for the format. Output is written from a dedicated thread so that it never blocks stream I/O, and dropping the
+/// recorder waits for pending packets to be written. Output errors are logged and stop the recording, without failing
+/// stream I/O.
+pub struct Recorder<S, W: Write + Send + 'static> {
+    inner: S,
+    output: RecordWriter<W>,
+    start: Instant,
+    sent: Vec<u8>,
+    received: Vec<u8>,
+}
+
+/// Thread writing records to recorder output.
+struct RecordWriter<W: Write + Send + 'static> {
+    records: Option<Sender<Record>>,
+    thread: Option<JoinHandle<W>>,
+}
+
+impl<W: Write + Send + 'static> RecordWriter<W> {
+    fn new(mut output: W) -> Self {
+        let (records, receiver) = channel();
+        let thread = thread::spawn(move || {
+            if let Err(e) = write_records(&receiver, &mut output) {
+                warn!("Stopped session recording, failed writing output: {}", e);
+            }
+            output
+        });
+        Self { records: Some(records), thread: Some(thread) }
+    }
+
+    fn send(&self, record: Record) {
+        if let Some(records) = &self.records {
+            // fails only if recording stopped on output error, which is already logged
+            let _ = records.send(record);
+        }
+    }
+
+    /// Wait for pending records to be written and get back output.
+    fn finish(&mut self) -> Option<thread::Result<W>> {
+        self.records.take();
+        self.thread.take().map(JoinHandle::join)
+    }
+}
+
+impl<W: Write + Send + 'static> Drop for RecordWriter<W> {
+    fn drop(&mut self) {
+        // a panic writing output is already reported by the thread
+        let _ = self.finish();
+    }
+}
+
+/// Write records until all senders are dropped, flushing output whenever no record is pending.
+fn write_records(records: &Receiver<Record>, output: &mut impl Write) -> io::Result<()> {
+    while let Ok(record) = records.recv() {
+        writeln!(output, "{}", record)?;
+        for record in records.try_iter() {
+            writeln!(output, "{}", record)?;
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+impl<S> Recorder<S, File> {
+    /// Record packets going through `stream` to a new file at `path`.
+    pub fn to_file(stream: S, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(stream, File::create(path)?))
+    }
+}
+
+impl<S, W: Write + Send + 'static> Recorder<S, W> {
+    /// Record packets going through `stream` to `output`.
+    pub fn new(stream: S, output: W) -> Self {
+        Self {
+            inner: stream,
+            output: RecordWriter::new(output),
+            start: Instant::now(),
+            sent: Vec::new(),
+            received: Vec::new(),
+        }
+    }
+
+    /// Get back wrapped stream and output, once all recorded packets are written.
+    pub fn into_inner(mut self) -> (S, W) {
+        let output = match self.output.finish().expect("output taken only once") {
+            Ok(output) => output,
+            Err(panic) => std::panic::resume_unwind(panic),
+        };
+        (self.inner, output)
+    }
+
+    fn record(&mut self, origin: Origin, data: &[u8]) {
+        let elapsed = self.start.elapsed();
+        let buf = match origin {
+            Origin::Client => &mut self.sent,
+            Origin::Server => &mut self.received,
+        };
+        buf.extend_from_slice(data);
+        while let Some(len) = packet_len(buf) {
+            let data = buf.drain(..len).collect();
+            self.output.send(Record { elapsed, origin, data });
+        }
+    }
+}
+
+/// Length of the first packet in `buf`, if it is complete.
+///
+/// Data not starting with a packet header cannot be framed, it is considered a single packet.
+fn packet_len(buf: &[u8]) -> Option<usize> {
+    if buf.is_empty() {
+        return None;
+    }
+    if !b"ORGB".starts_with(&buf[..buf.len().min(4)]) {
+        return Some(buf.len());
+    }
+    if buf.len() < HEADER_LEN {
+        return None;
+    }
+    let len = HEADER_LEN + u32::from_le_bytes([buf[12], buf[13], buf[14], buf[15]]) as usize;
+    if buf.len() < len {
+        return None;
+    }
+    Some(len)
+}
+
+impl<S: AsyncRead + Unpin, W: Write + Send + 'static> AsyncRead for Recorder<S, W> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            self.record(Origin::Server, &buf.filled()[before..]);
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin, W: Write + Send + 'static> AsyncWrite for Recorder<S, W> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.record(Origin::Client, &buf[..n]);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Stream replaying a [Recording].
+///
+/// Server packets are served to the client in recorded order, once the client sent all packets preceding them.
+/// Client writes are checked against recorded client packets, any difference fails with an
+/// [InvalidData](ErrorKind::InvalidData) error. Reads past the end of the recording return end of file.
+pub struct Replay {
+    records: VecDeque<Record>,
+    offset: usize,
+    reader: Option<Waker>,
+}
+
+impl Replay {
+    /// Replay given recording.
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            records: recording.records.into(),
+            offset: 0,
+            reader: None,
+        }
+    }
+
+    /// Replay recording at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Recording::load(path).map(Self::new)
+    }
+
+    /// Check whether the whole recording was replayed.
+    pub fn is_finished(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.offset += n;
+        if self.records.front().is_some_and(|r| self.offset >= r.data.len()) {
+            self.records.pop_front();
+            self.offset = 0;
+            if let Some(waker) = self.reader.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl AsyncRead for Replay {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.records.front() {
+            None => Poll::Ready(Ok(())),
+            Some(Record { origin: Origin::Client, .. }) => {
+                self.reader = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            Some(Record { origin: Origin::Server, data, .. }) => {
+                let data = &data[self.offset..];
+                let n = data.len().min(buf.remaining());
+                buf.put_slice(&data[..n]);
+                self.advance(n);
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Replay {
+    fn poll_write(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.records.front() {
+            Some(Record { origin: Origin::Client, data, .. }) => {
+                let expected = &data[self.offset..];
+                let n = expected.len().min(buf.len());
+                if buf[..n] != expected[..n] {
+                    return Poll::Ready(Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("client sent {}, expected {}", to_hex(&buf[..n]), to_hex(&expected[..n])),
+                    )));
+                }
+                self.advance(n);
+                Poll::Ready(Ok(n))
+            }
+            _ => Poll::Ready(Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("client sent {}, expected no data", to_hex(buf)),
+            ))),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub(crate) fn to_hex(data: &[u8]) -> String {
+    data.iter().fold(String::with_capacity(data.len() * 2), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::time::Duration;
+
+    use tokio_test::io::Builder;
+
+    use crate::data::Color;
+    use crate::OpenRGB;
+    use crate::session::{Origin, Record, Recorder, Recording, Replay};
+    use crate::tests::{OpenRGBMockBuilder, setup};
+
+    #[tokio::test]
+    async fn test_record_replay_001() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let path = std::env::temp_dir().join(format!("openrgb-test-{}.session", std::process::id()));
+
+        let mock = Builder::new()
+            .negotiate_protocol(3)
+            .write(b"ORGB") // magic
+            .write(&7_u32.to_le_bytes()) // device id
+            .write(&1052_u32.to_le_bytes()) // packet id
+            .write(&8_u32.to_le_bytes()) // data size
+            .write(&2_i32.to_le_bytes()) // led id
+            .write(&[255_u8, 0_u8, 0_u8, 0_u8]) // color
+            .build();
+
+        let client = OpenRGB::new(Recorder::to_file(mock, &path)?).await?;
+        client.update_led(7, 2, Color { r: 255, g: 0, b: 0 }).await?;
+        drop(client);
+
+        let recording = Recording::load(&path)?;
+        assert_eq!(recording.records.iter().map(|r| r.origin).collect::<Vec<_>>(), vec![Origin::Client, Origin::Server, Origin::Client]);
+
+        let client = OpenRGB::new(Replay::new(recording.clone())).await?;
+        assert_eq!(client.get_protocol_version(), 3);
+        client.update_led(7, 2, Color { r: 255, g: 0, b: 0 }).await?;
+
+        let client = OpenRGB::new(Replay::new(recording)).await?;
+        assert!(client.update_led(7, 2, Color { r: 0, g: 255, b: 0 }).await.is_err());
+
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_record_output_error() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        struct Failing;
+
+        impl std::io::Write for Failing {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk full"))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mock = Builder::new()
+            .negotiate_protocol(3)
+            .write(b"ORGB") // magic
+            .write(&7_u32.to_le_bytes()) // device id
+            .write(&1052_u32.to_le_bytes()) // packet id
+            .write(&8_u32.to_le_bytes()) // data size
+            .write(&2_i32.to_le_bytes()) // led id
+            .write(&[255_u8, 0_u8, 0_u8, 0_u8]) // color
+            .build();
+
+        let client = OpenRGB::new(Recorder::new(mock, Failing)).await?;
+        client.update_led(7, 2, Color { r: 255, g: 0, b: 0 }).await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_001() -> Result<(), Box<dyn Error>> {
+        let recording = Recording::parse("# comment\n\n0.000012 > 4f524742\n1.5 < 00ff\n")?;
+
+        assert_eq!(recording, Recording {
+            records: vec![
+                Record { elapsed: Duration::from_micros(12), origin: Origin::Client, data: b"ORGB".to_vec() },
+                Record { elapsed: Duration::from_millis(1500), origin: Origin::Server, data: vec![0, 255] },
+            ],
+        });
+        assert_eq!(recording.to_string(), "0.000012 > 4f524742\n1.500000 < 00ff\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_002() {
+        assert!(Recording::parse("0.1 ? 00").is_err());
+        assert!(Recording::parse("0.1 > 0").is_err());
+        assert!(Recording::parse("abc > 00").is_err());
+    }
+}
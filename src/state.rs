@@ -0,0 +1,461 @@
+//! Lighting state capture and restoration.
+//!
+//! [OpenRGB::capture_state] saves the active mode and colors of every controller, [OpenRGB::restore] puts them back.
+//! [OpenRGB::restore_guard] captures state and returns a [RestoreGuard] restoring it when dropped or when a given event
+//! occurs (eg: Ctrl-C, see [RestoreGuard::restore_on]), eg: to undo a notification flash or a game effect.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::data::Color;
+//! # use std::error::Error;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let client = OpenRGB::connect().await?;
+//!
+//! let guard = client.restore_guard().await?;
+//! client.set_custom_mode(0).await?;
+//! client.update_leds(0, vec![Color::new(255, 0, 0); 8]).await?;
+//! // ...
+//! guard.restore().await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::future::Future;
+
+use log::{debug, warn};
+use tokio::runtime::Handle;
+
+use crate::{OpenRGB, OpenRGBError};
+use crate::data::{Color, ColorMode, Controller, Mode};
+use crate::protocol::OpenRGBStream;
+
+/// Lighting state of all controllers, see [OpenRGB::capture_state].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LightingState {
+    /// State of each controller, by controller ID.
+    pub controllers: Vec<ControllerState>,
+}
+
+/// Lighting state of a controller.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ControllerState {
+    /// Controller name, to detect controller list changes.
+    pub name: String,
+
+    /// Active mode index.
+    pub active_mode: i32,
+
+    /// Active mode parameters, if [ControllerState::active_mode] is a valid index.
+    pub mode: Option<Mode>,
+
+    /// LED colors.
+    pub colors: Vec<Color>,
+}
+
+impl ControllerState {
+    /// Lighting state of `controller`.
+    pub(crate) fn of(controller: Controller) -> Self {
+        Self {
+            mode: usize::try_from(controller.active_mode).ok().and_then(|i| controller.modes.get(i).cloned()),
+            name: controller.name,
+            active_mode: controller.active_mode,
+            colors: controller.colors,
+        }
+    }
+}
+
+impl<S: OpenRGBStream> OpenRGB<S> {
+    /// Capture lighting state of all controllers.
+    ///
+    /// Colors and modes last sent by this client connection are captured as they were before
+    /// [output filters](crate::output).
+    pub async fn capture_state(&self) -> Result<LightingState, OpenRGBError> {
+        let controllers = self.get_controllers_unfiltered().await?.into_iter().map(ControllerState::of).collect();
+        Ok(LightingState { controllers })
+    }
+
+    /// Restore lighting state captured with [OpenRGB::capture_state].
+    ///
+    /// Active mode is restored with [OpenRGB::update_mode], and LED colors with [OpenRGB::update_leds] if the mode uses
+    /// [per LED colors](ColorMode::PerLED), so [output filters](crate::output) apply to them.
+    ///
+    /// Captured controllers are matched to current ones by name, at the same ID if its name did not change, so that
+    /// state is not applied to another device when the controller list changed. Captured controllers without match are
+    /// skipped.
+    pub async fn restore(&self, state: &LightingState) -> Result<(), OpenRGBError> {
+        let names: Vec<String> = self.get_controllers().await?.into_iter().map(|controller| controller.name).collect();
+        let mut restored = vec![false; names.len()];
+        for (index, controller) in state.controllers.iter().enumerate() {
+            let controller_id = match names.get(index) {
+                Some(name) if *name == controller.name && !restored[index] => Some(index),
+                _ => (0..names.len()).find(|&i| !restored[i] && names[i] == controller.name),
+            };
+            match controller_id {
+                Some(controller_id) => {
+                    restored[controller_id] = true;
+                    self.restore_controller(controller_id as u32, controller).await?;
+                }
+                None => warn!("No controller {:?} to restore captured controller {} state to, skipping", controller.name, index),
+            }
+        }
+        Ok(())
+    }
+
+    /// Restore lighting state of a single controller.
+    pub(crate) async fn restore_controller(&self, controller_id: u32, controller: &ControllerState) -> Result<(), OpenRGBError> {
+        let mode = match &controller.mode {
+            Some(mode) => mode,
+            None => return Ok(()),
+        };
+        debug!("Restoring {:?} mode of controller {} ({})", mode.name, controller_id, controller.name);
+        self.update_mode(controller_id, controller.active_mode, mode.clone()).await?;
+        if mode.color_mode == Some(ColorMode::PerLED) && !controller.colors.is_empty() {
+            self.update_leds(controller_id, controller.colors.clone()).await?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: OpenRGBStream + 'static> OpenRGB<S> {
+    /// Capture lighting state of all controllers, and return a guard restoring it when dropped.
+    ///
+    /// See [RestoreGuard].
+    pub async fn restore_guard(&self) -> Result<RestoreGuard<S>, OpenRGBError> {
+        let state = self.capture_state().await?;
+        Ok(RestoreGuard {
+            client: Some(self.with_timeouts(self.get_timeouts())),
+            state,
+            #[cfg(feature = "signal")]
+            ctrl_c: None,
+        })
+    }
+}
+
+/// Guard restoring captured lighting state, see [OpenRGB::restore_guard].
+///
+/// State is restored:
+/// * by [RestoreGuard::restore], reporting errors,
+/// * by [RestoreGuard::restore_on], once a given event occurs (eg: Ctrl-C), reporting errors,
+/// * when dropped, in a background task: errors are logged, and restoration may not complete if the runtime shuts down
+///   first,
+/// * on Ctrl-C, once enabled with [RestoreGuard::restore_on_ctrl_c] (requires the `signal` feature).
+///
+/// Otherwise, the guard does not handle signals: on Ctrl-C, the default handler exits the process without dropping it.
+/// Use [RestoreGuard::disarm] to drop the guard without restoring state.
+#[must_use = "lighting state is restored as soon as the guard is dropped"]
+pub struct RestoreGuard<S: OpenRGBStream + 'static> {
+    client: Option<OpenRGB<S>>,
+    state: LightingState,
+    #[cfg(feature = "signal")]
+    ctrl_c: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+impl<S: OpenRGBStream + 'static> RestoreGuard<S> {
+    /// Captured lighting state.
+    pub fn state(&self) -> &LightingState {
+        &self.state
+    }
+
+    /// Restore captured lighting state now.
+    pub async fn restore(mut self) -> Result<(), OpenRGBError> {
+        match self.client.take() {
+            Some(client) => client.restore(&self.state).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Wait for `event`, then restore captured lighting state.
+    ///
+    /// # Example
+    ///
+    /// Restore lighting state on Ctrl-C (requires tokio `signal` feature):
+    ///
+    /// ```no_run
+    /// # use openrgb::OpenRGB;
+    /// # use std::error::Error;
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let client = OpenRGB::connect().await?;
+    /// let guard = client.restore_guard().await?;
+    /// // ... start an effect in a background task
+    /// guard.restore_on(tokio::signal::ctrl_c()).await?;
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn restore_on(self, event: impl Future) -> Result<(), OpenRGBError> {
+        event.await;
+        debug!("Restoring lighting state");
+        self.restore().await
+    }
+
+    /// Also restore captured lighting state on Ctrl-C, then exit the process with status 130.
+    ///
+    /// Requires the `signal` feature. This replaces the default Ctrl-C handler of the process, until the guard restores
+    /// state, is disarmed or dropped.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use openrgb::OpenRGB;
+    /// # use std::error::Error;
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let client = OpenRGB::connect().await?;
+    /// let guard = client.restore_guard().await?.restore_on_ctrl_c();
+    /// // ... play an effect until done or Ctrl-C
+    /// guard.restore().await?;
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "signal")]
+    pub fn restore_on_ctrl_c(mut self) -> Self {
+        let client = match &self.client {
+            Some(client) => client.with_timeouts(client.get_timeouts()),
+            None => return self,
+        };
+        let state = self.state.clone();
+        let (cancel, cancelled) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            tokio::select! {
+                // guard restored, disarmed or dropped
+                _ = cancelled => return,
+                result = tokio::signal::ctrl_c() => if let Err(e) = result {
+                    warn!("Cannot restore lighting state on Ctrl-C: {}", e);
+                    return;
+                },
+            }
+            debug!("Restoring lighting state on Ctrl-C");
+            if let Err(e) = client.restore(&state).await {
+                warn!("Failed restoring lighting state: {}", e);
+            }
+            std::process::exit(130);
+        });
+        self.ctrl_c = Some(cancel);
+        self
+    }
+
+    /// Drop guard without restoring lighting state.
+    pub fn disarm(mut self) {
+        self.client = None;
+    }
+}
+
+impl<S: OpenRGBStream + 'static> Drop for RestoreGuard<S> {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            let state = std::mem::replace(&mut self.state, LightingState { controllers: Vec::new() });
+            match Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn(async move {
+                        if let Err(e) = client.restore(&state).await {
+                            warn!("Failed restoring lighting state: {}", e);
+                        }
+                    });
+                }
+                Err(_) => warn!("Cannot restore lighting state outside of a tokio runtime"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::DEFAULT_PROTOCOL;
+    use crate::data::{Color, ColorMode, Controller, fixtures};
+    use crate::message::{Request, Response};
+    use crate::mock::MockBuilder;
+    use crate::output::Brightness;
+    use crate::state::{ControllerState, LightingState};
+    use crate::tests::setup;
+
+    /// Expect client to read data of all `controllers`.
+    fn expect_controllers<'a>(mock: &'a mut MockBuilder, controllers: &[&Controller]) -> &'a mut MockBuilder {
+        mock.expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(controllers.len() as u32));
+        for (controller_id, controller) in (0..).zip(controllers) {
+            mock.expect(Request::ControllerData { controller: controller_id })
+                .respond(Response::ControllerData((*controller).clone()));
+        }
+        mock
+    }
+
+    #[tokio::test]
+    async fn test_capture_restore() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let mut per_led = controller.clone();
+        per_led.name = "Per LED".to_string();
+        per_led.modes[0].color_mode = Some(ColorMode::PerLED);
+
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol();
+        expect_controllers(&mut mock, &[&controller, &per_led]);
+        expect_controllers(&mut mock, &[&controller, &per_led])
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: controller.modes[0].clone() })
+            .expect(Request::UpdateMode { controller: 1, mode_id: 0, mode: per_led.modes[0].clone() })
+            .expect(Request::UpdateLeds { controller: 1, colors: per_led.colors.clone() });
+        let client = mock.to_client().await?;
+
+        let state = client.capture_state().await?;
+        assert_eq!(state.controllers[1], ControllerState {
+            name: per_led.name.clone(),
+            active_mode: 0,
+            mode: Some(per_led.modes[0].clone()),
+            colors: per_led.colors.clone(),
+        });
+        client.restore(&state).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_changed_controllers() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, keyboard) = fixtures::controller(DEFAULT_PROTOCOL);
+        let mut mouse = keyboard.clone();
+        mouse.name = "Mouse".to_string();
+        mouse.modes[0].speed = Some(1);
+        let mut strip = keyboard.clone();
+        strip.name = "Strip".to_string();
+
+        // keyboard was unplugged, mouse is now controller 0, and a new strip is controller 1
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol();
+        expect_controllers(&mut mock, &[&mouse, &strip])
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: mouse.modes[0].clone() });
+        let client = mock.to_client().await?;
+
+        client.restore(&LightingState {
+            controllers: [keyboard, mouse].map(ControllerState::of).to_vec(),
+        }).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_capture_restore_with_output_filter() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, mut controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        controller.modes[0].color_mode = Some(ColorMode::PerLED);
+        let (mode, colors) = (controller.modes[0].clone(), controller.colors.clone());
+
+        // server reads back filtered values
+        let mut dimmed = controller.clone();
+        dimmed.modes[0].brightness = Some(40);
+        dimmed.colors = vec![Color::new(0, 128, 0), Color::new(0, 0, 128)];
+
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol()
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: dimmed.modes[0].clone() })
+            .expect(Request::UpdateLeds { controller: 0, colors: dimmed.colors.clone() });
+        expect_controllers(&mut mock, &[&dimmed]);
+        expect_controllers(&mut mock, &[&dimmed])
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: dimmed.modes[0].clone() })
+            .expect(Request::UpdateLeds { controller: 0, colors: dimmed.colors.clone() });
+        let client = mock.to_client().await?;
+        let brightness = Arc::new(Brightness::new());
+        brightness.set_global(0.5);
+        client.add_output_filter(brightness);
+
+        client.update_mode(0, 0, mode.clone()).await?;
+        client.update_leds(0, colors.clone()).await?;
+
+        // state is captured before filters, and restoring it applies them once
+        let state = client.capture_state().await?;
+        assert_eq!(state.controllers[0].mode, Some(mode));
+        assert_eq!(state.controllers[0].colors, colors);
+        client.restore(&state).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_invalid_mode() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, mut controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        controller.name = "test".to_string();
+
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol();
+        expect_controllers(&mut mock, &[&controller]);
+        let client = mock.to_client().await?;
+
+        client.restore(&LightingState {
+            controllers: vec![ControllerState { name: "test".to_string(), active_mode: -1, mode: None, colors: vec![Color::new(0, 0, 0)] }],
+        }).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_guard() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol();
+        expect_controllers(&mut mock, &[&controller])
+            .expect(Request::SetCustomMode { controller: 0 });
+        expect_controllers(&mut mock, &[&controller])
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: controller.modes[0].clone() });
+        expect_controllers(&mut mock, &[&controller]);
+        expect_controllers(&mut mock, &[&controller])
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: controller.modes[0].clone() })
+            .expect(Request::ControllerCount)
+            .respond(Response::ControllerCount(0));
+        let client = mock.to_client().await?;
+
+        let guard = client.restore_guard().await?;
+        assert_eq!(guard.state().controllers.len(), 1);
+        client.set_custom_mode(0).await?;
+        guard.restore().await?;
+
+        let guard = client.restore_guard().await?;
+        drop(guard);
+        tokio::task::yield_now().await;
+        assert_eq!(client.get_controller_count().await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_restore_guard_on() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+
+        let mut mock = MockBuilder::new();
+        mock.negotiate_default_protocol();
+        expect_controllers(&mut mock, &[&controller])
+            .expect(Request::SetCustomMode { controller: 0 });
+        expect_controllers(&mut mock, &[&controller])
+            .expect(Request::UpdateMode { controller: 0, mode_id: 0, mode: controller.modes[0].clone() });
+        let client = mock.to_client().await?;
+
+        let guard = client.restore_guard().await?;
+        client.set_custom_mode(0).await?;
+        let start = tokio::time::Instant::now();
+        guard.restore_on(tokio::time::sleep(Duration::from_secs(5))).await?;
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+
+        Ok(())
+    }
+}
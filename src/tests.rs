@@ -7,13 +7,7 @@ use simplelog::{ColorChoice, CombinedLogger, Config, TerminalMode, TermLogger};
 use tokio_test::io::{Builder, Mock};
 
 use crate::{DEFAULT_PROTOCOL, OpenRGB, OpenRGBError};
-use crate::protocol::{OpenRGBReadableStream, OpenRGBStream, OpenRGBWritableStream};
-
-impl OpenRGBReadableStream for Mock {}
-
-impl OpenRGBWritableStream for Mock {}
-
-impl OpenRGBStream for Mock {}
+use crate::protocol::OpenRGBStream;
 
 static INIT_ONCE: Once = Once::new();
 
@@ -0,0 +1,61 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::OpenRGBError;
+use crate::OpenRGBError::Timeout;
+
+/// Timeouts applied to communication with OpenRGB server, `None` disables a timeout.
+///
+/// When a request times out, the connection is poisoned: the server may still send the reply later, so all subsequent
+/// calls fail with [OpenRGBError::ConnectionPoisoned] and a new client must be connected. The same happens when a request
+/// fails, or its future is dropped (eg: by a caller timeout), after it started being sent and before its whole reply was
+/// read.
+///
+/// # Example
+///
+/// ```no_run
+/// # use openrgb::{OpenRGB, Timeouts};
+/// # use std::error::Error;
+/// # use std::time::Duration;
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let mut client = OpenRGB::connect().await?;
+/// client.set_timeouts(Timeouts { read: Some(Duration::from_secs(5)), ..Default::default() });
+///
+/// // override timeouts for a single call
+/// let slow = Timeouts { read: Some(Duration::from_secs(60)), ..client.get_timeouts() };
+/// let controller = client.with_timeouts(slow).get_controller(0).await?;
+/// #
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Timeouts {
+    /// Timeout for opening connection to server.
+    pub connect: Option<Duration>,
+
+    /// Timeout for reading a reply from server.
+    pub read: Option<Duration>,
+
+    /// Timeout for writing a request to server.
+    pub write: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Some(Duration::from_secs(5)),
+            read: Some(Duration::from_secs(30)),
+            write: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+/// Run `f`, failing with [OpenRGBError::Timeout] if it does not complete within `timeout`.
+pub(crate) async fn with_timeout<T, F: Future<Output=Result<T, OpenRGBError>>>(timeout: Option<Duration>, f: F) -> Result<T, OpenRGBError> {
+    match timeout {
+        None => f.await,
+        Some(duration) => tokio::time::timeout(duration, f).await.map_err(|_| Timeout { duration })?,
+    }
+}
@@ -0,0 +1,518 @@
+//! Smooth transitions between color states.
+//!
+//! [Transitions] fades controllers, zones or the whole rig from their current colors to target colors or to a
+//! [captured state](crate::state::LightingState), over a duration and with an [Easing] curve.
+//!
+//! Colors are interpolated in the [Oklab](https://bottosson.github.io/posts/oklab/) perceptual color space, so that
+//! intermediate colors keep an even perceived brightness and hue (eg: fading red to green does not go through a dark
+//! brown). See [interpolate].
+//!
+//! Starting a transition cancels running transitions on any of its controllers: they stop updating these controllers at
+//! their next frame, keep fading their other controllers, and return [Outcome::Cancelled] once done. The new transition
+//! starts from the colors left by the cancelled ones.
+//!
+//! Transitions write LED colors with [OpenRGB::update_leds] and [OpenRGB::update_zone_leds], so they only show on
+//! controllers in a per-LED mode.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use openrgb::OpenRGB;
+//! # use openrgb::data::Color;
+//! # use openrgb::transition::{Easing, Transitions};
+//! # use std::error::Error;
+//! # use std::time::Duration;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn Error>> {
+//! let transitions = Transitions::new(OpenRGB::connect().await?);
+//! transitions.fade_all(Color::new(255, 128, 0), Duration::from_secs(2), Easing::EaseInOut).await?;
+//! #
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use log::{debug, warn};
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::{OpenRGB, OpenRGBError};
+use crate::data::Color;
+use crate::protocol::OpenRGBStream;
+use crate::state::LightingState;
+
+/// Easing curve, mapping elapsed time fraction to transition progress.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Easing {
+    /// Constant speed.
+    Linear,
+
+    /// Slow start (quadratic).
+    EaseIn,
+
+    /// Slow end (quadratic).
+    EaseOut,
+
+    /// Slow start and end (cubic).
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// Progress at time fraction `t`, both between 0 and 1.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => if t < 0.5 { 4.0 * t * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0 },
+        }
+    }
+}
+
+/// Transition target.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Target {
+    /// All LEDs of a controller.
+    Controller {
+        /// Controller ID.
+        controller: u32,
+
+        /// Target colors, missing colors repeat the last one (eg: a single color applies to all LEDs).
+        colors: Vec<Color>,
+    },
+
+    /// LEDs of a controller zone.
+    Zone {
+        /// Controller ID.
+        controller: u32,
+
+        /// Zone ID.
+        zone: u32,
+
+        /// Target colors, missing colors repeat the last one (eg: a single color applies to all LEDs).
+        colors: Vec<Color>,
+    },
+}
+
+impl Target {
+    fn controller(&self) -> u32 {
+        match self {
+            Target::Controller { controller, .. } | Target::Zone { controller, .. } => *controller,
+        }
+    }
+}
+
+/// How a transition ended.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Outcome {
+    /// Transition reached its target colors.
+    Completed,
+
+    /// Transition was cancelled by another one on some or all of its controllers.
+    Cancelled,
+}
+
+/// Transition to play, resolved against current controller colors.
+struct Fade {
+    controller: u32,
+    zone: Option<u32>,
+    from: Vec<Color>,
+    to: Vec<Color>,
+    cancelled: bool,
+}
+
+/// Ownership of controllers by a transition, released when the transition ends or is dropped.
+struct Ownership<'a> {
+    owners: &'a Mutex<HashMap<u32, u64>>,
+    id: u64,
+}
+
+impl<'a> Ownership<'a> {
+    fn acquire(owners: &'a Mutex<HashMap<u32, u64>>, id: u64, controllers: impl IntoIterator<Item=u32>) -> Self {
+        let mut map = owners.lock().unwrap();
+        for controller in controllers {
+            map.insert(controller, id);
+        }
+        Self { owners, id }
+    }
+
+    fn owns(&self, controller: u32) -> bool {
+        self.owners.lock().unwrap().get(&controller) == Some(&self.id)
+    }
+}
+
+impl Drop for Ownership<'_> {
+    fn drop(&mut self) {
+        self.owners.lock().unwrap().retain(|_, owner| *owner != self.id);
+    }
+}
+
+/// Transitions player, see [module documentation](self).
+pub struct Transitions<S: OpenRGBStream> {
+    client: OpenRGB<S>,
+    frame_interval: Duration,
+    next_id: AtomicU64,
+    owners: Mutex<HashMap<u32, u64>>,
+}
+
+impl<S: OpenRGBStream> Transitions<S> {
+    /// Build a transitions player sending to `client`, at 30 frames per second.
+    pub fn new(client: OpenRGB<S>) -> Self {
+        Self {
+            client,
+            frame_interval: Duration::from_secs(1) / 30,
+            next_id: AtomicU64::new(1),
+            owners: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set interval between frames (default: 1/30 s).
+    pub fn frame_interval(mut self, interval: Duration) -> Self {
+        self.frame_interval = interval;
+        self
+    }
+
+    /// Client used by transitions.
+    pub fn client(&self) -> &OpenRGB<S> {
+        &self.client
+    }
+
+    /// Fade all LEDs of controller `controller_id` to `colors`.
+    pub async fn fade_controller(&self, controller_id: u32, colors: Vec<Color>, duration: Duration, easing: Easing) -> Result<Outcome, OpenRGBError> {
+        self.fade(vec![Target::Controller { controller: controller_id, colors }], duration, easing).await
+    }
+
+    /// Fade LEDs of zone `zone_id` of controller `controller_id` to `colors`.
+    pub async fn fade_zone(&self, controller_id: u32, zone_id: u32, colors: Vec<Color>, duration: Duration, easing: Easing) -> Result<Outcome, OpenRGBError> {
+        self.fade(vec![Target::Zone { controller: controller_id, zone: zone_id, colors }], duration, easing).await
+    }
+
+    /// Fade all LEDs of all controllers to `color`.
+    pub async fn fade_all(&self, color: Color, duration: Duration, easing: Easing) -> Result<Outcome, OpenRGBError> {
+        let targets = (0..self.client.get_controller_count().await?)
+            .map(|controller| Target::Controller { controller, colors: vec![color] })
+            .collect();
+        self.fade(targets, duration, easing).await
+    }
+
+    /// Fade controllers to colors of captured `state`, then [restore](OpenRGB::restore) it if transition completed.
+    pub async fn fade_to_state(&self, state: &LightingState, duration: Duration, easing: Easing) -> Result<Outcome, OpenRGBError> {
+        let targets = (0..).zip(&state.controllers)
+            .filter(|(_, controller)| !controller.colors.is_empty())
+            .map(|(controller, state)| Target::Controller { controller, colors: state.colors.clone() })
+            .collect();
+        let outcome = self.fade(targets, duration, easing).await?;
+        if outcome == Outcome::Completed {
+            self.client.restore(state).await?;
+        }
+        Ok(outcome)
+    }
+
+    /// Fade `targets` from their current colors, cancelling running transitions on their controllers.
+    ///
+    /// Invalid targets (unknown zones, empty colors) are skipped.
+    pub async fn fade(&self, targets: Vec<Target>, duration: Duration, easing: Easing) -> Result<Outcome, OpenRGBError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let ownership = Ownership::acquire(&self.owners, id, targets.iter().map(Target::controller));
+
+        let mut fades = Vec::new();
+        let mut controllers = HashMap::new();
+        for target in targets {
+            let controller_id = target.controller();
+            let controller = match controllers.entry(controller_id) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => e.insert(self.client.get_controller_unfiltered(controller_id).await?),
+            };
+            let (zone, colors, range) = match target {
+                Target::Controller { colors, .. } => (None, colors, 0..controller.colors.len()),
+                Target::Zone { zone, colors, .. } => match controller.zones.get(zone as usize) {
+                    Some(z) => {
+                        let start = controller.zones.iter().take(zone as usize).map(|z| z.leds_count as usize).sum();
+                        (Some(zone), colors, start..start + z.leds_count as usize)
+                    }
+                    None => {
+                        warn!("Skipping transition of unknown zone {} of controller {}", zone, controller_id);
+                        continue;
+                    }
+                },
+            };
+            let from = match controller.colors.get(range) {
+                Some(from) if !colors.is_empty() => from.to_vec(),
+                _ => continue,
+            };
+            let to = (0..from.len()).map(|i| colors[i.min(colors.len() - 1)]).collect();
+            fades.push(Fade { controller: controller_id, zone, from, to, cancelled: false });
+        }
+
+        debug!("Starting transition {} of {} targets over {:?}", id, fades.len(), duration);
+        let start = Instant::now();
+        let mut interval = tokio::time::interval(self.frame_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        interval.tick().await;
+        let mut cancelled = false;
+        loop {
+            interval.tick().await;
+            let t = if duration.is_zero() { 1.0 } else { start.elapsed().as_secs_f32() / duration.as_secs_f32() };
+            let progress = easing.apply(t);
+            for fade in &mut fades {
+                if !ownership.owns(fade.controller) {
+                    debug!("Transition {} cancelled on controller {}", id, fade.controller);
+                    fade.cancelled = true;
+                    continue;
+                }
+                let colors = fade.from.iter().zip(&fade.to).map(|(a, b)| interpolate(*a, *b, progress)).collect();
+                match fade.zone {
+                    Some(zone) => self.client.update_zone_leds(fade.controller, zone, colors).await?,
+                    None => self.client.update_leds(fade.controller, colors).await?,
+                }
+            }
+            if fades.iter().any(|fade| fade.cancelled) {
+                cancelled = true;
+                fades.retain(|fade| !fade.cancelled);
+                if fades.is_empty() {
+                    return Ok(Outcome::Cancelled);
+                }
+            }
+            if t >= 1.0 {
+                break;
+            }
+        }
+
+        Ok(if cancelled { Outcome::Cancelled } else { Outcome::Completed })
+    }
+
+    /// Cancel running transitions on controller `controller_id`.
+    pub fn cancel(&self, controller_id: u32) {
+        self.owners.lock().unwrap().remove(&controller_id);
+    }
+
+    /// Cancel all running transitions.
+    pub fn cancel_all(&self) {
+        self.owners.lock().unwrap().clear();
+    }
+}
+
+/// Interpolate between colors `a` and `b` in Oklab color space, `t` being between 0 (`a`) and 1 (`b`).
+pub fn interpolate(a: Color, b: Color, t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    let (a, b) = (to_oklab(a), to_oklab(b));
+    from_oklab([a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t])
+}
+
+/// Convert sRGB channel value to linear light, between 0 and 1.
+pub(crate) fn to_linear(c: u8) -> f32 {
+    let c = f32::from(c) / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Convert linear light channel value, between 0 and 1, to sRGB.
+pub(crate) fn from_linear(c: f32) -> u8 {
+    let c = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// matrices from Oklab reference implementation
+#[allow(clippy::excessive_precision)]
+fn to_oklab(c: Color) -> [f32; 3] {
+    let (r, g, b) = (to_linear(c.r), to_linear(c.g), to_linear(c.b));
+    let l = (0.41222147 * r + 0.53633254 * g + 0.05144599 * b).cbrt();
+    let m = (0.21190350 * r + 0.68069955 * g + 0.10739696 * b).cbrt();
+    let s = (0.08830246 * r + 0.28171884 * g + 0.62997870 * b).cbrt();
+    [
+        0.21045426 * l + 0.79361779 * m - 0.00407205 * s,
+        1.97799850 * l - 2.42859221 * m + 0.45059371 * s,
+        0.02590404 * l + 0.78277177 * m - 0.80867577 * s,
+    ]
+}
+
+#[allow(clippy::excessive_precision)]
+fn from_oklab([l, a, b]: [f32; 3]) -> Color {
+    let l_ = (l + 0.39633779 * a + 0.21580376 * b).powi(3);
+    let m_ = (l - 0.10556134 * a - 0.06385417 * b).powi(3);
+    let s_ = (l - 0.08948418 * a - 1.29148555 * b).powi(3);
+    Color::new(
+        from_linear(4.07674166 * l_ - 3.30771159 * m_ + 0.23096993 * s_),
+        from_linear(-1.26843800 * l_ + 2.60975740 * m_ - 0.34131940 * s_),
+        from_linear(-0.00419609 * l_ - 0.70341861 * m_ + 1.70761470 * s_),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::data::{Color, fixtures};
+    use crate::DEFAULT_PROTOCOL;
+    use crate::message::{Request, Response};
+    use crate::mock::MockBuilder;
+    use crate::output::Brightness;
+    use crate::tests::setup;
+    use crate::transition::{Easing, from_oklab, interpolate, Outcome, Target, to_oklab, Transitions};
+
+    #[test]
+    fn test_oklab() {
+        for color in [Color::new(0, 0, 0), Color::new(255, 255, 255), Color::new(255, 0, 0), Color::new(12, 200, 97)] {
+            assert_eq!(from_oklab(to_oklab(color)), color);
+        }
+        let [l, a, b] = to_oklab(Color::new(255, 255, 255));
+        assert!((l - 1.0).abs() < 1e-3 && a.abs() < 1e-3 && b.abs() < 1e-3);
+
+        assert_eq!(interpolate(Color::new(255, 0, 0), Color::new(0, 0, 255), 0.0), Color::new(255, 0, 0));
+        assert_eq!(interpolate(Color::new(255, 0, 0), Color::new(0, 0, 255), 1.0), Color::new(0, 0, 255));
+        // perceptual mid gray is lighter than the sRGB average
+        let gray = interpolate(Color::new(0, 0, 0), Color::new(255, 255, 255), 0.5);
+        assert!(gray.r == gray.g && gray.g == gray.b && gray.r > 90 && gray.r < 128, "{:?}", gray);
+    }
+
+    #[test]
+    fn test_easing() {
+        for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            assert_eq!(easing.apply(2.0), 1.0);
+        }
+        assert_eq!(Easing::Linear.apply(0.25), 0.25);
+        assert!(Easing::EaseIn.apply(0.25) < 0.25);
+        assert!(Easing::EaseOut.apply(0.25) > 0.25);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fade() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let target = Color::new(255, 255, 255);
+        let half = |from: &[Color]| from.iter().map(|c| interpolate(*c, target, 0.5)).collect::<Vec<_>>();
+
+        let transitions = Transitions::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateLeds { controller: 0, colors: half(&controller.colors) })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![target; 2] })
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateZoneLeds { controller: 0, zone: 0, colors: vec![target; 2] })
+            .to_client().await?)
+            .frame_interval(Duration::from_millis(50));
+
+        assert_eq!(transitions.fade_controller(0, vec![target], Duration::from_millis(100), Easing::Linear).await?, Outcome::Completed);
+        assert_eq!(transitions.fade_zone(0, 0, vec![target], Duration::ZERO, Easing::Linear).await?, Outcome::Completed);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fade_with_output_filter() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, mut controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let (white, gray) = (Color::new(255, 255, 255), Color::new(128, 128, 128));
+        controller.colors = vec![gray; 2];
+
+        // fade starts from colors sent before brightness filter, not from dimmed ones read back
+        let transitions = Transitions::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![gray; 2] })
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![gray; 2] })
+            .expect(Request::UpdateLeds { controller: 0, colors: vec![gray; 2] })
+            .to_client().await?)
+            .frame_interval(Duration::from_millis(50));
+        let brightness = Arc::new(Brightness::new());
+        brightness.set_global(0.5);
+        transitions.client().add_output_filter(brightness);
+
+        transitions.client().update_leds(0, vec![white; 2]).await?;
+        assert_eq!(transitions.fade_controller(0, vec![white], Duration::from_millis(100), Easing::Linear).await?, Outcome::Completed);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancel() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let (first, second) = (Color::new(255, 0, 0), Color::new(0, 255, 0));
+        let at = |to: Color, t: f32| controller.colors.iter().map(|c| interpolate(*c, to, t)).collect::<Vec<_>>();
+
+        let transitions = Transitions::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateLeds { controller: 0, colors: at(first, 0.25) })
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateLeds { controller: 0, colors: at(second, 0.5) })
+            .expect(Request::UpdateLeds { controller: 0, colors: at(second, 1.0) })
+            .to_client().await?)
+            .frame_interval(Duration::from_millis(50));
+
+        let (a, b) = tokio::join!(
+            transitions.fade_controller(0, vec![first], Duration::from_millis(200), Easing::Linear),
+            async {
+                tokio::time::sleep(Duration::from_millis(60)).await;
+                transitions.fade_controller(0, vec![second], Duration::from_millis(100), Easing::Linear).await
+            },
+        );
+        assert_eq!(a?, Outcome::Cancelled);
+        assert_eq!(b?, Outcome::Completed);
+        assert!(transitions.owners.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancel_partially() -> Result<(), Box<dyn Error>> {
+        setup()?;
+
+        let (_, controller) = fixtures::controller(DEFAULT_PROTOCOL);
+        let (first, second) = (Color::new(255, 0, 0), Color::new(0, 255, 0));
+        let at = |to: Color, t: f32| controller.colors.iter().map(|c| interpolate(*c, to, t)).collect::<Vec<_>>();
+
+        // first transition keeps fading controller 1 once second one took over controller 0
+        let transitions = Transitions::new(MockBuilder::new()
+            .negotiate_default_protocol()
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::ControllerData { controller: 1 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateLeds { controller: 0, colors: at(first, 0.25) })
+            .expect(Request::UpdateLeds { controller: 1, colors: at(first, 0.25) })
+            .expect(Request::ControllerData { controller: 0 })
+            .respond(Response::ControllerData(controller.clone()))
+            .expect(Request::UpdateLeds { controller: 1, colors: at(first, 0.5) })
+            .expect(Request::UpdateLeds { controller: 0, colors: at(second, 0.5) })
+            .expect(Request::UpdateLeds { controller: 1, colors: at(first, 0.75) })
+            .expect(Request::UpdateLeds { controller: 0, colors: at(second, 1.0) })
+            .expect(Request::UpdateLeds { controller: 1, colors: at(first, 1.0) })
+            .to_client().await?)
+            .frame_interval(Duration::from_millis(50));
+
+        let targets = (0..2).map(|controller| Target::Controller { controller, colors: vec![first] }).collect();
+        let (a, b) = tokio::join!(
+            transitions.fade(targets, Duration::from_millis(200), Easing::Linear),
+            async {
+                tokio::time::sleep(Duration::from_millis(60)).await;
+                transitions.fade_controller(0, vec![second], Duration::from_millis(100), Easing::Linear).await
+            },
+        );
+        assert_eq!(a?, Outcome::Cancelled);
+        assert_eq!(b?, Outcome::Completed);
+        assert!(transitions.owners.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+}